@@ -0,0 +1,12 @@
+ALTER TABLE reports
+    DROP FOREIGN KEY reports_post_fk,
+    DROP FOREIGN KEY reports_resolver_fk;
+
+DELETE FROM reports WHERE post_id IS NULL;
+
+ALTER TABLE reports
+    DROP COLUMN resolved_at,
+    DROP COLUMN resolved_by,
+    DROP COLUMN created_at,
+    MODIFY post_id INTEGER UNSIGNED NOT NULL,
+    ADD    CONSTRAINT reports_ibfk_1 FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE;
\ No newline at end of file
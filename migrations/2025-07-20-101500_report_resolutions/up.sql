@@ -0,0 +1,10 @@
+ALTER TABLE reports
+    DROP FOREIGN KEY reports_ibfk_1;
+
+ALTER TABLE reports
+    MODIFY post_id     INTEGER UNSIGNED,
+    ADD    created_at  DATETIME         NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD    resolved_by BIGINT  UNSIGNED,
+    ADD    resolved_at DATETIME,
+    ADD    CONSTRAINT reports_post_fk     FOREIGN KEY (post_id)     REFERENCES posts(id) ON DELETE SET NULL,
+    ADD    CONSTRAINT reports_resolver_fk FOREIGN KEY (resolved_by) REFERENCES users(id) ON DELETE SET NULL;
\ No newline at end of file
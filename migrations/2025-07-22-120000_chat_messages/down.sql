@@ -0,0 +1 @@
+DROP TABLE chat_messages;
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE chat_messages (
+    id            BIGINT UNSIGNED  NOT NULL  AUTO_INCREMENT,
+    room          VARCHAR(255)     NOT NULL,
+    user_id       BIGINT UNSIGNED  NOT NULL,
+    display_name  VARCHAR(255)     NOT NULL,
+    body          TEXT             NOT NULL,
+    created_at    DATETIME         NOT NULL  DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX (room, id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,5 @@
+DROP TABLE chat_mod_actions;
+DROP TABLE chat_timeouts;
+
+ALTER TABLE chat_rooms
+    DROP COLUMN slow_mode_seconds;
\ No newline at end of file
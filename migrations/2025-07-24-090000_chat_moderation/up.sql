@@ -0,0 +1,24 @@
+ALTER TABLE chat_rooms
+    ADD slow_mode_seconds INTEGER UNSIGNED NOT NULL DEFAULT 0;
+
+CREATE TABLE chat_timeouts (
+    user_id       BIGINT UNSIGNED  NOT NULL,
+    moderator_id  BIGINT UNSIGNED  NOT NULL,
+    expires_at    DATETIME         NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (moderator_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE chat_mod_actions (
+    id            INTEGER UNSIGNED NOT NULL  AUTO_INCREMENT,
+    moderator_id  BIGINT UNSIGNED  NOT NULL,
+    target_id     BIGINT UNSIGNED,
+    room          VARCHAR(255),
+    action        VARCHAR(16)      NOT NULL,
+    details       TEXT,
+    created_at    DATETIME         NOT NULL  DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    FOREIGN KEY (moderator_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (target_id) REFERENCES users(id) ON DELETE SET NULL
+);
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE threads DROP INDEX threads_title_fulltext;
+ALTER TABLE posts DROP INDEX posts_message_fulltext;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD FULLTEXT INDEX posts_message_fulltext (message);
+ALTER TABLE threads ADD FULLTEXT INDEX threads_title_fulltext (title);
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE bans
+    DROP INDEX bans_range,
+    DROP COLUMN range_end,
+    DROP COLUMN range_start,
+    DROP COLUMN ip_range;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE bans
+    ADD COLUMN ip_range    VARCHAR(49),
+    ADD COLUMN range_start VARBINARY(16),
+    ADD COLUMN range_end   VARBINARY(16),
+    ADD INDEX bans_range (range_start, range_end);
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE boards
+    DROP COLUMN flags;
\ No newline at end of file
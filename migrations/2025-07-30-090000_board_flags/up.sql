@@ -0,0 +1,2 @@
+ALTER TABLE boards
+    ADD COLUMN flags BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE posts
+    DROP COLUMN private_note;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE posts
+    ADD COLUMN private_note TEXT;
\ No newline at end of file
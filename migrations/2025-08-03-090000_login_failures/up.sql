@@ -0,0 +1,13 @@
+CREATE TABLE login_failures (
+    id            BIGINT UNSIGNED  NOT NULL  AUTO_INCREMENT,
+    user_id       BIGINT UNSIGNED,
+    login         VARCHAR(255)     NOT NULL,
+    ip_address    VARCHAR(45)      NOT NULL,
+    created_at    DATETIME         NOT NULL  DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX (user_id, created_at),
+    INDEX (login, created_at),
+    INDEX (ip_address, created_at),
+    INDEX (created_at),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,5 @@
+DROP TABLE user_roles;
+
+DROP TABLE role_capabilities;
+
+DROP TABLE roles;
\ No newline at end of file
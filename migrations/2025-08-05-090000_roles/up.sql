@@ -0,0 +1,53 @@
+CREATE TABLE roles (
+    id            INTEGER UNSIGNED NOT NULL  AUTO_INCREMENT,
+    name          VARCHAR(32)      NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (name)
+);
+
+CREATE TABLE role_capabilities (
+    role_id       INTEGER UNSIGNED NOT NULL,
+    capability    VARCHAR(32)      NOT NULL,
+    PRIMARY KEY (role_id, capability),
+    FOREIGN KEY (role_id) REFERENCES roles(id) ON DELETE CASCADE
+);
+
+CREATE TABLE user_roles (
+    id            INTEGER UNSIGNED NOT NULL  AUTO_INCREMENT,
+    user_id       BIGINT UNSIGNED  NOT NULL,
+    role_id       INTEGER UNSIGNED NOT NULL,
+    board_id      INTEGER UNSIGNED,
+    created_at    DATETIME         NOT NULL  DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id) REFERENCES roles(id) ON DELETE CASCADE,
+    FOREIGN KEY (board_id) REFERENCES boards(id) ON DELETE CASCADE
+);
+
+INSERT INTO roles (name) VALUES ('janitor'), ('moderator'), ('admin');
+
+INSERT INTO role_capabilities (role_id, capability)
+SELECT id, 'delete_post' FROM roles WHERE name IN ('janitor', 'moderator', 'admin');
+
+INSERT INTO role_capabilities (role_id, capability)
+SELECT roles.id, capabilities.capability
+FROM roles
+CROSS JOIN (
+    SELECT 'ban' AS capability UNION ALL
+    SELECT 'pin' UNION ALL
+    SELECT 'lock' UNION ALL
+    SELECT 'handle_reports'
+) AS capabilities
+WHERE roles.name IN ('moderator', 'admin');
+
+INSERT INTO role_capabilities (role_id, capability)
+SELECT roles.id, capabilities.capability
+FROM roles
+CROSS JOIN (
+    SELECT 'manage_boards' AS capability UNION ALL
+    SELECT 'manage_chats' UNION ALL
+    SELECT 'manage_users' UNION ALL
+    SELECT 'review_applications'
+) AS capabilities
+WHERE roles.name = 'admin';
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE mod_actions;
\ No newline at end of file
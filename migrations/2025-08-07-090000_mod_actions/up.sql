@@ -0,0 +1,17 @@
+CREATE TABLE mod_actions (
+    id            INTEGER UNSIGNED NOT NULL  AUTO_INCREMENT,
+    moderator_id  BIGINT UNSIGNED  NOT NULL,
+    action        VARCHAR(32)      NOT NULL,
+    item_id       BIGINT UNSIGNED,
+    target_id     BIGINT UNSIGNED,
+    board_id      INTEGER UNSIGNED,
+    details       TEXT,
+    snapshot      MEDIUMTEXT,
+    created_at    DATETIME         NOT NULL  DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX (action),
+    INDEX (target_id),
+    INDEX (created_at),
+    FOREIGN KEY (moderator_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (board_id) REFERENCES boards(id) ON DELETE SET NULL
+);
\ No newline at end of file
@@ -0,0 +1,11 @@
+ALTER TABLE posts
+    DROP FOREIGN KEY posts_deleted_by_fk,
+    DROP INDEX posts_deleted_at,
+    DROP COLUMN deleted_at,
+    DROP COLUMN deleted_by;
+
+ALTER TABLE threads
+    DROP FOREIGN KEY threads_deleted_by_fk,
+    DROP INDEX threads_deleted_at,
+    DROP COLUMN deleted_at,
+    DROP COLUMN deleted_by;
\ No newline at end of file
@@ -0,0 +1,11 @@
+ALTER TABLE threads
+    ADD COLUMN deleted_at DATETIME,
+    ADD COLUMN deleted_by BIGINT UNSIGNED,
+    ADD INDEX threads_deleted_at (deleted_at),
+    ADD CONSTRAINT threads_deleted_by_fk FOREIGN KEY (deleted_by) REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE posts
+    ADD COLUMN deleted_at DATETIME,
+    ADD COLUMN deleted_by BIGINT UNSIGNED,
+    ADD INDEX posts_deleted_at (deleted_at),
+    ADD CONSTRAINT posts_deleted_by_fk FOREIGN KEY (deleted_by) REFERENCES users(id) ON DELETE SET NULL;
\ No newline at end of file
@@ -0,0 +1,13 @@
+ALTER TABLE chat_messages
+    ADD COLUMN room VARCHAR(255) NOT NULL DEFAULT '' AFTER id;
+
+UPDATE chat_messages
+JOIN chat_rooms ON chat_rooms.id = chat_messages.room_id
+SET chat_messages.room = chat_rooms.name;
+
+ALTER TABLE chat_messages
+    ALTER room DROP DEFAULT,
+    DROP FOREIGN KEY chat_messages_room_id_fk,
+    DROP INDEX chat_messages_room_id,
+    DROP COLUMN room_id,
+    ADD INDEX room (room, id);
\ No newline at end of file
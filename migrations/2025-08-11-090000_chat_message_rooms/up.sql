@@ -0,0 +1,16 @@
+ALTER TABLE chat_messages
+    ADD COLUMN room_id INT UNSIGNED AFTER id;
+
+UPDATE chat_messages
+JOIN chat_rooms ON chat_rooms.name = chat_messages.room
+SET chat_messages.room_id = chat_rooms.id;
+
+-- History of deleted rooms has nowhere to go.
+DELETE FROM chat_messages WHERE room_id IS NULL;
+
+ALTER TABLE chat_messages
+    MODIFY room_id INT UNSIGNED NOT NULL,
+    DROP INDEX room,
+    DROP COLUMN room,
+    ADD INDEX chat_messages_room_id (room_id, id),
+    ADD CONSTRAINT chat_messages_room_id_fk FOREIGN KEY (room_id) REFERENCES chat_rooms(id) ON DELETE CASCADE;
\ No newline at end of file
@@ -0,0 +1,13 @@
+ALTER TABLE posts
+    ADD COLUMN capcode VARCHAR(32);
+
+-- Signed posts keep the title their author has now.
+UPDATE posts
+    JOIN users ON users.id = posts.user_id
+    SET posts.capcode = CASE
+        WHEN users.access_level = 255 THEN 'root'
+        WHEN users.access_level >= 200 THEN 'omistaja'
+        WHEN users.access_level >= 100 THEN 'admin'
+        WHEN users.access_level >= 90 THEN 'moderaattori'
+    END
+    WHERE posts.show_username;
\ No newline at end of file
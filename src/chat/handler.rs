@@ -1,25 +1,31 @@
 use std::time::{Duration, Instant};
 
 use actix_ws::AggregatedMessage;
-use chrono::Utc;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::{sync::mpsc, time::interval};
 
-use crate::models::users::AccessLevel;
-
-use super::server::{ChatServerHandle, ConnId, MessagesChanged, User, NEW_MESSAGE};
+use super::server::{ChatServerHandle, ConnId, ModAction, User};
 
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Longest timeout and message clearing window of the mod commands.
+const MAX_MOD_MINUTES: i64 = 365 * 24 * 60;
+
+/// Longest ban the mod commands can set.
+const MAX_BAN_DAYS: i64 = 365;
+
 
 pub async fn chat_ws(
+    user_id: u64,
     user: User,
     access_level: u8,
+    ip_addr: String,
+    max_message_bytes: usize,
     chat_server: ChatServerHandle,
     mut session: actix_ws::Session,
     msg_stream: actix_ws::MessageStream,
@@ -29,7 +35,7 @@ pub async fn chat_ws(
 
     let (conn_tx, mut conn_rx) = mpsc::unbounded_channel();
 
-    let conn_id = chat_server.connect(user.clone(), conn_tx).await;
+    let conn_id = chat_server.connect(user_id, user.clone(), access_level, ip_addr, conn_tx).await;
 
     let mut msg_stream = msg_stream
         .max_frame_size(128 * 1024)
@@ -50,7 +56,7 @@ pub async fn chat_ws(
                     }
 
                     AggregatedMessage::Text(text) => {
-                        process_text_msg(conn_id, user.clone(), access_level, &chat_server, &mut session, &text)
+                        process_text_msg(conn_id, user.clone(), access_level, max_message_bytes, &chat_server, &mut session, &text)
                             .await;
                     }
 
@@ -60,9 +66,14 @@ pub async fn chat_ws(
                 }
             }
 
-            Some(chat_msg) = conn_rx.recv() => {
-                session.text(chat_msg).await.unwrap();
-            }
+            chat_msg = conn_rx.recv() => match chat_msg {
+                Some(chat_msg) => {
+                    session.text(chat_msg).await.unwrap();
+                }
+
+                // The chat server dropped the connection, e.g. the user was kicked.
+                None => break None,
+            },
 
             _ = interval.tick() => {
                 if Instant::now().duration_since(last_heartbeat) > CLIENT_TIMEOUT {
@@ -87,13 +98,18 @@ const UNKNOWN_COMMAND: u8 = 0;
 const SEND_MESSAGE: u8 = 1;
 const LIST_ROOMS: u8 = 2;
 const LIST_USERS: u8 = 3;
+const JOIN_ROOM: u8 = 4;
+const LEAVE_ROOM: u8 = 5;
+const LOAD_HISTORY: u8 = 6;
 const TOO_LONG_MESSAGE_ERROR: u8 = 7;
+const MODERATION_RESULT: u8 = 14;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct InputCommand {
     event: u8,
     message: Option<String>,
     room: Option<String>,
+    before: Option<u64>,
 }
 
 #[derive(Serialize, Debug)]
@@ -102,6 +118,13 @@ pub struct OutputCommand {
     data: Vec<String>,
 }
 
+#[derive(Serialize, Debug)]
+pub struct RoomOutputCommand {
+    event: u8,
+    room: String,
+    data: Vec<String>,
+}
+
 #[derive(Serialize, Debug)]
 pub struct ErrorOutput {
     event: u8,
@@ -112,6 +135,7 @@ async fn process_text_msg(
     id: ConnId,
     user: User,
     access_level: u8,
+    max_message_bytes: usize,
     chat_server: &ChatServerHandle,
     session: &mut actix_ws::Session,
     text: &str,
@@ -123,25 +147,56 @@ async fn process_text_msg(
                 event: UNKNOWN_COMMAND,
                 message: None,
                 room: None,
+                before: None,
             }
         },
     };
 
     match command.event {
         LIST_USERS => {
-            let users = chat_server.list_users().await;
+            let room = command.room.unwrap_or_default();
 
-            session
-                .text(json!(OutputCommand { 
-                    event: 4, 
-                    data: users 
-                }).to_string())
-                .await
-                .unwrap();
+            if let Some(users) = chat_server.list_users(id, room.clone()).await {
+                session
+                    .text(json!(RoomOutputCommand { 
+                        event: 4, 
+                        room,
+                        data: users 
+                    }).to_string())
+                    .await
+                    .unwrap();
+            }
+        },
+
+        JOIN_ROOM => {
+            let room = command.room.unwrap_or_default();
+
+            if chat_server.join(id, room.clone()).await {
+                let Some(users) = chat_server.list_users(id, room.clone()).await else {
+                    return;
+                };
+
+                session
+                    .text(json!(RoomOutputCommand { 
+                        event: 4, 
+                        room,
+                        data: users 
+                    }).to_string())
+                    .await
+                    .unwrap();
+            }
+        },
+
+        LEAVE_ROOM => {
+            chat_server.leave(id, command.room.unwrap_or_default()).await;
+        },
+
+        LOAD_HISTORY => {
+            chat_server.load_history(id, command.room.unwrap_or_default(), command.before).await;
         },
 
         LIST_ROOMS => {
-            let rooms = chat_server.list_rooms().await;
+            let rooms = chat_server.list_rooms(access_level).await;
 
             session
                 .text(json!(OutputCommand { 
@@ -155,11 +210,11 @@ async fn process_text_msg(
         SEND_MESSAGE => {
             let msg = command.clone().message.unwrap_or_default().trim().to_owned();
 
-            if msg.len() > 2000 {
+            if msg.len() > max_message_bytes {
                 session
                 .text(json!(ErrorOutput { 
                     event: TOO_LONG_MESSAGE_ERROR, 
-                    message: "Viestisi on liian pitkä (yli 2000 tavua)!".to_owned(), 
+                    message: format!("Viestisi on liian pitkä (yli {} tavua)!", max_message_bytes), 
                 }).to_string())
                 .await
                 .unwrap();
@@ -168,42 +223,33 @@ async fn process_text_msg(
             }
 
             if msg.starts_with('/') {
-                let mut cmd_args = msg.splitn(3, ' ');
-
-                match cmd_args.next().unwrap() {
-                    "/timeout" => match cmd_args.next() {
-                        Some(user) => match cmd_args.next() {
-                            Some(time) => {
-                                let time = match time.parse::<i64>() {
-                                    Ok(time) => {
-                                        if access_level >= AccessLevel::Moderator as u8 {
-                                            let timeout = (Utc::now() + chrono::Duration::minutes(time)).naive_utc();
-
-                                            chat_server.timeout_user(user.to_owned(), timeout).await;
-                                        }
-                                    },
-                                    Err(_) => (),
-                                };
-                            },
-                            None => (),
-                        },
-                        None => (),
+                let output = match parse_mod_command(&msg) {
+                    Some((target, action)) => {
+                        let room = command.room.unwrap_or_default();
+
+                        match chat_server.moderate(id, room, target, action).await {
+                            Ok(feedback) => feedback,
+                            Err(error) => error,
+                        }
                     },
+                    None => "Tuntematon komento! Komennot: /timeout <käyttäjä> <min>, /untimeout <käyttäjä>, \
+                    /kick <käyttäjä>, /ban <käyttäjä> <päivää> [syy], /clear <käyttäjä> [min], /slow <s>".to_owned(),
+                };
 
-                    _ => ()
-                }
+                session
+                .text(json!(ErrorOutput { 
+                    event: MODERATION_RESULT, 
+                    message: output, 
+                }).to_string())
+                .await
+                .unwrap();
             } else {
                 chat_server.send_chat_message(
                     id,
                     user.clone(),
                     access_level,
-                    command.room.clone().unwrap_or_default(),
-                    json!(MessagesChanged { 
-                        event: NEW_MESSAGE, 
-                        username: &user, 
-                        message: &command.message.unwrap_or_default(), 
-                        room: &command.room.unwrap_or_default() 
-                    }).to_string()
+                    command.room.unwrap_or_default(),
+                    command.message.unwrap_or_default(),
                 ).await
             }
         },
@@ -215,4 +261,38 @@ async fn process_text_msg(
                 .unwrap();
         },
     }
+}
+
+fn parse_mod_command(msg: &str) -> Option<(Option<User>, ModAction)> {
+    let mut cmd_args = msg.splitn(4, ' ');
+
+    let cmd = cmd_args.next()?;
+
+    if cmd == "/slow" {
+        let seconds = cmd_args.next()?.parse::<u32>().ok()?;
+        return Some((None, ModAction::Slow { seconds }));
+    }
+
+    let target = cmd_args.next()?.to_owned();
+
+    let action = match cmd {
+        "/timeout" => ModAction::Timeout { 
+            minutes: cmd_args.next()?.parse::<i64>().ok().filter(|min| (1..=MAX_MOD_MINUTES).contains(min))?, 
+        },
+        "/untimeout" => ModAction::Untimeout,
+        "/kick" => ModAction::Kick,
+        "/ban" => ModAction::Ban { 
+            days: cmd_args.next()?.parse::<i64>().ok().filter(|days| (1..=MAX_BAN_DAYS).contains(days))?, 
+            reason: cmd_args.next().unwrap_or_default().to_owned(), 
+        },
+        "/clear" => ModAction::Clear { 
+            minutes: match cmd_args.next() {
+                Some(minutes) => minutes.parse::<i64>().ok().filter(|min| (1..=MAX_MOD_MINUTES).contains(min))?,
+                None => 60,
+            }, 
+        },
+        _ => return None,
+    };
+
+    Some((Some(target), action))
 }
\ No newline at end of file
@@ -1,22 +1,30 @@
-use std::{collections::HashMap, io};
+use std::{collections::{HashMap, HashSet}, io};
 
 use chrono::{NaiveDateTime, Utc};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
 use rand::Rng;
 use serde_json::json;
 use tokio::sync::{mpsc, oneshot};
 
-use crate::{models::chat_rooms::ChatRoom, services::time::fi_datetime};
+use crate::{models::{bans::BanModel, chat_messages::{ChatMessage, ChatMessageModel}, chat_moderation::{ChatModActionModel, ChatTimeout}, chat_rooms::ChatRoom, users::{AccessLevel, User as UserAccount}}, services::{permissions::{require, require_above}, time::fi_datetime}};
 
 
 const USER_JOINED: u8 = 1;
 const USER_LEFT: u8 = 2;
 pub const NEW_MESSAGE: u8 = 3;
 const TIMEOUT: u8 = 6;
+const ROOM_ACCESS_ERROR: u8 = 8;
+const HISTORY: u8 = 9;
+const SERVER_ERROR: u8 = 10;
+const ROOMS_CHANGED: u8 = 11;
+const KICKED: u8 = 12;
+const MESSAGES_REMOVED: u8 = 13;
 
 #[derive(serde::Serialize)]
 pub struct UsersChanged<'a> {
     pub event: u8,
     pub username: &'a str,
+    pub room: &'a str,
 }
 
 #[derive(serde::Serialize)]
@@ -28,9 +36,39 @@ pub struct Timeout<'a> {
 #[derive(serde::Serialize)]
 pub struct MessagesChanged<'a> {
     pub event: u8,
+    pub id: u64,
     pub username: &'a str,
     pub message: &'a str,
     pub room: &'a str,
+    pub created_at: &'a str,
+}
+
+#[derive(serde::Serialize)]
+pub struct MessagesRemoved {
+    pub event: u8,
+    pub ids: Vec<u64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RoomsChanged {
+    pub event: u8,
+    pub data: Vec<Room>,
+}
+
+#[derive(serde::Serialize)]
+pub struct HistoryMessage {
+    pub id: u64,
+    pub username: String,
+    pub message: String,
+    pub created_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct HistoryChanged<'a> {
+    pub event: u8,
+    pub room: &'a str,
+    pub older: bool,
+    pub data: Vec<HistoryMessage>,
 }
 
 pub type ConnId = u64;
@@ -38,10 +76,42 @@ pub type Room = String;
 pub type User = String;
 pub type Msg = String;
 
+/// Moderation command issued from the chat by a staff member.
+#[derive(Debug)]
+pub enum ModAction {
+    Timeout { minutes: i64 },
+    Untimeout,
+    Kick,
+    Ban { days: i64, reason: String },
+    Clear { minutes: i64 },
+    Slow { seconds: u32 },
+}
+
+impl ModAction {
+    fn name(&self) -> &'static str {
+        match self {
+            ModAction::Timeout { .. } => "timeout",
+            ModAction::Untimeout => "untimeout",
+            ModAction::Kick => "kick",
+            ModAction::Ban { .. } => "ban",
+            ModAction::Clear { .. } => "clear",
+            ModAction::Slow { .. } => "slow",
+        }
+    }
+}
+
+struct ModTarget {
+    user_id: u64,
+    access_level: u8,
+}
+
 #[derive(Debug)]
 enum Command {
     Connect {
+        user_id: u64,
         user: User,
+        access_level: u8,
+        ip_addr: String,
         conn_tx: mpsc::UnboundedSender<Msg>,
         res_tx: oneshot::Sender<ConnId>,
     },
@@ -50,12 +120,34 @@ enum Command {
         conn: ConnId,
     },
 
+    Join {
+        conn: ConnId,
+        room: Room,
+        res_tx: oneshot::Sender<bool>,
+    },
+
+    Leave {
+        conn: ConnId,
+        room: Room,
+        res_tx: oneshot::Sender<()>,
+    },
+
+    History {
+        conn: ConnId,
+        room: Room,
+        before: Option<u64>,
+        res_tx: oneshot::Sender<()>,
+    },
+
     ListRoom {
+        access_level: u8,
         res_tx: oneshot::Sender<Vec<Room>>,
     },
 
     ListUser {
-        res_tx: oneshot::Sender<Vec<User>>,
+        conn: ConnId,
+        room: Room,
+        res_tx: oneshot::Sender<Option<Vec<User>>>,
     },
 
     Message {
@@ -72,24 +164,58 @@ enum Command {
         res_tx: oneshot::Sender<()>,
     },
 
-    SetTimeOut {
-        user: User,
-        timeout: NaiveDateTime,
+    Moderate {
+        conn: ConnId,
+        room: Room,
+        target: Option<User>,
+        action: ModAction,
+        res_tx: oneshot::Sender<Result<String, String>>,
+    },
+
+    AddRoom {
+        room: ChatRoom,
         res_tx: oneshot::Sender<()>,
-    }
+    },
+
+    RenameRoom {
+        id: u32,
+        name: Room,
+        res_tx: oneshot::Sender<()>,
+    },
+
+    ChangeRoomAccess {
+        id: u32,
+        access_level: u8,
+        res_tx: oneshot::Sender<()>,
+    },
+
+    RemoveRoom {
+        id: u32,
+        res_tx: oneshot::Sender<()>,
+    },
 }
 
-#[derive(Debug)]
 pub struct ChatServer {
     sessions: HashMap<ConnId, mpsc::UnboundedSender<Msg>>,
     users: HashMap<ConnId, User>,
-    timouts: HashMap<User, NaiveDateTime>,
+    user_ids: HashMap<ConnId, u64>,
+    access_levels: HashMap<ConnId, u8>,
+    ip_addrs: HashMap<ConnId, String>,
+    members: HashMap<Room, HashSet<ConnId>>,
+    timeouts: HashMap<u64, NaiveDateTime>,
+    last_messages: HashMap<(u64, Room), NaiveDateTime>,
     rooms: Vec<ChatRoom>,
+    conn_pool: Pool<AsyncMysqlConnection>,
+    backlog_size: i64,
     cmd_rx: mpsc::UnboundedReceiver<Command>,
 }
 
 impl ChatServer {
-    pub fn new(rooms: Vec<ChatRoom>) -> (Self, ChatServerHandle) {
+    pub fn new(
+        rooms: Vec<ChatRoom>, 
+        conn_pool: Pool<AsyncMysqlConnection>, 
+        backlog_size: i64,
+    ) -> (Self, ChatServerHandle) {
 
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
 
@@ -97,8 +223,15 @@ impl ChatServer {
             Self {
                 sessions: HashMap::new(),
                 users: HashMap::new(),
-                timouts: HashMap::new(),
+                user_ids: HashMap::new(),
+                access_levels: HashMap::new(),
+                ip_addrs: HashMap::new(),
+                members: HashMap::new(),
+                timeouts: HashMap::new(),
+                last_messages: HashMap::new(),
                 rooms,
+                conn_pool,
+                backlog_size,
                 cmd_rx,
             },
             ChatServerHandle { cmd_tx },
@@ -113,15 +246,52 @@ impl ChatServer {
         }
     }
 
-    async fn send_chat_message(&self, id: ConnId, user: User, access_level: u8, room: Room, msg: impl Into<Msg>) {
+    async fn send_room_message(&self, room: &ChatRoom, msg: impl Into<Msg>) {
+        let msg = msg.into();
+
+        let members = match self.members.get(&room.name) {
+            Some(members) => members,
+            None => return,
+        };
+
+        for conn_id in members.iter() {
+            let access_level = self.access_levels.get(conn_id).copied().unwrap_or_default();
+
+            if access_level < room.access_level {
+                continue;
+            }
+
+            if let Some(session) = self.sessions.get(conn_id) {
+                let _ = session.send(msg.clone());
+            }
+        }
+    }
+
+    fn send_error(&self, conn_id: ConnId, event: u8, message: &str) {
+        if let Some(session) = self.sessions.get(&conn_id) {
+            let _ = session.send(json!(Timeout {
+                event,
+                message,
+            }).to_string());
+        }
+    }
+
+    async fn send_chat_message(&mut self, id: ConnId, user: User, access_level: u8, room: Room, msg: impl Into<Msg>) {
         let msg = msg.into();
 
-        let timeout = match self.timouts.get(&user) {
+        let user_id = match self.user_ids.get(&id) {
+            Some(user_id) => *user_id,
+            None => return,
+        };
+
+        let now = Utc::now().naive_utc();
+
+        let timeout = match self.timeouts.get(&user_id) {
             Some(timeout) => timeout,
-            None => &Utc::now().naive_utc(),
+            None => &now,
         };
 
-        if timeout > &Utc::now().naive_utc() {
+        if timeout > &now {
             self.sessions.get(&id).and_then(|conn| {
                 let _ = conn.send(json!(Timeout { 
                     event: TIMEOUT, 
@@ -130,65 +300,503 @@ impl ChatServer {
                 Some(())
             });
         } else {
-            for rm in self.rooms.iter() {
-                if rm.name.eq(&room) && rm.access_level <= access_level {
-                    self.send_message(msg.clone()).await;
-                    break;
+            let is_member = self.members.get(&room)
+            .map(|members| members.contains(&id))
+            .unwrap_or(false);
+
+            let chat_room = match self.rooms.iter().find(|rm| rm.name.eq(&room)) {
+                Some(chat_room) if chat_room.access_level <= access_level && is_member => chat_room.clone(),
+                _ => return,
+            };
+
+            // Staff is exempt from slow mode.
+            if chat_room.slow_mode_seconds > 0 && access_level < AccessLevel::Moderator as u8 {
+                if let Some(last_message) = self.last_messages.get(&(user_id, room.clone())) {
+                    let next_message = *last_message + chrono::Duration::seconds(chat_room.slow_mode_seconds.into());
+
+                    if next_message > now {
+                        self.send_error(id, TIMEOUT, &("Hidas tila on päällä! Voit lähettää viestin uudelleen ".to_owned() + &fi_datetime(next_message)));
+                        return;
+                    }
                 }
             }
+
+            let message_model = ChatMessageModel {
+                room_id: chat_room.id,
+                user_id,
+                display_name: &user,
+                body: &msg,
+            };
+
+            let chat_message = match message_model.insert(&self.conn_pool).await {
+                Ok(chat_message) => chat_message,
+                Err(_) => {
+                    self.send_error(id, SERVER_ERROR, "Viestin tallentaminen epäonnistui!");
+                    return;
+                },
+            };
+
+            self.last_messages.insert((user_id, room.clone()), now);
+
+            self.send_room_message(&chat_room, json!(MessagesChanged { 
+                event: NEW_MESSAGE, 
+                id: chat_message.id,
+                username: &chat_message.display_name, 
+                message: &chat_message.body, 
+                room: &chat_room.name,
+                created_at: &fi_datetime(chat_message.created_at),
+            }).to_string()).await;
         }
     }
 
-    async fn connect(&mut self, user: User, tx: mpsc::UnboundedSender<Msg>) -> ConnId {
-        self.send_message(json!(UsersChanged { 
-            event: USER_JOINED, 
-            username: &user,
-        }).to_string()).await;
-
+    async fn connect(&mut self, user_id: u64, user: User, access_level: u8, ip_addr: String, tx: mpsc::UnboundedSender<Msg>) -> ConnId {
         let id = rand::rng().random::<ConnId>();
         self.sessions.insert(id, tx);
         self.users.insert(id, user);
+        self.user_ids.insert(id, user_id);
+        self.access_levels.insert(id, access_level);
+        self.ip_addrs.insert(id, ip_addr);
 
         id
     }
 
     async fn disconnect(&mut self, conn_id: ConnId) {
+        let joined_rooms: Vec<Room> = self.members.iter()
+        .filter(|(_, members)| members.contains(&conn_id))
+        .map(|(room, _)| room.to_owned())
+        .collect();
+
+        for room in joined_rooms {
+            self.leave(conn_id, room).await;
+        }
+
         self.sessions.remove(&conn_id);
+        self.users.remove(&conn_id);
+        self.user_ids.remove(&conn_id);
+        self.access_levels.remove(&conn_id);
+        self.ip_addrs.remove(&conn_id);
+    }
+
+    async fn join(&mut self, conn_id: ConnId, room: Room) -> bool {
+        let access_level = self.access_levels.get(&conn_id).copied().unwrap_or_default();
+
+        let chat_room = match self.rooms.iter().find(|rm| rm.name.eq(&room)) {
+            Some(chat_room) if chat_room.access_level <= access_level => chat_room.clone(),
+            _ => {
+                self.send_error(conn_id, ROOM_ACCESS_ERROR, "Sinulla ei ole pääsyä tähän huoneeseen!");
+                return false;
+            },
+        };
+
+        let newly_joined = self.members
+        .entry(room.clone())
+        .or_default()
+        .insert(conn_id);
+
+        if newly_joined {
+            if let Some(username) = self.users.get(&conn_id) {
+                self.send_room_message(&chat_room, json!(UsersChanged {
+                    event: USER_JOINED,
+                    username,
+                    room: &room,
+                }).to_string()).await;
+            }
+        }
+
+        self.send_history(conn_id, room, None).await;
+
+        true
+    }
+
+    /// Sends a page of stored messages to a room member. Without `before` the
+    /// latest backlog is sent, otherwise messages older than the given id.
+    async fn send_history(&self, conn_id: ConnId, room: Room, before: Option<u64>) {
+        let is_member = self.members.get(&room)
+        .map(|members| members.contains(&conn_id))
+        .unwrap_or(false);
+
+        if !is_member {
+            return;
+        }
+
+        let room_id = match self.rooms.iter().find(|rm| rm.name.eq(&room)) {
+            Some(chat_room) => chat_room.id,
+            None => return,
+        };
+
+        let messages = match ChatMessage::list_by_room(&self.conn_pool, room_id, before, self.backlog_size).await {
+            Ok(messages) => messages,
+            Err(_) => {
+                self.send_error(conn_id, SERVER_ERROR, "Viestihistorian lataaminen epäonnistui!");
+                return;
+            },
+        };
+
+        let data = messages.into_iter()
+        .map(|message| HistoryMessage {
+            id: message.id,
+            username: message.display_name,
+            message: message.body,
+            created_at: fi_datetime(message.created_at),
+        })
+        .collect();
+
+        if let Some(session) = self.sessions.get(&conn_id) {
+            let _ = session.send(json!(HistoryChanged {
+                event: HISTORY,
+                room: &room,
+                older: before.is_some(),
+                data,
+            }).to_string());
+        }
+    }
 
-        if let Some(username) = self.users.remove(&conn_id) {
-            self.send_message(&json!(UsersChanged { 
-                event: USER_LEFT, 
-                username: &username, 
+    async fn leave(&mut self, conn_id: ConnId, room: Room) {
+        let left = match self.members.get_mut(&room) {
+            Some(members) => members.remove(&conn_id),
+            None => false,
+        };
+
+        if !left {
+            return;
+        }
+
+        let chat_room = match self.rooms.iter().find(|rm| rm.name.eq(&room)) {
+            Some(chat_room) => chat_room.clone(),
+            None => return,
+        };
+
+        if let Some(username) = self.users.get(&conn_id) {
+            self.send_room_message(&chat_room, json!(UsersChanged {
+                event: USER_LEFT,
+                username,
+                room: &room,
             }).to_string()).await;
         }
     }
 
-    fn list_rooms(&self) -> Vec<Room> {
+    fn list_rooms(&self, access_level: u8) -> Vec<Room> {
         self.rooms.clone()
         .into_iter()
+        .filter(|room| room.access_level <= access_level)
         .map(|room| room.name)
         .collect()
     }
 
-    fn list_users(&self) -> Vec<User> {
+    /// Lists the users of a room the connection has joined, `None` if it
+    /// isn't a member or lacks access to the room.
+    fn list_users(&self, conn_id: ConnId, room: Room) -> Option<Vec<User>> {
+        let access_level = self.access_levels.get(&conn_id).copied().unwrap_or_default();
+
+        let members = match self.members.get(&room) {
+            Some(members) if members.contains(&conn_id) => members,
+            _ => {
+                self.send_error(conn_id, ROOM_ACCESS_ERROR, "Sinulla ei ole pääsyä tähän huoneeseen!");
+                return None;
+            },
+        };
+
+        match self.rooms.iter().find(|rm| rm.name.eq(&room)) {
+            Some(chat_room) if chat_room.access_level <= access_level => (),
+            _ => {
+                self.send_error(conn_id, ROOM_ACCESS_ERROR, "Sinulla ei ole pääsyä tähän huoneeseen!");
+                return None;
+            },
+        };
+
         let mut usernames = Vec::new();
 
-        for (_, username) in self.users.iter() {
-            usernames.push(username.to_owned());
+        for conn_id in members.iter() {
+            if let Some(username) = self.users.get(conn_id) {
+                usernames.push(username.to_owned());
+            }
         }
 
-        usernames
+        Some(usernames)
     }
 
-    fn timeout_user(&mut self, user: User, timeout: NaiveDateTime) {
-        self.timouts.insert(user, timeout);
+    /// Finds the target of a moderation command by display name, first from
+    /// the connected users and then from the database.
+    async fn resolve_target(&self, name: &str) -> Option<ModTarget> {
+        for (conn_id, username) in self.users.iter() {
+            if username.eq(name) {
+                return Some(ModTarget {
+                    user_id: self.user_ids.get(conn_id).copied()?,
+                    access_level: self.access_levels.get(conn_id).copied().unwrap_or_default(),
+                });
+            }
+        }
+
+        let user = match name.strip_prefix("anonyymi-").and_then(|id| id.parse::<u64>().ok()) {
+            Some(id) => UserAccount::by_id(id, &self.conn_pool).await.ok()?,
+            None => UserAccount::by_username(name, &self.conn_pool).await.ok()?,
+        };
+
+        Some(ModTarget {
+            user_id: user.id,
+            access_level: user.access_level,
+        })
+    }
+
+    fn connections_of(&self, user_id: u64) -> Vec<ConnId> {
+        self.user_ids.iter()
+        .filter(|(_, id)| **id == user_id)
+        .map(|(conn_id, _)| *conn_id)
+        .collect()
+    }
+
+    async fn kick(&mut self, user_id: u64, message: &str) {
+        for conn_id in self.connections_of(user_id) {
+            self.send_error(conn_id, KICKED, message);
+            self.disconnect(conn_id).await;
+        }
+    }
+
+    async fn moderate(&mut self, conn_id: ConnId, room: Room, target: Option<User>, action: ModAction) -> Result<String, String> {
+        let moderator_id = match self.user_ids.get(&conn_id) {
+            Some(user_id) => *user_id,
+            None => return Err("Yhteyttä ei löytynyt!".to_owned()),
+        };
+
+        let moderator_level = self.access_levels.get(&conn_id).copied().unwrap_or_default();
+
+        if require(moderator_level, AccessLevel::Moderator).is_err() {
+            return Err("Sinulla ei ole oikeutta tähän komentoon!".to_owned());
+        }
+
+        let target = match target {
+            Some(name) => match self.resolve_target(&name).await {
+                Some(target) if require_above(moderator_level, target.access_level).is_err() => {
+                    return Err("Et voi moderoida itseäsi ylempiä käyttäjiä!".to_owned());
+                },
+                Some(target) => Some(target),
+                None => return Err(format!("Käyttäjää {} ei löytynyt!", name)),
+            },
+            None => None,
+        };
+
+        let target_id = target.as_ref().map(|target| target.user_id);
+        let now = Utc::now().naive_utc();
+
+        let (details, feedback) = match (&action, target_id) {
+            (ModAction::Timeout { minutes }, Some(target_id)) => {
+                let expires_at = match chrono::Duration::try_minutes(*minutes)
+                .and_then(|duration| now.checked_add_signed(duration)) {
+                    Some(expires_at) => expires_at,
+                    None => return Err("Aikalisä on liian pitkä!".to_owned()),
+                };
+
+                let timeout = ChatTimeout {
+                    user_id: target_id,
+                    moderator_id,
+                    expires_at,
+                };
+
+                if timeout.upsert(&self.conn_pool).await.is_err() {
+                    return Err("Aikalisän tallentaminen epäonnistui!".to_owned());
+                }
+
+                self.timeouts.insert(target_id, expires_at);
+
+                (Some(format!("{} min", minutes)), "Aikalisä asetettu.".to_owned())
+            },
+
+            (ModAction::Untimeout, Some(target_id)) => {
+                if ChatTimeout::delete_by_user_id(&self.conn_pool, target_id).await.is_err() {
+                    return Err("Aikalisän poistaminen epäonnistui!".to_owned());
+                }
+
+                self.timeouts.remove(&target_id);
+
+                (None, "Aikalisä poistettu.".to_owned())
+            },
+
+            (ModAction::Kick, Some(target_id)) => {
+                if self.connections_of(target_id).is_empty() {
+                    return Err("Käyttäjä ei ole paikalla!".to_owned());
+                }
+
+                self.kick(target_id, "Sinut on potkittu chatista!").await;
+
+                (None, "Käyttäjä potkittu.".to_owned())
+            },
+
+            (ModAction::Ban { days, reason }, Some(target_id)) => {
+                // Chat bans are tied to the address the user is connected from.
+                let ip_addr = match self.connections_of(target_id).first()
+                .and_then(|conn_id| self.ip_addrs.get(conn_id)) {
+                    Some(ip_addr) => ip_addr.clone(),
+                    None => return Err("Käyttäjä ei ole paikalla!".to_owned()),
+                };
+
+                let expires_at = match chrono::Duration::try_days(*days)
+                .and_then(|duration| now.checked_add_signed(duration)) {
+                    Some(expires_at) => expires_at,
+                    None => return Err("Banni on liian pitkä!".to_owned()),
+                };
+
+                let ban = BanModel {
+                    moderator_id,
+                    user_id: Some(target_id),
+                    post_id: None,
+                    reason: Some(reason.as_str()),
+                    ip_address: &ip_addr,
+                    expires_at,
+                    ip_range: None,
+                    range_start: None,
+                    range_end: None,
+                };
+
+                if ban.insert(&self.conn_pool).await.is_err() {
+                    return Err("Bannien tallentaminen epäonnistui!".to_owned());
+                }
+
+                self.kick(target_id, "Sinut on bannattu!").await;
+
+                (Some(format!("{} pv: {}", days, reason)), "Käyttäjä bannattu.".to_owned())
+            },
+
+            (ModAction::Clear { minutes }, Some(target_id)) => {
+                let since = match chrono::Duration::try_minutes(*minutes)
+                .and_then(|duration| now.checked_sub_signed(duration)) {
+                    Some(since) => since,
+                    None => return Err("Aikaväli on liian pitkä!".to_owned()),
+                };
+
+                let chat_room = match self.rooms.iter().find(|rm| rm.name.eq(&room)) {
+                    Some(chat_room) => chat_room,
+                    None => return Err("Huonetta ei löytynyt!".to_owned()),
+                };
+
+                let ids = match ChatMessage::delete_recent_by_user(&self.conn_pool, chat_room.id, target_id, since).await {
+                    Ok(ids) => ids,
+                    Err(_) => return Err("Viestien poistaminen epäonnistui!".to_owned()),
+                };
+
+                let count = ids.len();
+
+                self.send_room_message(chat_room, json!(MessagesRemoved {
+                    event: MESSAGES_REMOVED,
+                    ids,
+                }).to_string()).await;
+
+                (Some(format!("{} min, {} viestiä", minutes, count)), format!("{} viestiä poistettu.", count))
+            },
+
+            (ModAction::Slow { seconds }, _) => {
+                let chat_room = match self.rooms.iter_mut().find(|rm| rm.name.eq(&room)) {
+                    Some(chat_room) => chat_room,
+                    None => return Err("Huonetta ei löytynyt!".to_owned()),
+                };
+
+                if ChatRoom::update_slow_mode(&self.conn_pool, chat_room.id, *seconds).await.is_err() {
+                    return Err("Hitaan tilan tallentaminen epäonnistui!".to_owned());
+                }
+
+                chat_room.slow_mode_seconds = *seconds;
+
+                let feedback = match seconds {
+                    0 => "Hidas tila poistettu.".to_owned(),
+                    _ => format!("Hidas tila asetettu ({} s).", seconds),
+                };
+
+                (Some(format!("{} s", seconds)), feedback)
+            },
+
+            (_, None) => return Err("Anna kohdekäyttäjä!".to_owned()),
+        };
+
+        let log_entry = ChatModActionModel {
+            moderator_id,
+            target_id,
+            room: Some(&room),
+            action: action.name(),
+            details: details.as_deref(),
+        };
+
+        let _ = log_entry.insert(&self.conn_pool).await;
+
+        Ok(feedback)
+    }
+
+    /// Sends every connection the list of rooms its access level permits.
+    async fn send_rooms_changed(&self) {
+        for (conn_id, session) in self.sessions.iter() {
+            let access_level = self.access_levels.get(conn_id).copied().unwrap_or_default();
+
+            let _ = session.send(json!(RoomsChanged {
+                event: ROOMS_CHANGED,
+                data: self.list_rooms(access_level),
+            }).to_string());
+        }
+    }
+
+    async fn add_room(&mut self, room: ChatRoom) {
+        self.rooms.push(room);
+        self.send_rooms_changed().await;
+    }
+
+    async fn rename_room(&mut self, id: u32, name: Room) {
+        let chat_room = match self.rooms.iter_mut().find(|rm| rm.id == id) {
+            Some(chat_room) => chat_room,
+            None => return,
+        };
+
+        let old_name = std::mem::replace(&mut chat_room.name, name.clone());
+
+        if let Some(members) = self.members.remove(&old_name) {
+            self.members.insert(name, members);
+        }
+
+        self.send_rooms_changed().await;
+    }
+
+    async fn change_room_access(&mut self, id: u32, access_level: u8) {
+        let chat_room = match self.rooms.iter_mut().find(|rm| rm.id == id) {
+            Some(chat_room) => {
+                chat_room.access_level = access_level;
+                chat_room.clone()
+            },
+            None => return,
+        };
+
+        // Members without sufficient access are removed from the room.
+        let evicted: Vec<ConnId> = match self.members.get(&chat_room.name) {
+            Some(members) => members.iter()
+            .filter(|conn_id| self.access_levels.get(conn_id).copied().unwrap_or_default() < access_level)
+            .copied()
+            .collect(),
+            None => Vec::new(),
+        };
+
+        for conn_id in evicted {
+            self.leave(conn_id, chat_room.name.clone()).await;
+        }
+
+        self.send_rooms_changed().await;
+    }
+
+    async fn remove_room(&mut self, id: u32) {
+        if let Some(index) = self.rooms.iter().position(|rm| rm.id == id) {
+            let chat_room = self.rooms.remove(index);
+            self.members.remove(&chat_room.name);
+        }
+
+        self.send_rooms_changed().await;
     }
 
     pub async fn run(mut self) -> io::Result<()> {
+        // Restore timeouts that have not yet expired.
+        if let Ok(timeouts) = ChatTimeout::list_active(&self.conn_pool, Utc::now().naive_utc()).await {
+            for timeout in timeouts {
+                self.timeouts.insert(timeout.user_id, timeout.expires_at);
+            }
+        }
+
         while let Some(cmd) = self.cmd_rx.recv().await {
             match cmd {
-                Command::Connect { conn_tx, res_tx, user } => {
-                    let conn_id = self.connect(user, conn_tx).await;
+                Command::Connect { conn_tx, res_tx, user_id, user, access_level, ip_addr } => {
+                    let conn_id = self.connect(user_id, user, access_level, ip_addr, conn_tx).await;
                     let _ = res_tx.send(conn_id);
                 }
 
@@ -196,12 +804,27 @@ impl ChatServer {
                     self.disconnect(conn).await;
                 }
 
-                Command::ListRoom { res_tx } => {
-                    let _ = res_tx.send(self.list_rooms());
+                Command::Join { conn, room, res_tx } => {
+                    let joined = self.join(conn, room).await;
+                    let _ = res_tx.send(joined);
                 }
 
-                Command::ListUser { res_tx } => {
-                    let _ = res_tx.send(self.list_users());
+                Command::Leave { conn, room, res_tx } => {
+                    self.leave(conn, room).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::History { conn, room, before, res_tx } => {
+                    self.send_history(conn, room, before).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::ListRoom { access_level, res_tx } => {
+                    let _ = res_tx.send(self.list_rooms(access_level));
+                }
+
+                Command::ListUser { conn, room, res_tx } => {
+                    let _ = res_tx.send(self.list_users(conn, room));
                 }
 
                 Command::Message { msg, res_tx } => {
@@ -214,8 +837,28 @@ impl ChatServer {
                     let _ = res_tx.send(());
                 }
 
-                Command::SetTimeOut { user, timeout, res_tx } => {
-                    self.timeout_user(user, timeout);
+                Command::Moderate { conn, room, target, action, res_tx } => {
+                    let res = self.moderate(conn, room, target, action).await;
+                    let _ = res_tx.send(res);
+                }
+
+                Command::AddRoom { room, res_tx } => {
+                    self.add_room(room).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::RenameRoom { id, name, res_tx } => {
+                    self.rename_room(id, name).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::ChangeRoomAccess { id, access_level, res_tx } => {
+                    self.change_room_access(id, access_level).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::RemoveRoom { id, res_tx } => {
+                    self.remove_room(id).await;
                     let _ = res_tx.send(());
                 }
             }
@@ -231,28 +874,58 @@ pub struct ChatServerHandle {
 }
 
 impl ChatServerHandle {
-    pub async fn connect(&self, user: User, conn_tx: mpsc::UnboundedSender<Msg>) -> ConnId {
+    pub async fn connect(&self, user_id: u64, user: User, access_level: u8, ip_addr: String, conn_tx: mpsc::UnboundedSender<Msg>) -> ConnId {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::Connect { user_id, user, access_level, ip_addr, conn_tx, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn join(&self, conn: ConnId, room: Room) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::Join { conn, room, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn leave(&self, conn: ConnId, room: Room) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::Leave { conn, room, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn load_history(&self, conn: ConnId, room: Room, before: Option<u64>) {
         let (res_tx, res_rx) = oneshot::channel();
 
         self.cmd_tx
-            .send(Command::Connect { user, conn_tx, res_tx })
+            .send(Command::History { conn, room, before, res_tx })
             .unwrap();
 
         res_rx.await.unwrap()
     }
 
-    pub async fn list_rooms(&self) -> Vec<Room> {
+    pub async fn list_rooms(&self, access_level: u8) -> Vec<Room> {
         let (res_tx, res_rx) = oneshot::channel();
 
-        self.cmd_tx.send(Command::ListRoom { res_tx }).unwrap();
+        self.cmd_tx.send(Command::ListRoom { access_level, res_tx }).unwrap();
 
         res_rx.await.unwrap()
     }
 
-    pub async fn list_users(&self) -> Vec<User> {
+    pub async fn list_users(&self, conn: ConnId, room: Room) -> Option<Vec<User>> {
         let (res_tx, res_rx) = oneshot::channel();
 
-        self.cmd_tx.send(Command::ListUser { res_tx }).unwrap();
+        self.cmd_tx.send(Command::ListUser { conn, room, res_tx }).unwrap();
 
         res_rx.await.unwrap()
     }
@@ -291,17 +964,57 @@ impl ChatServerHandle {
         self.cmd_tx.send(Command::Disconnect { conn }).unwrap();
     }
 
-    pub async fn timeout_user(&self, user: User, timeout: NaiveDateTime) {
+    pub async fn moderate(&self, conn: ConnId, room: Room, target: Option<User>, action: ModAction) -> Result<String, String> {
         let (res_tx, res_rx) = oneshot::channel();
 
         self.cmd_tx
-            .send(Command::SetTimeOut { 
-                user, 
-                timeout, 
+            .send(Command::Moderate { 
+                conn, 
+                room, 
+                target, 
+                action, 
                 res_tx,
             })
             .unwrap();
 
+        res_rx.await.unwrap()
+    }
+
+    pub async fn add_room(&self, room: ChatRoom) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx.send(Command::AddRoom { room, res_tx }).unwrap();
+
+        res_rx.await.unwrap();
+    }
+
+    pub async fn rename_room(&self, id: u32, name: Room) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx.send(Command::RenameRoom { id, name, res_tx }).unwrap();
+
+        res_rx.await.unwrap();
+    }
+
+    pub async fn change_room_access(&self, id: u32, access_level: u8) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::ChangeRoomAccess { 
+                id, 
+                access_level, 
+                res_tx,
+            })
+            .unwrap();
+
+        res_rx.await.unwrap();
+    }
+
+    pub async fn remove_room(&self, id: u32) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx.send(Command::RemoveRoom { id, res_tx }).unwrap();
+
         res_rx.await.unwrap();
     }
 }
\ No newline at end of file
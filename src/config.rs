@@ -0,0 +1,476 @@
+use std::{env, fmt, fs, io, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::services::{flood::DuplicateScope, ip::TrustedProxies};
+
+
+/// Path of the configuration file, unless overridden with `KAPCHAN_CONFIG`.
+pub const DEFAULT_CONFIG_PATH: &str = "kapchan.toml";
+
+/// Server configuration loaded on startup and shared with the handlers
+/// through `web::Data<Config>`.
+///
+/// Values are read from a TOML file and can be overridden with environment
+/// variables named `KAPCHAN_<SECTION>_<KEY>`, e.g. `KAPCHAN_SERVER_PORT`.
+/// Every key is optional and falls back to its default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub posts: PostsConfig,
+    pub flood: FloodConfig,
+    pub rate_limit: RateLimitConfig,
+    pub captcha: CaptchaConfig,
+    pub security: SecurityConfig,
+    pub login: LoginConfig,
+    pub chat: ChatConfig,
+    pub geoip: GeoIpConfig,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    pub port: u16,
+    pub session_ttl_days: i64,
+    /// Proxies allowed to set `Forwarded`, `X-Forwarded-For` and `X-Real-IP`.
+    /// Addresses or CIDR ranges; forwarding headers are ignored when empty.
+    pub trusted_proxies: TrustedProxies,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1".to_owned(),
+            port: 8080,
+            session_ttl_days: 365,
+            trusted_proxies: TrustedProxies::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostsConfig {
+    /// Largest accepted attachment in bytes.
+    pub max_attachment_bytes: usize,
+    /// Longest accepted post message in bytes.
+    pub max_message_length: usize,
+    /// Thumbnails are scaled to fit a square of this size in pixels.
+    pub thumbnail_size: u32,
+}
+
+impl Default for PostsConfig {
+    fn default() -> Self {
+        Self {
+            max_attachment_bytes: 5_000_000,
+            max_message_length: 20_000,
+            thumbnail_size: 300,
+        }
+    }
+}
+
+/// Limits enforced on new posts and threads, staff is exempt. A value of 0
+/// turns the check off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FloodConfig {
+    /// Seconds between two posts from the same user or address.
+    pub post_cooldown_seconds: i64,
+    /// Seconds between two new threads from the same user or address.
+    pub thread_cooldown_seconds: i64,
+    /// Seconds during which a message identical to an earlier post is rejected.
+    pub duplicate_window_seconds: i64,
+    /// Look for identical messages on the same `board` or on every board (`global`).
+    pub duplicate_scope: DuplicateScope,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        Self {
+            post_cooldown_seconds: 15,
+            thread_cooldown_seconds: 300,
+            duplicate_window_seconds: 600,
+            duplicate_scope: DuplicateScope::Board,
+        }
+    }
+}
+
+/// Request limits per address and per user for each group of write
+/// endpoints, moderators are exempt.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// `GET /captcha`, which stores a new captcha on every call.
+    pub captcha: TokenBucket,
+    /// `POST /login`.
+    pub login: TokenBucket,
+    /// `POST /register` and `POST /apply`.
+    pub register: TokenBucket,
+    /// `POST /report-post`.
+    pub report: TokenBucket,
+    /// New threads and replies.
+    pub post: TokenBucket,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            captcha: TokenBucket { burst: 10, per_minute: 6 },
+            login: TokenBucket { burst: 10, per_minute: 5 },
+            register: TokenBucket { burst: 3, per_minute: 1 },
+            report: TokenBucket { burst: 5, per_minute: 2 },
+            post: TokenBucket { burst: 10, per_minute: 10 },
+        }
+    }
+}
+
+/// Token bucket holding up to `burst` requests and refilled with
+/// `per_minute` requests a minute. A `per_minute` of 0 turns the limit off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenBucket {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl TokenBucket {
+    pub fn is_enabled(&self) -> bool {
+        self.per_minute > 0
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptchaConfig {
+    pub expiry_minutes: i64,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        Self {
+            expiry_minutes: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    pub pbkdf2_iterations: u32,
+    /// Rejects state-changing requests without the session's CSRF token or
+    /// from another origin.
+    pub csrf_protection: bool,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            pbkdf2_iterations: 5000,
+            csrf_protection: true,
+        }
+    }
+}
+
+/// Protection against password guessing. Failed logins are counted per
+/// account and per address.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginConfig {
+    /// Failures older than this are forgotten.
+    pub window_minutes: i64,
+    /// Failures on an account or from an address after which a captcha is
+    /// required. 0 turns captchas off.
+    pub captcha_after: i64,
+    /// Failures on an account after which it is locked. 0 turns it off.
+    pub lockout_after: i64,
+    /// Failures from an address after which it is locked out. Addresses
+    /// may be shared, so this is usually higher. 0 turns it off.
+    pub ip_lockout_after: i64,
+    /// Length of the first lockout, doubled with every further failure.
+    pub lockout_seconds: i64,
+    /// Longest lockout.
+    pub max_lockout_minutes: i64,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            window_minutes: 60,
+            captcha_after: 3,
+            lockout_after: 5,
+            ip_lockout_after: 20,
+            lockout_seconds: 30,
+            max_lockout_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    pub max_message_bytes: usize,
+    /// Number of stored messages sent when joining a room.
+    pub backlog_size: i64,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: 2000,
+            backlog_size: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoIpConfig {
+    /// MaxMind format country database (`.mmdb`) used for post flags.
+    /// Country lookups are disabled when not set.
+    pub database: Option<String>,
+}
+
+/// Deleted posts and threads can be restored by moderators until they are
+/// purged together with their files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Days a deleted post or thread is kept before it is purged.
+    pub deleted_days: i64,
+    /// Minutes between two purges.
+    pub purge_interval_minutes: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            deleted_days: 30,
+            purge_interval_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, io::Error),
+    Parse(String, toml::de::Error),
+    Env(String, String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "failed to read config file `{}`: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config file `{}`:\n{}", path, e),
+            ConfigError::Env(name, value) => write!(f, "invalid value `{}` for env variable `{}`", value, name),
+            ConfigError::Invalid(errors) => {
+                writeln!(f, "invalid configuration:")?;
+
+                for error in errors {
+                    writeln!(f, "  - {}", error)?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads the config file, applies environment overrides and validates
+    /// the result. A missing config file is not an error when its path was
+    /// not given explicitly.
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, explicit) = match env::var("KAPCHAN_CONFIG") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_owned(), false),
+        };
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str::<Config>(&contents)
+            .map_err(|e| ConfigError::Parse(path.clone(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => Config::default(),
+            Err(e) => return Err(ConfigError::Read(path, e)),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        env_override("KAPCHAN_SERVER_BIND_ADDRESS", &mut self.server.bind_address)?;
+        env_override("KAPCHAN_SERVER_PORT", &mut self.server.port)?;
+        env_override("KAPCHAN_SERVER_SESSION_TTL_DAYS", &mut self.server.session_ttl_days)?;
+        env_override("KAPCHAN_SERVER_TRUSTED_PROXIES", &mut self.server.trusted_proxies)?;
+        env_override("KAPCHAN_POSTS_MAX_ATTACHMENT_BYTES", &mut self.posts.max_attachment_bytes)?;
+        env_override("KAPCHAN_POSTS_MAX_MESSAGE_LENGTH", &mut self.posts.max_message_length)?;
+        env_override("KAPCHAN_POSTS_THUMBNAIL_SIZE", &mut self.posts.thumbnail_size)?;
+        env_override("KAPCHAN_FLOOD_POST_COOLDOWN_SECONDS", &mut self.flood.post_cooldown_seconds)?;
+        env_override("KAPCHAN_FLOOD_THREAD_COOLDOWN_SECONDS", &mut self.flood.thread_cooldown_seconds)?;
+        env_override("KAPCHAN_FLOOD_DUPLICATE_WINDOW_SECONDS", &mut self.flood.duplicate_window_seconds)?;
+        env_override("KAPCHAN_FLOOD_DUPLICATE_SCOPE", &mut self.flood.duplicate_scope)?;
+        env_override("KAPCHAN_RATE_LIMIT_CAPTCHA_BURST", &mut self.rate_limit.captcha.burst)?;
+        env_override("KAPCHAN_RATE_LIMIT_CAPTCHA_PER_MINUTE", &mut self.rate_limit.captcha.per_minute)?;
+        env_override("KAPCHAN_RATE_LIMIT_LOGIN_BURST", &mut self.rate_limit.login.burst)?;
+        env_override("KAPCHAN_RATE_LIMIT_LOGIN_PER_MINUTE", &mut self.rate_limit.login.per_minute)?;
+        env_override("KAPCHAN_RATE_LIMIT_REGISTER_BURST", &mut self.rate_limit.register.burst)?;
+        env_override("KAPCHAN_RATE_LIMIT_REGISTER_PER_MINUTE", &mut self.rate_limit.register.per_minute)?;
+        env_override("KAPCHAN_RATE_LIMIT_REPORT_BURST", &mut self.rate_limit.report.burst)?;
+        env_override("KAPCHAN_RATE_LIMIT_REPORT_PER_MINUTE", &mut self.rate_limit.report.per_minute)?;
+        env_override("KAPCHAN_RATE_LIMIT_POST_BURST", &mut self.rate_limit.post.burst)?;
+        env_override("KAPCHAN_RATE_LIMIT_POST_PER_MINUTE", &mut self.rate_limit.post.per_minute)?;
+        env_override("KAPCHAN_CAPTCHA_EXPIRY_MINUTES", &mut self.captcha.expiry_minutes)?;
+        env_override("KAPCHAN_SECURITY_PBKDF2_ITERATIONS", &mut self.security.pbkdf2_iterations)?;
+        env_override("KAPCHAN_SECURITY_CSRF_PROTECTION", &mut self.security.csrf_protection)?;
+        env_override("KAPCHAN_LOGIN_WINDOW_MINUTES", &mut self.login.window_minutes)?;
+        env_override("KAPCHAN_LOGIN_CAPTCHA_AFTER", &mut self.login.captcha_after)?;
+        env_override("KAPCHAN_LOGIN_LOCKOUT_AFTER", &mut self.login.lockout_after)?;
+        env_override("KAPCHAN_LOGIN_IP_LOCKOUT_AFTER", &mut self.login.ip_lockout_after)?;
+        env_override("KAPCHAN_LOGIN_LOCKOUT_SECONDS", &mut self.login.lockout_seconds)?;
+        env_override("KAPCHAN_LOGIN_MAX_LOCKOUT_MINUTES", &mut self.login.max_lockout_minutes)?;
+        env_override("KAPCHAN_CHAT_MAX_MESSAGE_BYTES", &mut self.chat.max_message_bytes)?;
+        env_override("KAPCHAN_CHAT_BACKLOG_SIZE", &mut self.chat.backlog_size)?;
+        env_override("KAPCHAN_RETENTION_DELETED_DAYS", &mut self.retention.deleted_days)?;
+        env_override("KAPCHAN_RETENTION_PURGE_INTERVAL_MINUTES", &mut self.retention.purge_interval_minutes)?;
+
+        if let Ok(database) = env::var("KAPCHAN_GEOIP_DATABASE") {
+            self.geoip.database = Some(database.trim().to_owned()).filter(|path| !path.is_empty());
+        }
+
+        Ok(())
+    }
+
+    /// Checks every value and reports all problems at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.server.bind_address.trim().is_empty() {
+            errors.push("server.bind_address must not be empty".to_owned());
+        }
+
+        if self.server.port == 0 {
+            errors.push("server.port must be between 1 and 65535".to_owned());
+        }
+
+        // Durations are built from these later, so they can't be left unbounded.
+        if !(1..=3650).contains(&self.server.session_ttl_days) {
+            errors.push("server.session_ttl_days must be between 1 and 3650".to_owned());
+        }
+
+        if self.posts.max_attachment_bytes == 0 {
+            errors.push("posts.max_attachment_bytes must be at least 1".to_owned());
+        }
+
+        if self.posts.max_message_length == 0 {
+            errors.push("posts.max_message_length must be at least 1".to_owned());
+        }
+
+        // posts.message is a TEXT column.
+        if self.posts.max_message_length > 65_535 {
+            errors.push("posts.max_message_length must be at most 65535".to_owned());
+        }
+
+        if !(16..=2048).contains(&self.posts.thumbnail_size) {
+            errors.push("posts.thumbnail_size must be between 16 and 2048".to_owned());
+        }
+
+        for (name, seconds) in [
+            ("post_cooldown_seconds", self.flood.post_cooldown_seconds),
+            ("thread_cooldown_seconds", self.flood.thread_cooldown_seconds),
+            ("duplicate_window_seconds", self.flood.duplicate_window_seconds),
+        ] {
+            if !(0..=604_800).contains(&seconds) {
+                errors.push(format!("flood.{} must be between 0 and 604800", name));
+            }
+        }
+
+        for (group, limit) in [
+            ("captcha", &self.rate_limit.captcha),
+            ("login", &self.rate_limit.login),
+            ("register", &self.rate_limit.register),
+            ("report", &self.rate_limit.report),
+            ("post", &self.rate_limit.post),
+        ] {
+            if limit.is_enabled() && limit.burst == 0 {
+                errors.push(format!("rate_limit.{}.burst must be at least 1 when per_minute is set", group));
+            }
+        }
+
+        if !(1..=1440).contains(&self.captcha.expiry_minutes) {
+            errors.push("captcha.expiry_minutes must be between 1 and 1440".to_owned());
+        }
+
+        if self.security.pbkdf2_iterations < 1000 {
+            errors.push("security.pbkdf2_iterations must be at least 1000".to_owned());
+        }
+
+        if !(1..=10_080).contains(&self.login.window_minutes) {
+            errors.push("login.window_minutes must be between 1 and 10080".to_owned());
+        }
+
+        if self.login.captcha_after < 0 || self.login.lockout_after < 0 || self.login.ip_lockout_after < 0 {
+            errors.push("login.captcha_after, login.lockout_after and login.ip_lockout_after must not be negative".to_owned());
+        }
+
+        if self.login.lockout_seconds < 1 {
+            errors.push("login.lockout_seconds must be at least 1".to_owned());
+        }
+
+        if !(1..=10_080).contains(&self.login.max_lockout_minutes) {
+            errors.push("login.max_lockout_minutes must be between 1 and 10080".to_owned());
+        }
+
+        if self.chat.max_message_bytes == 0 {
+            errors.push("chat.max_message_bytes must be at least 1".to_owned());
+        }
+
+        if self.chat.backlog_size < 0 {
+            errors.push("chat.backlog_size must not be negative".to_owned());
+        }
+
+        if self.geoip.database.as_ref().is_some_and(|path| path.trim().is_empty()) {
+            errors.push("geoip.database must not be empty, leave it out to disable country lookups".to_owned());
+        }
+
+        if !(0..=3650).contains(&self.retention.deleted_days) {
+            errors.push("retention.deleted_days must be between 0 and 3650".to_owned());
+        }
+
+        if !(1..=10_080).contains(&self.retention.purge_interval_minutes) {
+            errors.push("retention.purge_interval_minutes must be between 1 and 10080".to_owned());
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(errors)),
+        }
+    }
+
+    /// Upper bound for a whole multipart post form: the attachment plus the
+    /// text fields, which may take up to four bytes per character.
+    pub fn max_post_form_bytes(&self) -> usize {
+        self.posts.max_attachment_bytes + 4 * self.posts.max_message_length + 64 * 1024
+    }
+}
+
+fn env_override<T: FromStr>(
+    name: &str,
+    value: &mut T,
+) -> Result<(), ConfigError> {
+    if let Ok(env_value) = env::var(name) {
+        *value = env_value
+        .trim()
+        .parse()
+        .map_err(|_| ConfigError::Env(name.to_owned(), env_value.clone()))?;
+    }
+
+    Ok(())
+}
\ No newline at end of file
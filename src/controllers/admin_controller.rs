@@ -1,20 +1,21 @@
 use actix_identity::Identity;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use chrono::{Duration, Utc};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use chrono::Days;
+use diesel_async::{pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncMysqlConnection};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::{models::{applications::Application, bans::{Ban, BanModel}, boards::{Board, BoardModel}, chat_rooms::ChatRoom, posts::Post, users::{AccessLevel, User}}, services::{applications::{count_preview_pages, is_reviewed, load_application_previews, review_application}, authentication::resolve_user}, views::{admin_view::{self, AdminTemplate}, application_list_view::{self, ApplicationListTemplate}, application_review_view::{self, ApplicationReviewTemplate}, banned_view::{self, BannedTemplate}, forbidden_view::{self, ForbiddenTemplate}, user_view::{self, UserTemplate}, users_view::{self, UsersTemplate}}};
+use crate::{middleware::csrf::CsrfToken, models::{applications::Application, bans::{Ban, BanModel}, boards::{Board, BoardModel}, chat_rooms::ChatRoom, error::UserError, mod_actions::{ModAction, ModActionFilter, ModActionKind, ModActionModel}, posts::Post, roles::{Role, UserRole, UserRoleModel}, users::{AccessLevel, User}}, services::{applications::{count_preview_pages, is_reviewed, load_application_previews, review_application}, authentication::resolve_user, ip::range_ban, moderation::{self, ban_expiry, invalid_ban_duration, transaction}, permissions::{authorize_area, authorize_ban, authorize_user_modification, require_above, Capability, PermissionError, Permissions}}, views::{admin_view::{self, AdminTemplate}, application_list_view::{self, ApplicationListTemplate}, application_review_view::{self, ApplicationReviewTemplate}, banned_view::{self, BannedTemplate}, forbidden_view::{self, ForbiddenTemplate}, mod_log_view::{self, ModLogTemplate}, user_view::{self, UserTemplate}, users_view::{self, UsersTemplate}}};
 
-use super::post_controller::BanUserInput;
+use super::{board_controller::parse_date, post_controller::BanUserInput};
 
 
 pub async fn admin(
     user: Option<Identity>,
     req: HttpRequest,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    csrf: CsrfToken,
 ) -> actix_web::Result<HttpResponse> {
     let user_data = match resolve_user(user, req, &conn_pool).await {
         Ok(usr_data) => usr_data,
@@ -60,6 +61,7 @@ pub async fn admin(
         errors: vec![],
         boards,
         chat_rooms,
+        csrf_token: csrf.0,
     }).await
 }
 
@@ -91,26 +93,25 @@ pub struct CreateBoardForm {
     pub thread_size: u32,
     pub captcha: Option<String>,
     pub nsfw: Option<String>,
+    pub flags: Option<String>,
 }
 
 pub async fn handle_board_creation(
-    user: Option<Identity>,
+    perms: Permissions,
     input: web::Form<CreateBoardForm>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
+    csrf: CsrfToken,
 ) -> actix_web::Result<HttpResponse> {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let user_data = &perms.user;
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return Ok(HttpResponse::Forbidden().finish());
+    if let Err(e) = perms.require(Capability::ManageBoards, None) {
+        return Ok(e.error_response());
     }
 
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return Ok(HttpResponse::Forbidden().finish())
-    }
+    let access_level = match authorize_area(user_data.access_level, input.access_level) {
+        Ok(access_level) => access_level,
+        Err(e) => return Ok(e.error_response()),
+    };
 
     match input.validate() {
         Ok(_) => (),
@@ -136,6 +137,7 @@ pub async fn handle_board_creation(
                 access_level: user_data.access_level,
                 boards,
                 chat_rooms,
+                csrf_token: csrf.0,
             };
 
             return admin_view::render(template).await;
@@ -145,20 +147,37 @@ pub async fn handle_board_creation(
     let board = BoardModel {
         handle: &input.handle,
         title: &input.title,
-        access_level: input.access_level,
+        access_level: access_level as u8,
         active_threads_limit: input.threads_limit,
         thread_size_limit: input.thread_size,
         captcha: input.captcha.is_some(),
         nsfw: input.nsfw.is_some(),
+        flags: input.flags.is_some(),
         description: &input.description,
-    }
-    .insert(&conn_pool)
+    };
+
+    let details = format!("/{}/ - {}", input.handle, input.title);
+    let moderator_id = user_data.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        let board = board.insert_conn(conn).await?;
+
+        ModActionModel {
+            item_id: Some(board.id.into()),
+            board_id: Some(board.id),
+            details: Some(&details),
+            ..ModActionModel::new(moderator_id, ModActionKind::CreateBoard)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
     .await;
 
-    match board {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/admin")).finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    if res.is_err() {
+        return Ok(HttpResponse::InternalServerError().finish());
     }
+
+    Ok(HttpResponse::Found().append_header(("Location", "/admin")).finish())
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -189,29 +208,27 @@ pub struct EditBoardForm {
     pub thread_size: u32,
     pub captcha: Option<String>,
     pub nsfw: Option<String>,
+    pub flags: Option<String>,
 }
 
 pub async fn handle_board_edit(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     input: web::Form<EditBoardForm>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
+    csrf: CsrfToken,
 ) -> actix_web::Result<HttpResponse> {
     let board_id = path.into_inner();
+    let user_data = &perms.user;
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
-
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return Ok(HttpResponse::Forbidden().finish());
+    if let Err(e) = perms.require(Capability::ManageBoards, Some(board_id)) {
+        return Ok(e.error_response());
     }
 
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return Ok(HttpResponse::Forbidden().finish())
-    }
+    let access_level = match authorize_area(user_data.access_level, input.access_level) {
+        Ok(access_level) => access_level,
+        Err(e) => return Ok(e.error_response()),
+    };
 
     match input.validate() {
         Ok(_) => (),
@@ -237,25 +254,47 @@ pub async fn handle_board_edit(
                 access_level: user_data.access_level,
                 boards,
                 chat_rooms,
+                csrf_token: csrf.0,
             };
 
             return admin_view::render(template).await;
         },
     };
 
-    match Board::update_board(&conn_pool, board_id, BoardModel {
+    let model = BoardModel {
         handle: &input.handle,
         title: &input.title,
         description: &input.description,
-        access_level: input.access_level,
+        access_level: access_level as u8,
         active_threads_limit: input.threads_limit,
         thread_size_limit: input.thread_size,
         captcha: input.captcha.is_some(),
         nsfw: input.nsfw.is_some(),
-    }).await {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/admin")).finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+        flags: input.flags.is_some(),
+    };
+
+    let details = format!("/{}/ - {}", input.handle, input.title);
+    let moderator_id = user_data.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Board::update_board_conn(conn, board_id, model).await?;
+
+        ModActionModel {
+            item_id: Some(board_id.into()),
+            board_id: Some(board_id),
+            details: Some(&details),
+            ..ModActionModel::new(moderator_id, ModActionKind::EditBoard)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    if res.is_err() {
+        return Ok(HttpResponse::InternalServerError().finish());
     }
+
+    Ok(HttpResponse::Found().append_header(("Location", "/admin")).finish())
 }
 
 pub async fn applications_list(
@@ -323,6 +362,7 @@ pub async fn application_review(
     user: Option<Identity>,
     req: HttpRequest,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    csrf: CsrfToken,
 ) -> actix_web::Result<HttpResponse> {
     let user_data = match resolve_user(user, req, &conn_pool).await {
         Ok(usr_data) => usr_data,
@@ -369,26 +409,19 @@ pub async fn application_review(
         access_level: user_data.access_level,
         boards,
         application,
+        csrf_token: csrf.0,
     }).await
 }
 
 pub async fn handle_application_accept(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> actix_web::Result<HttpResponse> {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
-
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return Ok(HttpResponse::Forbidden().finish());
-    }
+    let user_data = &perms.user;
 
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return Ok(HttpResponse::Forbidden().finish());
+    if let Err(e) = perms.require(Capability::ReviewApplications, None) {
+        return Ok(e.error_response());
     }
 
     let application_id = path.into_inner();
@@ -402,34 +435,37 @@ pub async fn handle_application_accept(
         return Ok(HttpResponse::Forbidden().finish())
     }
 
-    let application = match review_application(&conn_pool, application_id, user_data.id, true).await {
-        Ok(app) => app,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let moderator_id = user_data.id;
 
-    match User::update_access_level(application.user_id, AccessLevel::Member as u8, &conn_pool).await {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/applications/1")).finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    let res = transaction(&conn_pool, |conn| async move {
+        let application = review_application(conn, application_id, moderator_id, true).await?;
+
+        ModActionModel {
+            item_id: Some(application.id.into()),
+            target_id: Some(application.user_id),
+            ..ModActionModel::new(moderator_id, ModActionKind::AcceptApplication)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    if res.is_err() {
+        return Ok(HttpResponse::InternalServerError().finish());
     }
+
+    Ok(HttpResponse::Found().append_header(("Location", "/applications/1")).finish())
 }
 
 pub async fn handle_application_deny(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> actix_web::Result<HttpResponse> {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let user_data = &perms.user;
 
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return Ok(HttpResponse::Forbidden().finish())
-    }
-
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return Ok(HttpResponse::Forbidden().finish());
+    if let Err(e) = perms.require(Capability::ReviewApplications, None) {
+        return Ok(e.error_response());
     }
 
     let application_id = path.into_inner();
@@ -443,15 +479,26 @@ pub async fn handle_application_deny(
         return Ok(HttpResponse::Forbidden().finish())
     }
 
-    let application = match review_application(&conn_pool, application_id, user_data.id, false).await {
-        Ok(app) => app,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let moderator_id = user_data.id;
 
-    match User::update_access_level(application.user_id, AccessLevel::Registered as u8, &conn_pool).await {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/applications/1")).finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    let res = transaction(&conn_pool, |conn| async move {
+        let application = review_application(conn, application_id, moderator_id, false).await?;
+
+        ModActionModel {
+            item_id: Some(application.id.into()),
+            target_id: Some(application.user_id),
+            ..ModActionModel::new(moderator_id, ModActionKind::DenyApplication)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    if res.is_err() {
+        return Ok(HttpResponse::InternalServerError().finish());
     }
+
+    Ok(HttpResponse::Found().append_header(("Location", "/applications/1")).finish())
 }
 
 #[derive(Debug, Deserialize)]
@@ -539,16 +586,11 @@ pub async fn users_list(
 
 pub async fn user(
     path: web::Path<u64>,
-    user: Option<Identity>,
-    req: HttpRequest,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
 ) -> actix_web::Result<HttpResponse> {
     let target_user_id = path.into_inner();
-
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let user_data = &perms.user;
 
     if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
         let mut ban_post: Option<Post> = None;
@@ -561,7 +603,7 @@ pub async fn user(
         }
 
         return banned_view::render(BannedTemplate {
-            ban: user_data.banned.unwrap(),
+            ban: user_data.banned.clone().unwrap(),
             post: ban_post,
         })
         .await;
@@ -589,37 +631,76 @@ pub async fn user(
         Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
     };
 
+    let user_roles = match UserRole::list_by_user(target_user_id, &conn_pool).await {
+        Ok(user_roles) => user_roles,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let roles = match Role::list_all(&conn_pool).await {
+        Ok(roles) => roles,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
     return user_view::render(UserTemplate {
         access_level: user_data.access_level,
+        manage_users: perms.can(Capability::ManageUsers, None),
         boards,
         user,
         bans,
+        user_roles,
+        roles,
     })
     .await;
 }
 
 pub async fn handle_ban_deletion(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> actix_web::Result<HttpResponse> {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    let user_data = &perms.user;
+
+    if let Err(e) = perms.require(Capability::Ban, None) {
+        return Ok(e.error_response());
+    }
+
+    let ban_id = path.into_inner();
+
+    let ban = match Ban::by_id(&conn_pool, ban_id).await {
+        Ok(ban) => ban,
+        Err(diesel::result::Error::NotFound) => return Ok(HttpResponse::NotFound().finish()),
         Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return Ok(HttpResponse::Forbidden().finish());
-    }
+    // Address bans have no user to outrank.
+    if let Some(banned_user_id) = ban.user_id {
+        let banned_user = match User::by_id(banned_user_id, &conn_pool).await {
+            Ok(banned_user) => banned_user,
+            Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+        };
 
-    if user_data.access_level < AccessLevel::Moderator as u8 {
-        return Ok(HttpResponse::Forbidden().finish())
+        if let Err(e) = authorize_ban(user_data.access_level, banned_user.access_level) {
+            return Ok(e.error_response());
+        }
     }
 
-    let ban_id = path.into_inner();
+    let moderator_id = user_data.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Ban::delete_ban_conn(conn, ban_id).await?;
 
-    match Ban::delete_ban(&conn_pool, ban_id).await {
+        ModActionModel {
+            item_id: Some(ban.id.into()),
+            target_id: ban.user_id,
+            details: ban.reason.as_deref(),
+            ..ModActionModel::new(moderator_id, ModActionKind::LiftBan)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => Ok(HttpResponse::Found().finish()),
         Err(_) => Ok(HttpResponse::InternalServerError().finish()),
     }
@@ -634,33 +715,47 @@ pub struct ModifyUserInput {
 
 pub async fn modify_user_by_id(
     path: web::Path<u64>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     input: web::Json<ModifyUserInput>,
-    req: HttpRequest,
 ) -> impl Responder {
     let user_id = path.into_inner();
+    let user_data = &perms.user;
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    if let Err(e) = perms.require(Capability::ManageUsers, None) {
+        return e.error_response();
+    }
+
+    let target_user = match User::by_id(user_id, &conn_pool).await {
+        Ok(target_user) => target_user,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
-    
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
+    let access_level = match authorize_user_modification(user_data.access_level, target_user.access_level, input.access_level) {
+        Ok(access_level) => access_level,
+        Err(e) => return e.error_response(),
+    };
 
-    match User::update_user(
-        user_id, 
-        input.access_level, 
-        input.username.clone(), 
-        input.email.clone(), 
-        &conn_pool
-    ).await {
+    let details = format!("käyttäjätaso {} -> {}", target_user.access_level, access_level as u8);
+    let moderator_id = user_data.id;
+    let (username, email) = (input.username.clone(), input.email.clone());
+
+    let res = transaction(&conn_pool, |conn| async move {
+        User::update_user_conn(conn, user_id, access_level as u8, username, email).await?;
+
+        ModActionModel {
+            item_id: Some(user_id),
+            target_id: Some(user_id),
+            details: Some(&details),
+            ..ModActionModel::new(moderator_id, ModActionKind::ModifyUser)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => HttpResponse::Created().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
@@ -668,20 +763,15 @@ pub async fn modify_user_by_id(
 
 pub async fn ban_user_by_id(
     path: web::Path<u64>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     input: web::Json<BanUserInput>,
-    req: HttpRequest,
 ) -> impl Responder {
     let user_id = path.into_inner();
+    let user_data = &perms.user;
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = perms.require(Capability::Ban, None) {
+        return e.error_response();
     }
 
     let target_user = match User::by_id(user_id, &conn_pool).await {
@@ -689,11 +779,31 @@ pub async fn ban_user_by_id(
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.access_level < AccessLevel::Moderator as u8 || user_data.access_level <= target_user.access_level {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = authorize_ban(user_data.access_level, target_user.access_level) {
+        return e.error_response();
     }
 
-    let expires_at = (Utc::now() + Duration::days(input.ban_duration_days)).naive_utc();
+    let expires_at = match ban_expiry(input.ban_duration_days) {
+        Some(expires_at) => expires_at,
+        None => return HttpResponse::BadRequest().json(invalid_ban_duration()),
+    };
+
+    // Range bans are placed around the address of the user's latest post.
+    let range = match input.ban_range {
+        Some(ban_range) => match Post::last_ip_by_user(target_user.id, &conn_pool).await {
+            Ok(Some(ip_address)) => match range_ban(&ip_address, ban_range) {
+                Some(range) => Some(range),
+                None => return HttpResponse::BadRequest().json(UserError {
+                    error: "Käyttäjän IP-osoite on virheellinen!".to_owned(),
+                }),
+            },
+            Ok(None) => return HttpResponse::BadRequest().json(UserError {
+                error: "Käyttäjän IP-osoitetta ei tunneta!".to_owned(),
+            }),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        None => None,
+    };
 
     let ban_model = BanModel {
         moderator_id: user_data.id,
@@ -702,10 +812,247 @@ pub async fn ban_user_by_id(
         reason: Some(&input.reason),
         ip_address: "",
         expires_at,
+        ip_range: range.as_ref().map(|range| range.cidr.as_str()),
+        range_start: range.as_ref().map(|range| range.start.clone()),
+        range_end: range.as_ref().map(|range| range.end.clone()),
+    };
+
+    let res = transaction(&conn_pool, |conn| {
+        moderation::ban(conn, &ban_model, None).scope_boxed()
+    })
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UserRoleInput {
+    pub role_id: u32,
+    /// Board the role is limited to, all boards if not given.
+    pub board_id: Option<u32>,
+}
+
+pub async fn handle_user_role_creation(
+    path: web::Path<u64>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    input: web::Json<UserRoleInput>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    if let Err(e) = perms.require(Capability::ManageUsers, None) {
+        return e.error_response();
+    }
+
+    let target_user = match User::by_id(user_id, &conn_pool).await {
+        Ok(target_user) => target_user,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Err(e) = require_above(perms.user.access_level, target_user.access_level) {
+        return e.error_response();
+    }
+
+    let role = match Role::by_id(input.role_id, &conn_pool).await {
+        Ok(role) => role,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::BadRequest().json(UserError {
+            error: "Tuntematon rooli!".to_owned(),
+        }),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    match ban_model.insert(&conn_pool).await {
+    let capabilities = match Role::capabilities(role.id, &conn_pool).await {
+        Ok(capabilities) => capabilities,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // A role can only give what the granter may do on the same boards.
+    // Capabilities unknown to this version grant nothing, so they're skipped.
+    let granted = capabilities
+    .iter()
+    .filter_map(|capability| capability.parse::<Capability>().ok())
+    .all(|capability| perms.can(capability, input.board_id));
+
+    if !granted {
+        return PermissionError::Forbidden.error_response();
+    }
+
+    if let Some(board_id) = input.board_id {
+        match Board::by_id(board_id, &conn_pool).await {
+            Ok(_) => (),
+            Err(diesel::result::Error::NotFound) => return HttpResponse::BadRequest().json(UserError {
+                error: "Tuntematon lauta!".to_owned(),
+            }),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    }
+
+    let user_role = UserRoleModel {
+        user_id: target_user.id,
+        role_id: input.role_id,
+        board_id: input.board_id,
+    };
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        user_role.insert_conn(conn).await?;
+
+        ModActionModel {
+            item_id: Some(user_role.role_id.into()),
+            target_id: Some(user_role.user_id),
+            board_id: user_role.board_id,
+            details: Some(&role.name),
+            ..ModActionModel::new(moderator_id, ModActionKind::AssignRole)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => HttpResponse::Created().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
+}
+
+pub async fn handle_user_role_deletion(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let user_role_id = path.into_inner();
+
+    if let Err(e) = perms.require(Capability::ManageUsers, None) {
+        return e.error_response();
+    }
+
+    let user_role = match UserRole::by_id(user_role_id, &conn_pool).await {
+        Ok(user_role) => user_role,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let target_user = match User::by_id(user_role.user_id, &conn_pool).await {
+        Ok(target_user) => target_user,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Err(e) = require_above(perms.user.access_level, target_user.access_level) {
+        return e.error_response();
+    }
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        UserRole::delete_conn(conn, user_role.id).await?;
+
+        ModActionModel {
+            item_id: Some(user_role.role_id.into()),
+            target_id: Some(user_role.user_id),
+            board_id: user_role.board_id,
+            ..ModActionModel::new(moderator_id, ModActionKind::RemoveRole)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModLogRequest {
+    moderator: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+pub async fn mod_log(
+    path: web::Path<u32>,
+    info: web::Query<ModLogRequest>,
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> actix_web::Result<HttpResponse> {
+    let page = path.into_inner().max(1);
+
+    let user_data = match resolve_user(user, req, &conn_pool).await {
+        Ok(usr_data) => usr_data,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
+        let mut ban_post: Option<Post> = None;
+
+        if let Some(post_id) = user_data.banned.clone().unwrap().post_id {
+            match Post::by_id(post_id, &conn_pool).await {
+                Ok(post) => ban_post = Some(post),
+                Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+            };
+        }
+
+        return banned_view::render(BannedTemplate {
+            ban: user_data.banned.unwrap(),
+            post: ban_post,
+        })
+        .await;
+    }
+
+    if user_data.access_level < AccessLevel::Moderator as u8 {
+        return forbidden_view::render(ForbiddenTemplate {
+            required_access_level: AccessLevel::Moderator as u8,
+        })
+        .await;
+    }
+
+    // The end date is inclusive, like in the archive.
+    let filter = ModActionFilter {
+        moderator_id: info.moderator.as_deref().and_then(|id| id.trim().parse().ok()),
+        action: info.action.clone().filter(|action| !action.is_empty()),
+        target_id: info.target.as_deref().and_then(|id| id.trim().parse().ok()),
+        from: parse_date(&info.from),
+        to: parse_date(&info.to).and_then(|to| to.checked_add_days(Days::new(1))),
+    };
+
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(boards) => boards,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let pages = match ModAction::count_actions(&conn_pool, &filter).await {
+        Ok(count) => {
+            let count = u64::try_from(count).unwrap();
+            count.div_ceil(20)
+        },
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let offset = (page - 1) * 20;
+
+    let actions = match ModAction::load_actions(&conn_pool, &filter, 20, offset.into()).await {
+        Ok(actions) => actions,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    mod_log_view::render(ModLogTemplate {
+        access_level: user_data.access_level,
+        boards,
+        actions,
+        pages,
+        moderator: info.moderator.clone().unwrap_or_default(),
+        action: info.action.clone().unwrap_or_default(),
+        target: info.target.clone().unwrap_or_default(),
+        from: info.from.clone().unwrap_or_default(),
+        to: info.to.clone().unwrap_or_default(),
+    }).await
 }
\ No newline at end of file
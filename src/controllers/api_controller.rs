@@ -0,0 +1,170 @@
+use actix_identity::Identity;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+
+use crate::{models::{boards::Board, error::UserError, posts::Post, threads::Thread, users::{AccessLevel, UserData}}, services::authentication::resolve_user};
+
+
+fn banned_response() -> HttpResponse {
+    HttpResponse::Forbidden().json(UserError {
+        error: "Käyttäjätilisi on bannattu!".to_owned(),
+    })
+}
+
+fn is_banned(user_data: &UserData) -> bool {
+    user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8
+}
+
+pub async fn boards(
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let user_data = match resolve_user(user, req, &conn_pool).await {
+        Ok(usr_data) => usr_data,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if is_banned(&user_data) {
+        return banned_response();
+    }
+
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(boards) => boards,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let boards: Vec<Board> = boards.into_iter()
+    .filter(|board| board.access_level <= user_data.access_level)
+    .collect();
+
+    HttpResponse::Ok().json(boards)
+}
+
+pub async fn catalog(
+    path: web::Path<String>,
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let user_data = match resolve_user(user, req, &conn_pool).await {
+        Ok(usr_data) => usr_data,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if is_banned(&user_data) {
+        return banned_response();
+    }
+
+    let handle = path.into_inner();
+
+    let board = match Board::by_handle(&conn_pool, &handle).await {
+        Ok(board) => board,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    if board.access_level > user_data.access_level {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    match Thread::list_threads_by_board_catalog(&conn_pool, board.id).await {
+        Ok(threads) => HttpResponse::Ok().json(threads),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn thread(
+    path: web::Path<(String, u32)>,
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let user_data = match resolve_user(user, req, &conn_pool).await {
+        Ok(usr_data) => usr_data,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if is_banned(&user_data) {
+        return banned_response();
+    }
+
+    let (handle, thread_id) = path.into_inner();
+
+    let board = match Board::by_handle(&conn_pool, &handle).await {
+        Ok(board) => board,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    if board.access_level > user_data.access_level {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let mut thread = match Thread::by_id(thread_id, &conn_pool).await {
+        Ok(thread) => thread,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    if thread.thread.board_id != board.id || thread.thread.deleted_at.is_some() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    thread.hide_deleted();
+    thread.hide_restricted(user_data.access_level);
+
+    HttpResponse::Ok().json(thread)
+}
+
+pub async fn post(
+    path: web::Path<u32>,
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let user_data = match resolve_user(user, req, &conn_pool).await {
+        Ok(usr_data) => usr_data,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if is_banned(&user_data) {
+        return banned_response();
+    }
+
+    let post_id = path.into_inner();
+
+    let post = match Post::full_post_by_id(post_id, &conn_pool).await {
+        Ok(post) => post,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let thread = match Thread::thread_by_id(post.post.thread_id, &conn_pool).await {
+        Ok(thread) => thread,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if post.post.deleted_at.is_some() || thread.deleted_at.is_some() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let board = match Board::by_id(thread.board_id, &conn_pool).await {
+        Ok(board) => board,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if board.access_level > user_data.access_level || post.post.access_level > user_data.access_level {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    HttpResponse::Ok().json(post)
+}
\ No newline at end of file
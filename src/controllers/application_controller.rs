@@ -5,12 +5,15 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::{models::{posts::Post, users::AccessLevel}, services::{applications::submit_application, authentication::resolve_user, users::register_user}, views::{application_view::{self, ApplicationTemplate}, banned_view::{self, BannedTemplate}, register_view::{self, RegisterTemplate}}};
+use crate::{config::Config, middleware::csrf::CsrfToken, models::{posts::Post, users::AccessLevel}, services::{applications::submit_application, authentication::resolve_user, users::register_user}, views::{application_view::{self, ApplicationTemplate}, banned_view::{self, BannedTemplate}, register_view::{self, RegisterTemplate}}};
 
 
-pub async fn register() -> actix_web::Result<HttpResponse> {
+pub async fn register(
+    csrf: CsrfToken,
+) -> actix_web::Result<HttpResponse> {
     register_view::render(RegisterTemplate {
         errors: vec![],
+        csrf_token: csrf.0,
     }).await
 }
 
@@ -52,6 +55,8 @@ pub async fn handle_registration(
     user: Option<Identity>,
     input: web::Form<RegisterForm>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    config: web::Data<Config>,
+    csrf: CsrfToken,
     req: HttpRequest,
 ) -> actix_web::Result<HttpResponse> {
     let user_data = match resolve_user(user, req, &conn_pool).await {
@@ -78,6 +83,7 @@ pub async fn handle_registration(
 
             let template = RegisterTemplate {
                 errors,
+                csrf_token: csrf.0,
             };
 
             return register_view::render(template).await;
@@ -89,7 +95,8 @@ pub async fn handle_registration(
         user_data.id, 
         &input.username, 
         &input.email, 
-        &input.pwd
+        &input.pwd,
+        config.security.pbkdf2_iterations,
     ).await;
 
     match result {
@@ -99,7 +106,8 @@ pub async fn handle_registration(
                 diesel::result::DatabaseErrorKind::UniqueViolation => 
                 {
                     let template = RegisterTemplate {
-                        errors: vec!["Käyttäjänimi tai sähköposti on jo olemassa!".to_string()]
+                        errors: vec!["Käyttäjänimi tai sähköposti on jo olemassa!".to_string()],
+                        csrf_token: csrf.0,
                     };
 
                     register_view::render(template).await
@@ -115,6 +123,7 @@ pub async fn application(
     user: Option<Identity>,
     req: HttpRequest,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    csrf: CsrfToken,
 ) -> actix_web::Result<HttpResponse> {
     let user_data = match resolve_user(user, req, &conn_pool).await {
         Ok(usr_data) => usr_data,
@@ -144,6 +153,7 @@ pub async fn application(
 
     application_view::render(ApplicationTemplate {
         errors: vec![],
+        csrf_token: csrf.0,
     }).await
 }
 
@@ -158,6 +168,7 @@ pub async fn handle_application(
     user: Option<Identity>,
     input: web::Form<ApplicationForm>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    csrf: CsrfToken,
     req: HttpRequest,
 ) -> actix_web::Result<HttpResponse> {
     let user_data = match resolve_user(user, req, &conn_pool).await {
@@ -186,7 +197,8 @@ pub async fn handle_application(
         Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/")).finish()),
         Err(_) => {
             let template = ApplicationTemplate {
-                errors: vec!["Server error.".to_string()]
+                errors: vec!["Server error.".to_string()],
+                csrf_token: csrf.0,
             };
     
             application_view::render(template).await
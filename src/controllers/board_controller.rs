@@ -1,22 +1,18 @@
-use std::fs::remove_file;
-
 use actix_identity::Identity;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use chrono::{Days, NaiveDate, NaiveDateTime};
+use diesel_async::{pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncMysqlConnection};
+use serde::Deserialize;
 
-use crate::{models::{boards::Board, posts::Post, threads::Thread, users::AccessLevel}, services::authentication::resolve_user, views::{banned_view::{self, BannedTemplate}, board_view::{self, BoardTemplate}, forbidden_view::{self, ForbiddenTemplate}, not_found_view}};
+use crate::{models::{boards::Board, error::UserError, mod_actions::{snapshot_posts, ModActionKind, ModActionModel}, posts::Post, threads::{ArchiveFilter, Thread}, users::AccessLevel}, services::{authentication::resolve_user, files::remove_attachment_files, moderation::transaction, permissions::{Capability, Permissions}, posts::{resolve_quotes, resolve_signatures}}, views::{archive_view::{self, ArchiveTemplate}, banned_view::{self, BannedTemplate}, board_view::{self, BoardTemplate}, forbidden_view::{self, ForbiddenTemplate}, not_found_view}};
 
 
 pub async fn board(
     path: web::Path<String>,
-    user: Option<Identity>,
-    req: HttpRequest,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
 ) -> actix_web::Result<HttpResponse> {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let user_data = &perms.user;
 
     if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
         let mut ban_post: Option<Post> = None;
@@ -29,7 +25,7 @@ pub async fn board(
         }
 
         return banned_view::render(BannedTemplate {
-            ban: user_data.banned.unwrap(),
+            ban: user_data.banned.clone().unwrap(),
             post: ban_post,
         })
         .await;
@@ -64,68 +60,271 @@ pub async fn board(
         Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
     };
 
+    let quotes = match resolve_quotes(
+        &conn_pool,
+        threads.iter().map(|thread| thread.op_post.message.as_str()),
+        &boards,
+        user_data.access_level,
+    ).await {
+        Ok(quotes) => quotes,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let signatures = match resolve_signatures(
+        &conn_pool,
+        threads.iter().filter(|thread| thread.op_post.show_username).map(|thread| thread.user_id),
+    ).await {
+        Ok(signatures) => signatures,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
     board_view::render(BoardTemplate {
         access_level: user_data.access_level,
         user_id: user_data.id,
+        tools: perms.board_tools(current_board.id),
         handle,
         boards,
         current_board,
         threads,
+        quotes,
+        signatures,
     }).await
 }
 
 pub async fn delete_board(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> impl Responder {
     let board_id = path.into_inner();
 
+    if let Err(e) = perms.require(Capability::ManageBoards, Some(board_id)) {
+        return e.error_response();
+    }
+
+    let board = match Board::by_id(board_id, &conn_pool).await {
+        Ok(board) => board,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let catalog = match Board::list_all_threads_and_posts(&conn_pool, board_id).await {
+        Ok(catalog) => catalog,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // The board is gone, so it is only named in the details.
+    let details = format!("/{}/ - {} ({} lankaa)", board.handle, board.title, catalog.len());
+
+    let snapshot = snapshot_posts(catalog.iter().flat_map(|thread| &thread.posts));
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Board::delete_board_conn(conn, board_id).await?;
+
+        ModActionModel {
+            item_id: Some(board_id.into()),
+            details: Some(&details),
+            snapshot: Some(&snapshot),
+            ..ModActionModel::new(moderator_id, ModActionKind::DeleteBoard)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    if res.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Files go only once the board is gone for good.
+    catalog.iter()
+    .flat_map(|thread| &thread.posts)
+    .filter_map(|post| post.attachment.as_ref())
+    .for_each(remove_attachment_files);
+
+    HttpResponse::Found().finish()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveRequest {
+    page: Option<u32>,
+    title: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+pub fn parse_date(date: &Option<String>) -> Option<NaiveDateTime> {
+    date.as_ref()
+    .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    .and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+pub async fn archive(
+    path: web::Path<String>,
+    info: web::Query<ArchiveRequest>,
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> actix_web::Result<HttpResponse> {
     let user_data = match resolve_user(user, req, &conn_pool).await {
         Ok(usr_data) => usr_data,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
     };
 
     if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+        let mut ban_post: Option<Post> = None;
+
+        if let Some(post_id) = user_data.banned.clone().unwrap().post_id {
+            match Post::by_id(post_id, &conn_pool).await {
+                Ok(post) => ban_post = Some(post),
+                Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+            };
+        }
+
+        return banned_view::render(BannedTemplate {
+            ban: user_data.banned.unwrap(),
+            post: ban_post,
+        })
+        .await;
     }
 
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return HttpResponse::Forbidden().finish();
+    let handle = path.into_inner();
+
+    let current_board = match Board::by_handle(&conn_pool, &handle).await {
+        Ok(board) => board,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => {
+                return not_found_view::render().await;
+            },
+            _ => return Ok(HttpResponse::InternalServerError().finish()),
+        },
+    };
+
+    if current_board.access_level > user_data.access_level {
+        return forbidden_view::render(ForbiddenTemplate {
+            required_access_level: current_board.access_level,
+        })
+        .await;
     }
 
-    let catalog = match Board::list_all_threads_and_posts(&conn_pool, board_id).await {
-        Ok(catalog) => catalog,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(board) => board,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let page = info.page.unwrap_or(1).max(1);
+
+    // The end date is inclusive, so threads bumped during that day are included.
+    let filter = ArchiveFilter {
+        title: info.title.clone().filter(|title| !title.trim().is_empty()),
+        from: parse_date(&info.from),
+        to: parse_date(&info.to).and_then(|to| to.checked_add_days(Days::new(1))),
+    };
+
+    let pages = match Thread::count_archived(&conn_pool, current_board.id, &filter).await {
+        Ok(count) => {
+            let count = u64::try_from(count).unwrap();
+            count.div_ceil(20)
+        },
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let offset = (page - 1) * 20;
+
+    let threads = match Thread::list_archived(&conn_pool, current_board.id, &filter, 20, offset.into()).await {
+        Ok(threads) => threads,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let quotes = match resolve_quotes(
+        &conn_pool,
+        threads.iter().map(|archived| archived.op_post.post.message.as_str()),
+        &boards,
+        user_data.access_level,
+    ).await {
+        Ok(quotes) => quotes,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    archive_view::render(ArchiveTemplate {
+        access_level: user_data.access_level,
+        handle,
+        boards,
+        current_board,
+        threads,
+        quotes,
+        pages,
+        title: info.title.clone().unwrap_or_default(),
+        from: info.from.clone().unwrap_or_default(),
+        to: info.to.clone().unwrap_or_default(),
+    }).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurgeArchiveInput {
+    pub before: Option<String>,
+}
+
+pub async fn purge_archive(
+    path: web::Path<String>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    input: web::Json<PurgeArchiveInput>,
+) -> impl Responder {
+    let before = match input.before.as_deref() {
+        Some("") | None => None,
+        Some(_) => match parse_date(&input.before) {
+            Some(before) => Some(before),
+            None => return HttpResponse::BadRequest().json(UserError {
+                error: "Virheellinen päivämäärä!".to_owned(),
+            }),
+        },
     };
 
-    // Delete files
-    catalog.iter().for_each(|thread| {
-        thread.posts.iter().for_each(|post| {
-            if let Some(attachment) = &post.attachment {
-                let file_location = format!("{}/{}", &attachment.file_location, &attachment.file_name);
-                let thumbnail_location = format!("{}/{}", &attachment.thumbnail_location, &attachment.file_name);
-        
-                match remove_file(file_location) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        println!("Error while removing file: {:?}", e);
-                    },
-                };
-        
-                match remove_file(thumbnail_location) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        println!("Error while removing file: {:?}", e);
-                    },
-                };
-            }
-        });
-    });
-
-    match Board::delete_board(&conn_pool, board_id).await {
-        Ok(_) => HttpResponse::Found().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    let board = match Board::by_handle(&conn_pool, &path.into_inner()).await {
+        Ok(board) => board,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    if let Err(e) = perms.require(Capability::ManageBoards, Some(board.id)) {
+        return e.error_response();
     }
+
+    let details = match before {
+        Some(before) => format!("ennen {}", before.date()),
+        None => "kaikki".to_owned(),
+    };
+
+    let board_id = board.id;
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        let attachments = Thread::purge_archived_conn(conn, board_id, before).await?;
+
+        ModActionModel {
+            item_id: Some(board_id.into()),
+            board_id: Some(board_id),
+            details: Some(&details),
+            ..ModActionModel::new(moderator_id, ModActionKind::PurgeArchive)
+        }
+        .insert_conn(conn)
+        .await?;
+
+        Ok(attachments)
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(attachments) => attachments.iter().for_each(remove_attachment_files),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    HttpResponse::Created().finish()
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use captcha::gen;
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
 use serde::Serialize;
 
-use crate::{models::users::AccessLevel, services::{authentication::resolve_user, captchas::new_captcha}};
+use crate::{config::Config, models::users::AccessLevel, services::{authentication::resolve_user, captchas::new_captcha}};
 
 
 #[derive(Debug, Serialize)]
@@ -16,6 +16,7 @@ pub struct CaptchaOutput {
 pub async fn captcha(
     user: Option<Identity>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    config: web::Data<Config>,
     req: HttpRequest,
 ) -> impl Responder {
     let user_data = match resolve_user(user, req, &conn_pool).await {
@@ -30,7 +31,7 @@ pub async fn captcha(
     let captcha = gen(captcha::Difficulty::Medium);
     let ans = captcha.chars_as_string();
 
-    let captcha_info = match new_captcha(&conn_pool, ans).await {
+    let captcha_info = match new_captcha(&conn_pool, ans, config.captcha.expiry_minutes).await {
         Ok(captcha) => captcha,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
@@ -1,10 +1,10 @@
 use actix_identity::Identity;
-use actix_web::{web, Error, HttpRequest, HttpResponse, Responder};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Responder, ResponseError};
+use diesel_async::{pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncMysqlConnection};
 use serde::Deserialize;
 use tokio::task::spawn_local;
 
-use crate::{chat::{handler, server::ChatServerHandle}, models::{boards::Board, chat_rooms::{ChatRoom, ChatRoomModel}, posts::Post, users::{AccessLevel, User}}, services::authentication::resolve_user, views::{banned_view::{self, BannedTemplate}, chat_view::{self, ChatTemplate}}};
+use crate::{chat::{handler, server::ChatServerHandle}, config::Config, models::{boards::Board, chat_moderation::ChatModAction, chat_rooms::{ChatRoom, ChatRoomModel}, mod_actions::{ModActionKind, ModActionModel}, posts::Post, users::{AccessLevel, User}}, services::{authentication::resolve_user, moderation::transaction, permissions::{authorize_area, Capability, Permissions}}, views::{banned_view::{self, BannedTemplate}, chat_log_view::{self, ChatLogTemplate}, chat_view::{self, ChatTemplate}, forbidden_view::{self, ForbiddenTemplate}}};
 
 
 pub async fn chat_ws(
@@ -13,6 +13,7 @@ pub async fn chat_ws(
     stream: web::Payload,
     chat_server: web::Data<ChatServerHandle>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    config: web::Data<Config>,
 ) -> Result<HttpResponse, Error> {
     let (res, session, msg_stream) = actix_ws::handle(&req, stream)?;
 
@@ -31,8 +32,11 @@ pub async fn chat_ws(
     };
     
     spawn_local(handler::chat_ws(
+        user_data.id,
         user.username.unwrap_or(format!("anonyymi-{}", user_data.id)),
         user_data.access_level,
+        user_data.ip_addr,
+        config.chat.max_message_bytes,
         (**chat_server).clone(),
         session,
         msg_stream,
@@ -86,60 +90,219 @@ pub struct ChatInput {
 }
 
 pub async fn create_chat_room(
-    user: Option<Identity>,
+    perms: Permissions,
+    chat_server: web::Data<ChatServerHandle>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     input: web::Json<ChatInput>,
-    req: HttpRequest,
 ) -> impl Responder {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    if let Err(e) = perms.require(Capability::ManageChats, None) {
+        return e.error_response();
+    }
+
+    let access_level = match authorize_area(perms.user.access_level, input.access_level) {
+        Ok(access_level) => access_level,
+        Err(e) => return e.error_response(),
+    };
+
+    let model = ChatRoomModel {
+        name: &input.name,
+        access_level: access_level as u8,
+    };
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        let chat_room = model.insert_conn(conn).await?;
+
+        ModActionModel {
+            item_id: Some(chat_room.id.into()),
+            details: Some(&chat_room.name),
+            ..ModActionModel::new(moderator_id, ModActionKind::CreateChatRoom)
+        }
+        .insert_conn(conn)
+        .await?;
+
+        Ok(chat_room)
+    }.scope_boxed())
+    .await;
+
+    let chat_room = match res {
+        Ok(chat_room) => chat_room,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
-    
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return HttpResponse::Forbidden().finish();
+    // Sessions only hear of the room once it is committed.
+    chat_server.add_room(chat_room).await;
+
+    HttpResponse::Created().finish()
+}
+
+pub async fn edit_chat_room(
+    path: web::Path<u32>,
+    perms: Permissions,
+    chat_server: web::Data<ChatServerHandle>,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    input: web::Json<ChatInput>,
+) -> impl Responder {
+    if let Err(e) = perms.require(Capability::ManageChats, None) {
+        return e.error_response();
     }
 
-    let chat_room = ChatRoomModel {
+    let access_level = match authorize_area(perms.user.access_level, input.access_level) {
+        Ok(access_level) => access_level,
+        Err(e) => return e.error_response(),
+    };
+
+    let id = path.into_inner();
+
+    let old_room = match ChatRoom::by_id(id, &conn_pool).await {
+        Ok(chat_room) => chat_room,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let model = ChatRoomModel {
         name: &input.name,
-        access_level: input.access_level,
-    }
-    .insert(&conn_pool)
+        access_level: access_level as u8,
+    };
+
+    let details = format!("{} -> {}", old_room.name, input.name);
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        let chat_room = ChatRoom::update_chat_room_conn(conn, id, model).await?;
+
+        ModActionModel {
+            item_id: Some(id.into()),
+            details: Some(&details),
+            ..ModActionModel::new(moderator_id, ModActionKind::EditChatRoom)
+        }
+        .insert_conn(conn)
+        .await?;
+
+        Ok(chat_room)
+    }.scope_boxed())
     .await;
 
-    match chat_room {
-        Ok(_) => HttpResponse::Created().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    let chat_room = match res {
+        Ok(chat_room) => chat_room,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if old_room.name != chat_room.name {
+        chat_server.rename_room(id, chat_room.name).await;
+    }
+
+    if old_room.access_level != chat_room.access_level {
+        chat_server.change_room_access(id, chat_room.access_level).await;
     }
+
+    HttpResponse::Created().finish()
 }
 
 pub async fn delete_chat_room(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
+    chat_server: web::Data<ChatServerHandle>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> impl Responder {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    if let Err(e) = perms.require(Capability::ManageChats, None) {
+        return e.error_response();
+    }
+
+    let id = path.into_inner();
+
+    let chat_room = match ChatRoom::by_id(id, &conn_pool).await {
+        Ok(chat_room) => chat_room,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
     };
 
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        ChatRoom::delete_chat_room_conn(conn, id).await?;
+
+        ModActionModel {
+            item_id: Some(id.into()),
+            details: Some(&chat_room.name),
+            ..ModActionModel::new(moderator_id, ModActionKind::DeleteChatRoom)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    if res.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    chat_server.remove_room(id).await;
+
+    HttpResponse::Created().finish()
+}
+
+pub async fn chat_log(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> actix_web::Result<HttpResponse> {
+    let page = path.into_inner().max(1);
+
+    let user_data = &perms.user;
+
     if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+        let mut ban_post: Option<Post> = None;
+
+        if let Some(post_id) = user_data.banned.as_ref().unwrap().post_id {
+            match Post::by_id(post_id, &conn_pool).await {
+                Ok(post) => ban_post = Some(post),
+                Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+            };
+        }
+
+        return banned_view::render(BannedTemplate {
+            ban: user_data.banned.clone().unwrap(),
+            post: ban_post,
+        })
+        .await;
     }
-    
-    if user_data.access_level < AccessLevel::Admin as u8 {
-        return HttpResponse::Forbidden().finish();
+
+    if perms.require(Capability::ManageChats, None).is_err() {
+        return forbidden_view::render(ForbiddenTemplate {
+            required_access_level: AccessLevel::Admin as u8,
+        })
+        .await;
     }
 
-    let id = path.into_inner();
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(boards) => boards,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
 
-    match ChatRoom::delete_chat_room(&conn_pool, id).await {
-        Ok(_) => HttpResponse::Created().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
-    }
+    let pages = match ChatModAction::count_actions(&conn_pool).await {
+        Ok(count) => {
+            let count = u64::try_from(count).unwrap();
+            count.div_ceil(20)
+        },
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let offset = (page - 1) * 20;
+
+    let actions = match ChatModAction::load_actions(&conn_pool, 20, offset.into()).await {
+        Ok(actions) => actions,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    chat_log_view::render(ChatLogTemplate {
+        access_level: user_data.access_level,
+        boards,
+        actions,
+        pages,
+    }).await
 }
\ No newline at end of file
@@ -1,21 +1,18 @@
-use std::fs::remove_file;
-
 use actix_identity::Identity;
 use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use chrono::{Duration, NaiveDateTime, Utc};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use diesel_async::{pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncMysqlConnection};
 use serde::{Deserialize, Serialize};
 
-use crate::{models::{bans::BanModel, boards::Board, error::UserError, posts::Post, reports::ReportModel, threads::Thread, users::{AccessLevel, User}}, services::{authentication::resolve_user, captchas::verify_captcha, files::display_filesize, posts::create_post_by_thread_id, time::fi_datetime}};
+use crate::{config::Config, models::{bans::BanModel, boards::Board, error::UserError, mod_actions::{ModActionKind, ModActionModel}, posts::Post, reports::ReportModel, threads::Thread, users::{AccessLevel, User}}, services::{authentication::resolve_user, captchas::verify_captcha, files::display_filesize, geoip::GeoIp, ip::{range_ban, BanRange}, markup::format_message, moderation::{self, ban_expiry, invalid_ban_duration, transaction}, permissions::{authorize_ban, Capability, Permissions}, posts::{create_post_by_thread_id, resolve_quotes, NewPost, PostError, PostOptions}, time::fi_datetime}};
 
 
 #[derive(Debug, MultipartForm)]
 pub struct PostForm {
     pub message: Text<String>,
+    pub options: Option<Text<String>>,
     pub captcha: Option<Text<String>>,
     pub captcha_id: Option<Text<u64>>,
-    #[multipart(limit = "5MB")]
     pub attachment: TempFile,
 }
 
@@ -24,6 +21,8 @@ pub async fn handle_post_creation(
     user: Option<Identity>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     MultipartForm(input): MultipartForm<PostForm>,
+    config: web::Data<Config>,
+    geoip: web::Data<GeoIp>,
     req: HttpRequest,
 ) -> impl Responder {
     if input.message.is_empty() {
@@ -32,9 +31,15 @@ pub async fn handle_post_creation(
         });
     }
 
-    if input.message.len() > 20_000 {
+    if input.message.len() > config.posts.max_message_length {
         return HttpResponse::Forbidden().json(UserError {
-            error: "Viesti on liian pitkä (yli 20 000 merkkiä)".to_owned(),
+            error: format!("Viesti on liian pitkä (yli {} merkkiä)", config.posts.max_message_length),
+        });
+    }
+
+    if input.attachment.size > config.posts.max_attachment_bytes {
+        return HttpResponse::PayloadTooLarge().json(UserError {
+            error: format!("Tiedosto on liian suuri (yli {})", display_filesize(config.posts.max_attachment_bytes as u64)),
         });
     }
 
@@ -76,6 +81,10 @@ pub async fn handle_post_creation(
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
+    if current_thread.deleted_at.is_some() {
+        return HttpResponse::NotFound().finish();
+    }
+
     if current_thread.archived {
         return HttpResponse::Forbidden().json(UserError {
             error: "Lanka on arkistoitu, eikä siihen voi enää vastata!".to_owned(),
@@ -109,17 +118,28 @@ pub async fn handle_post_creation(
         }
     }
 
+    let post = NewPost {
+        user_id: user_data.id,
+        user_access_level: user_data.access_level,
+        board_id: current_board.id,
+        access_level: current_board.access_level,
+        message: input.message.to_string(),
+        ip_address: user_data.ip_addr,
+        attachment: input.attachment,
+        options: input.options.as_deref().map(|options| PostOptions::parse(options)).unwrap_or_default(),
+        geoip: current_board.flags.then(|| geoip.get_ref()),
+    };
+
     match create_post_by_thread_id(
         &conn_pool,
-        user_data.id, 
-        thread_id, 
-        input.message.to_string(),
-        user_data.ip_addr,
-        input.attachment,
-        current_board.access_level
+        thread_id,
+        post,
+        config.posts.thumbnail_size,
+        &config.flood,
     ).await {
         Ok(_) => HttpResponse::Created().finish(),
-        Err(e) => {
+        Err(PostError::Flood(e)) => HttpResponse::TooManyRequests().json(e),
+        Err(PostError::Database(e)) => {
             match e {
                 diesel::result::Error::DatabaseError(database_error_kind, _) => match database_error_kind {
                     diesel::result::DatabaseErrorKind::ForeignKeyViolation => return HttpResponse::Forbidden().json(UserError {
@@ -178,6 +198,7 @@ pub struct FullPostOutput {
     post_id: u32,
     post_date: String,
     message: String,
+    message_html: String,
     attachment: bool,
     file_type: Option<String>,
     file_name: Option<String>,
@@ -214,6 +235,15 @@ pub async fn get_post_by_id(
         return HttpResponse::Forbidden().finish();
     }
 
+    let thread = match Thread::thread_by_id(post.post.thread_id, &conn_pool).await {
+        Ok(thread) => thread,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if post.post.deleted_at.is_some() || thread.deleted_at.is_some() {
+        return HttpResponse::NotFound().finish();
+    }
+
     let mut file_name: Option<String> = None;
     let mut file_info: Option<String> = None;
     let mut file_type: Option<String> = None;
@@ -223,10 +253,21 @@ pub async fn get_post_by_id(
         file_type = Some(attachment.file_type.clone());
         file_info = Some(format!("({},{}x{})", display_filesize(attachment.file_size_bytes), attachment.width, attachment.height));
     }
+
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(boards) => boards,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let quotes = match resolve_quotes(&conn_pool, [post.post.message.as_str()], &boards, user_data.access_level).await {
+        Ok(quotes) => quotes,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
     
     HttpResponse::Ok().json(FullPostOutput {
         post_id: post.post.id,
         post_date: fi_datetime(post.post.created_at),
+        message_html: format_message(&post.post.message, &quotes),
         message: post.post.message,
         attachment: post.attachment.is_some(),
         file_type,
@@ -238,16 +279,11 @@ pub async fn get_post_by_id(
 
 pub async fn delete_post(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> impl Responder {
     let post_id = path.into_inner();
-
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    let user_data = &perms.user;
 
     let post_wrapper = match Post::full_post_by_id(post_id, &conn_pool).await {
         Ok(post) => post,
@@ -258,8 +294,18 @@ pub async fn delete_post(
         return HttpResponse::Forbidden().finish();
     }
 
-    if user_data.access_level < AccessLevel::Moderator as u8 && user_data.id != post_wrapper.post.user_id {
-        return HttpResponse::Forbidden().finish();
+    let board_id = match user_data.id != post_wrapper.post.user_id {
+        true => match Thread::board_id(post_wrapper.post.thread_id, &conn_pool).await {
+            Ok(board_id) => Some(board_id),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        false => None,
+    };
+
+    if board_id.is_some() {
+        if let Err(e) = perms.require(Capability::DeletePost, board_id) {
+            return e.error_response();
+        }
     }
 
     let thread_op_post = match Thread::get_op_post(&conn_pool, post_wrapper.post.thread_id).await {
@@ -271,70 +317,149 @@ pub async fn delete_post(
         return HttpResponse::Forbidden().finish();
     }
 
-    if let Some(attachment) = &post_wrapper.attachment {
-        let file_location = format!("{}/{}", &attachment.file_location, &attachment.file_name);
-        let thumbnail_location = format!("{}/{}", &attachment.thumbnail_location, &attachment.file_name);
-
-        match remove_file(file_location) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error while removing file: {:?}", e);
-            },
-        };
-
-        match remove_file(thumbnail_location) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error while removing file: {:?}", e);
-            },
-        };
-    }
+    let deleted_by = user_data.id;
+
+    // The attachment is removed when the post is purged.
+    let res = transaction(&conn_pool, |conn| {
+        moderation::delete_post(conn, &post_wrapper, board_id, deleted_by).scope_boxed()
+    })
+    .await;
 
-    match Post::delete_post(&conn_pool, post_wrapper.post.id).await {
+    match res {
         Ok(_) => HttpResponse::Created().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
+pub async fn restore_post(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let post_id = path.into_inner();
+
+    let post = match Post::by_id(post_id, &conn_pool).await {
+        Ok(post) => post,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let board_id = match Thread::board_id(post.thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Err(e) = perms.require(Capability::DeletePost, Some(board_id)) {
+        return e.error_response();
+    }
+
+    if post.deleted_at.is_none() {
+        return HttpResponse::Conflict().json(UserError {
+            error: "Viestiä ei ole poistettu!".to_owned(),
+        });
+    }
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Post::restore_post_conn(conn, post.id).await?;
+
+        ModActionModel {
+            item_id: Some(post.id.into()),
+            target_id: Some(post.user_id),
+            board_id: Some(board_id),
+            ..ModActionModel::new(moderator_id, ModActionKind::RestorePost)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct BanUserInput {
     pub ban_duration_days: i64,
     pub reason: String,
+    #[serde(default)]
+    pub ban_range: Option<BanRange>,
+    /// Public note attached to the post, e.g. "(USER WAS BANNED FOR THIS POST)".
+    #[serde(default)]
+    pub mod_note: Option<String>,
+}
+
+/// Longest accepted moderator note in characters.
+const MAX_NOTE_LENGTH: usize = 500;
+
+/// Trims a note and treats an empty one as no note.
+fn parse_note(note: &Option<String>) -> Result<Option<&str>, UserError> {
+    let note = note.as_deref().map(str::trim).filter(|note| !note.is_empty());
+
+    match note {
+        Some(note) if note.chars().count() > MAX_NOTE_LENGTH => Err(UserError {
+            error: format!("Merkintä voi olla enintään {} merkkiä pitkä!", MAX_NOTE_LENGTH),
+        }),
+        _ => Ok(note),
+    }
 }
 
 pub async fn ban_user_by_post_id(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     input: web::Json<BanUserInput>,
-    req: HttpRequest,
 ) -> impl Responder {
     let post_id = path.into_inner();
+    let user_data = &perms.user;
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    let post_data = match Post::by_id(post_id, &conn_pool).await {
+        Ok(post_data) => post_data,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
-
-    let post_data = match Post::by_id(post_id, &conn_pool).await {
-        Ok(post_data) => post_data,
+    let board_id = match Thread::board_id(post_data.thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
+    if let Err(e) = perms.require(Capability::Ban, Some(board_id)) {
+        return e.error_response();
+    }
+
     let poster_user_data = match User::by_id(post_data.user_id, &conn_pool).await {
         Ok(poster_user_data) => poster_user_data,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.access_level < AccessLevel::Moderator as u8 || user_data.access_level <= poster_user_data.access_level {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = authorize_ban(user_data.access_level, poster_user_data.access_level) {
+        return e.error_response();
     }
 
-    let expires_at = (Utc::now() + Duration::days(input.ban_duration_days)).naive_utc();
+    let mod_note = match parse_note(&input.mod_note) {
+        Ok(mod_note) => mod_note,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    let expires_at = match ban_expiry(input.ban_duration_days) {
+        Some(expires_at) => expires_at,
+        None => return HttpResponse::BadRequest().json(invalid_ban_duration()),
+    };
+
+    let range = match input.ban_range {
+        Some(ban_range) => match range_ban(&post_data.ip_address, ban_range) {
+            Some(range) => Some(range),
+            None => return HttpResponse::BadRequest().json(UserError {
+                error: "Viestin IP-osoite on virheellinen!".to_owned(),
+            }),
+        },
+        None => None,
+    };
 
     let ban_model = BanModel {
         moderator_id: user_data.id,
@@ -343,14 +468,89 @@ pub async fn ban_user_by_post_id(
         reason: Some(&input.reason),
         ip_address: &post_data.ip_address,
         expires_at,
+        ip_range: range.as_ref().map(|range| range.cidr.as_str()),
+        range_start: range.as_ref().map(|range| range.start.clone()),
+        range_end: range.as_ref().map(|range| range.end.clone()),
     };
 
-    match ban_model.insert(&conn_pool).await {
+    let ban_model = &ban_model;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        moderation::ban(conn, ban_model, Some(board_id)).await?;
+
+        if mod_note.is_some() {
+            Post::update_mod_note_conn(conn, post_id, mod_note).await?;
+        }
+
+        Ok(())
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => HttpResponse::Created().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
+#[derive(Deserialize)]
+pub struct PostNoteInput {
+    pub note: Option<String>,
+}
+
+/// Sets or clears the private moderator annotation of a post.
+pub async fn update_private_note(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    input: web::Json<PostNoteInput>,
+) -> impl Responder {
+    let post_id = path.into_inner();
+
+    let post = match Post::by_id(post_id, &conn_pool).await {
+        Ok(post) => post,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let board_id = match Thread::board_id(post.thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Err(e) = perms.require(Capability::DeletePost, Some(board_id)) {
+        return e.error_response();
+    }
+
+    let private_note = match parse_note(&input.note) {
+        Ok(private_note) => private_note,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Post::update_private_note_conn(conn, post.id, private_note).await?;
+
+        ModActionModel {
+            item_id: Some(post.id.into()),
+            target_id: Some(post.user_id),
+            board_id: Some(board_id),
+            details: private_note,
+            ..ModActionModel::new(moderator_id, ModActionKind::PostNote)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct ReportPostInput {
     pub post_id: u32,
@@ -373,7 +573,7 @@ pub async fn report_post(
     }
 
     let report_model = ReportModel {
-        post_id: input.post_id,
+        post_id: Some(input.post_id),
         reason: &input.reason,
     };
 
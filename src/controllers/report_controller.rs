@@ -0,0 +1,324 @@
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use diesel_async::{pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncMysqlConnection};
+use serde::Deserialize;
+
+use crate::{models::{bans::BanModel, boards::Board, error::UserError, mod_actions::{ModActionKind, ModActionModel}, posts::Post, reports::Report, threads::Thread, users::{AccessLevel, User}}, services::{moderation::{self, ban, ban_expiry, invalid_ban_duration, transaction}, permissions::{authorize_ban, Capability, Permissions}, posts::resolve_quotes}, views::{banned_view::{self, BannedTemplate}, forbidden_view::{self, ForbiddenTemplate}, report_list_view::{self, ReportListTemplate}}};
+
+
+#[derive(Debug, Deserialize)]
+pub struct ReportsRequest {
+    board: Option<String>,
+    resolved: Option<bool>,
+}
+
+pub async fn reports_list(
+    path: web::Path<u32>,
+    info: web::Query<ReportsRequest>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> actix_web::Result<HttpResponse> {
+    let page = path.into_inner().max(1);
+
+    let user_data = &perms.user;
+
+    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
+        let mut ban_post: Option<Post> = None;
+
+        if let Some(post_id) = user_data.banned.as_ref().unwrap().post_id {
+            match Post::by_id(post_id, &conn_pool).await {
+                Ok(post) => ban_post = Some(post),
+                Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+            };
+        }
+
+        return banned_view::render(BannedTemplate {
+            ban: user_data.banned.clone().unwrap(),
+            post: ban_post,
+        })
+        .await;
+    }
+
+    let board_filter = info.board.clone().filter(|handle| !handle.is_empty());
+    let resolved = info.resolved.unwrap_or(false);
+
+    let board_id = match board_filter {
+        Some(ref handle) => match Board::by_handle(&conn_pool, handle).await {
+            Ok(board) => Some(board.id),
+            Err(_) => return Ok(HttpResponse::NotFound().finish()),
+        },
+        None => None,
+    };
+
+    if perms.require(Capability::HandleReports, board_id).is_err() {
+        return forbidden_view::render(ForbiddenTemplate {
+            required_access_level: AccessLevel::Moderator as u8,
+        })
+        .await;
+    }
+
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(boards) => boards,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let pages = match Report::count_reports(&conn_pool, board_id, resolved).await {
+        Ok(count) => {
+            let count = u64::try_from(count).unwrap();
+            count.div_ceil(20)
+        },
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let offset = (page - 1) * 20;
+
+    let reports = match Report::load_reports(&conn_pool, board_id, resolved, 20, offset.into()).await {
+        Ok(reports) => reports,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let quotes = match resolve_quotes(
+        &conn_pool,
+        reports.iter().filter_map(|report_view| report_view.post.as_ref()).map(|post| post.message.as_str()),
+        &boards,
+        user_data.access_level,
+    ).await {
+        Ok(quotes) => quotes,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    report_list_view::render(ReportListTemplate {
+        access_level: user_data.access_level,
+        boards,
+        reports,
+        quotes,
+        pages,
+        board_filter,
+        resolved,
+    }).await
+}
+
+pub async fn handle_report_dismiss(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let report_id = path.into_inner();
+    let user_data = &perms.user;
+
+    let report = match Report::by_id(report_id, &conn_pool).await {
+        Ok(report) => report,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    // Reports of deleted posts belong to no board.
+    let board_id = match report.post_id {
+        Some(post_id) => match report_board_id(post_id, &conn_pool).await {
+            Ok(board_id) => Some(board_id),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        None => None,
+    };
+
+    if let Err(e) = perms.require(Capability::HandleReports, board_id) {
+        return e.error_response();
+    }
+
+    let moderator_id = user_data.id;
+    let resolve_time = Utc::now().naive_utc();
+
+    let res = transaction(&conn_pool, |conn| async move {
+        if Report::resolve_conn(conn, report.id, moderator_id, resolve_time).await? == 0 {
+            return Err(diesel::result::Error::RollbackTransaction);
+        }
+
+        ModActionModel {
+            item_id: Some(report.id.into()),
+            board_id,
+            details: Some(&report.reason),
+            ..ModActionModel::new(moderator_id, ModActionKind::DismissReport)
+        }
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(diesel::result::Error::RollbackTransaction) => HttpResponse::Conflict().json(UserError {
+            error: "Ilmianto on jo käsitelty!".to_owned(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Board of a reported post.
+async fn report_board_id(
+    post_id: u32,
+    conn_pool: &Pool<AsyncMysqlConnection>,
+) -> Result<u32, diesel::result::Error> {
+    let post = Post::by_id(post_id, conn_pool).await?;
+
+    Thread::board_id(post.thread_id, conn_pool).await
+}
+
+#[derive(Deserialize)]
+pub struct ResolveReportInput {
+    pub delete_post: bool,
+    pub ban_duration_days: i64,
+    pub reason: String,
+}
+
+pub async fn handle_report_resolve(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    input: web::Json<ResolveReportInput>,
+) -> impl Responder {
+    let report_id = path.into_inner();
+    let user_data = &perms.user;
+
+    let report = match Report::by_id(report_id, &conn_pool).await {
+        Ok(report) => report,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let post_id = match report.post_id {
+        Some(post_id) => post_id,
+        None => return HttpResponse::Conflict().json(UserError {
+            error: "Ilmiannettu viesti on jo poistettu!".to_owned(),
+        }),
+    };
+
+    let post_wrapper = match Post::full_post_by_id(post_id, &conn_pool).await {
+        Ok(post) => post,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let board_id = match Thread::board_id(post_wrapper.post.thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let ban_requested = input.ban_duration_days > 0;
+
+    let expires_at = match ban_requested {
+        true => match ban_expiry(input.ban_duration_days) {
+            Some(expires_at) => Some(expires_at),
+            None => return HttpResponse::BadRequest().json(invalid_ban_duration()),
+        },
+        false => None,
+    };
+
+    let mut required = vec![Capability::HandleReports];
+
+    if input.delete_post {
+        required.push(Capability::DeletePost);
+    }
+
+    if ban_requested {
+        required.push(Capability::Ban);
+    }
+
+    for capability in required {
+        if let Err(e) = perms.require(capability, Some(board_id)) {
+            return e.error_response();
+        }
+    }
+
+    if ban_requested {
+        let poster_user_data = match User::by_id(post_wrapper.post.user_id, &conn_pool).await {
+            Ok(poster_user_data) => poster_user_data,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+
+        if let Err(e) = authorize_ban(user_data.access_level, poster_user_data.access_level) {
+            return e.error_response();
+        }
+    }
+
+    // Deleting the op post removes the whole thread.
+    let thread_wrapper = match input.delete_post {
+        true => {
+            let thread_op_post = match Thread::get_op_post(&conn_pool, post_wrapper.post.thread_id).await {
+                Ok(op_post) => op_post,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+
+            match thread_op_post.id == post_wrapper.post.id {
+                true => match Thread::by_id(post_wrapper.post.thread_id, &conn_pool).await {
+                    Ok(thread) => Some(thread),
+                    Err(_) => return HttpResponse::InternalServerError().finish(),
+                },
+                false => None,
+            }
+        },
+        false => None,
+    };
+
+    let ban_model = expires_at.map(|expires_at| BanModel {
+        moderator_id: user_data.id,
+        user_id: Some(post_wrapper.post.user_id),
+        post_id: Some(post_wrapper.post.id),
+        reason: Some(&input.reason),
+        ip_address: &post_wrapper.post.ip_address,
+        expires_at,
+        ip_range: None,
+        range_start: None,
+        range_end: None,
+    });
+
+    let moderator_id = user_data.id;
+    let delete = input.delete_post;
+    let resolve_time = Utc::now().naive_utc();
+    let (ban_model, thread_wrapper, post_wrapper, report) = (&ban_model, &thread_wrapper, &post_wrapper, &report);
+
+    let res = transaction(&conn_pool, |conn| async move {
+        if let Some(ban_model) = ban_model {
+            ban(conn, ban_model, Some(board_id)).await?;
+        }
+
+        if delete {
+            match thread_wrapper {
+                Some(thread_wrapper) => moderation::delete_thread(conn, thread_wrapper, moderator_id).await?,
+                None => moderation::delete_post(conn, post_wrapper, Some(board_id), moderator_id).await?,
+            };
+        }
+
+        // Resolving last rolls the actions back if another moderator has
+        // handled the report meanwhile.
+        if Report::resolve_conn(conn, report.id, moderator_id, resolve_time).await? == 0 {
+            return Err(diesel::result::Error::RollbackTransaction);
+        }
+
+        ModActionModel {
+            item_id: Some(report.id.into()),
+            target_id: Some(post_wrapper.post.user_id),
+            board_id: Some(board_id),
+            details: Some(&report.reason),
+            ..ModActionModel::new(moderator_id, ModActionKind::ResolveReport)
+        }
+        .insert_conn(conn)
+        .await?;
+
+        // Other open reports of the same post are handled by the same action.
+        Report::resolve_by_post_id_conn(conn, post_id, moderator_id, resolve_time).await?;
+
+        Ok(())
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(diesel::result::Error::RollbackTransaction) => HttpResponse::Conflict().json(UserError {
+            error: "Ilmianto on jo käsitelty!".to_owned(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,120 @@
+use actix_identity::Identity;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Days, NaiveDate, NaiveDateTime};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use serde::Deserialize;
+
+use crate::{models::{boards::Board, posts::{Post, SearchFilter}, users::AccessLevel}, services::{authentication::resolve_user, posts::resolve_quotes}, views::{banned_view::{self, BannedTemplate}, search_view::{self, SearchTemplate}}};
+
+
+#[derive(Debug, Deserialize)]
+pub struct SearchRequest {
+    page: Option<u32>,
+    q: Option<String>,
+    board: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    attachment: Option<String>,
+}
+
+fn parse_date(date: &Option<String>) -> Option<NaiveDateTime> {
+    date.as_ref()
+    .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    .and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+pub async fn search(
+    info: web::Query<SearchRequest>,
+    user: Option<Identity>,
+    req: HttpRequest,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> actix_web::Result<HttpResponse> {
+    let user_data = match resolve_user(user, req, &conn_pool).await {
+        Ok(usr_data) => usr_data,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
+        let mut ban_post: Option<Post> = None;
+
+        if let Some(post_id) = user_data.banned.clone().unwrap().post_id {
+            match Post::by_id(post_id, &conn_pool).await {
+                Ok(post) => ban_post = Some(post),
+                Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+            };
+        }
+
+        return banned_view::render(BannedTemplate {
+            ban: user_data.banned.unwrap(),
+            post: ban_post,
+        })
+        .await;
+    }
+
+    let boards = match Board::list_all(&conn_pool).await {
+        Ok(board) => board,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let query = info.q.clone().unwrap_or_default().trim().to_owned();
+    let board = info.board.clone().unwrap_or_default();
+    let has_attachment = info.attachment.is_some();
+
+    // Boards above the user's access level are treated as if they did not exist.
+    let board_id = boards.iter()
+    .find(|b| b.handle == board && b.access_level <= user_data.access_level)
+    .map(|b| b.id);
+
+    let page = info.page.unwrap_or(1).max(1);
+    let mut pages = 0;
+    let mut results = Vec::new();
+
+    if !query.is_empty() {
+        // The end date is inclusive, so posts made during that day are included.
+        let filter = SearchFilter {
+            query: query.clone(),
+            board_id,
+            from: parse_date(&info.from),
+            to: parse_date(&info.to).and_then(|to| to.checked_add_days(Days::new(1))),
+            has_attachment,
+        };
+
+        pages = match Post::count_search(&conn_pool, &filter, user_data.access_level).await {
+            Ok(count) => {
+                let count = u64::try_from(count).unwrap();
+                count.div_ceil(20)
+            },
+            Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+        };
+
+        let offset = (page - 1) * 20;
+
+        results = match Post::search(&conn_pool, &filter, user_data.access_level, 20, offset.into()).await {
+            Ok(results) => results,
+            Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+        };
+    }
+
+    let quotes = match resolve_quotes(
+        &conn_pool,
+        results.iter().map(|result| result.post.message.as_str()),
+        &boards,
+        user_data.access_level,
+    ).await {
+        Ok(quotes) => quotes,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    search_view::render(SearchTemplate {
+        access_level: user_data.access_level,
+        boards,
+        results,
+        quotes,
+        pages,
+        query,
+        board: board_id.map(|_| board).unwrap_or_default(),
+        from: info.from.clone().unwrap_or_default(),
+        to: info.to.clone().unwrap_or_default(),
+        has_attachment,
+    }).await
+}
\ No newline at end of file
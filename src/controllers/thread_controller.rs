@@ -1,21 +1,19 @@
-use std::fs::remove_file;
-
 use actix_identity::Identity;
 use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use diesel_async::{pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncMysqlConnection};
 use serde::Deserialize;
 
-use crate::{models::{boards::Board, error::UserError, posts::Post, threads::Thread, users::AccessLevel}, services::{authentication::resolve_user, captchas::verify_captcha, threads::create_thread}, views::{banned_view::{self, BannedTemplate}, forbidden_view::{self, ForbiddenTemplate}, not_found_view, thread_view::{self, ThreadTemplate}}};
+use crate::{config::Config, models::{boards::Board, error::UserError, mod_actions::{ModActionKind, ModActionModel}, posts::Post, threads::Thread, users::AccessLevel}, services::{authentication::resolve_user, captchas::verify_captcha, files::display_filesize, geoip::GeoIp, moderation::{self, transaction}, permissions::{Capability, Permissions}, posts::{resolve_quotes, resolve_signatures, NewPost, PostError, PostOptions}, threads::create_thread}, views::{banned_view::{self, BannedTemplate}, forbidden_view::{self, ForbiddenTemplate}, not_found_view, thread_view::{self, ThreadTemplate}}};
 
 
 #[derive(Debug, MultipartForm)]
 pub struct ThreadForm {
     pub topic: Text<String>,
     pub message: Text<String>,
+    pub options: Option<Text<String>>,
     pub captcha: Option<Text<String>>,
     pub captcha_id: Option<Text<u64>>,
-    #[multipart(limit = "5MB")]
     pub attachment: TempFile,
 }
 
@@ -24,6 +22,8 @@ pub async fn handle_thread_creation(
     user: Option<Identity>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     MultipartForm(input): MultipartForm<ThreadForm>,
+    config: web::Data<Config>,
+    geoip: web::Data<GeoIp>,
     req: HttpRequest,
 ) -> impl Responder {
     if input.message.is_empty() {
@@ -32,9 +32,15 @@ pub async fn handle_thread_creation(
         });
     }
 
-    if input.message.len() > 20_000 {
+    if input.message.len() > config.posts.max_message_length {
         return HttpResponse::Forbidden().json(UserError {
-            error: "Viesti on liian pitkä (yli 20 000 merkkiä)".to_owned(),
+            error: format!("Viesti on liian pitkä (yli {} merkkiä)", config.posts.max_message_length),
+        });
+    }
+
+    if input.attachment.size > config.posts.max_attachment_bytes {
+        return HttpResponse::PayloadTooLarge().json(UserError {
+            error: format!("Tiedosto on liian suuri (yli {})", display_filesize(config.posts.max_attachment_bytes as u64)),
         });
     }
     
@@ -81,21 +87,31 @@ pub async fn handle_thread_creation(
         }
     }
 
+    let post = NewPost {
+        user_id: user_data.id,
+        user_access_level: user_data.access_level,
+        board_id: current_board.id,
+        access_level: current_board.access_level,
+        message: input.message.to_string(),
+        ip_address: user_data.ip_addr,
+        attachment: input.attachment,
+        options: input.options.as_deref().map(|options| PostOptions::parse(options)).unwrap_or_default(),
+        geoip: current_board.flags.then(|| geoip.get_ref()),
+    };
+
     let result = create_thread(
-        &conn_pool, 
-        user_data.id, 
-        current_board.id, 
-        input.topic.to_string(), 
-        input.message.to_string(),
-        user_data.ip_addr,
-        input.attachment, 
-        current_board.access_level,
+        &conn_pool,
+        input.topic.to_string(),
+        post,
         current_board.active_threads_limit,
+        config.posts.thumbnail_size,
+        &config.flood,
     ).await;
 
     match result {
         Ok(_) => HttpResponse::Created().finish(),
-        Err(err) => match err {
+        Err(PostError::Flood(e)) => HttpResponse::TooManyRequests().json(e),
+        Err(PostError::Database(err)) => match err {
             diesel::result::Error::NotFound => HttpResponse::Forbidden().json(UserError {
                 error: "Ongelma tiedoston käsittelyssä!".to_owned(),
             }),
@@ -106,14 +122,10 @@ pub async fn handle_thread_creation(
 
 pub async fn thread(
     path: web::Path<(String, u32)>,
-    user: Option<Identity>,
-    req: HttpRequest,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
 ) -> actix_web::Result<HttpResponse> {
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
-        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
-    };
+    let user_data = &perms.user;
 
     if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
         let mut ban_post: Option<Post> = None;
@@ -126,7 +138,7 @@ pub async fn thread(
         }
 
         return banned_view::render(BannedTemplate {
-            ban: user_data.banned.unwrap(),
+            ban: user_data.banned.clone().unwrap(),
             post: ban_post,
         })
         .await;
@@ -151,7 +163,7 @@ pub async fn thread(
         .await;
     }
 
-    let thread = match Thread::by_id(thread_id, &conn_pool).await {
+    let mut thread = match Thread::by_id(thread_id, &conn_pool).await {
         Ok(thread) => thread,
         Err(e) => match e {
             diesel::result::Error::NotFound => {
@@ -161,37 +173,90 @@ pub async fn thread(
         },
     };
 
+    let tools = perms.board_tools(current_board.id);
+
+    // Deleted content is only shown to those who can restore it.
+    if !tools.delete_post {
+        if thread.thread.deleted_at.is_some() {
+            return not_found_view::render().await;
+        }
+
+        thread.hide_deleted();
+    }
+
+    let quotes = match resolve_quotes(
+        &conn_pool,
+        thread.posts.iter().map(|postdata| postdata.post.message.as_str()),
+        &boards,
+        user_data.access_level,
+    ).await {
+        Ok(quotes) => quotes,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    let signatures = match resolve_signatures(
+        &conn_pool,
+        thread.posts.iter().filter(|postdata| postdata.post.show_username).map(|postdata| postdata.post.user_id),
+    ).await {
+        Ok(signatures) => signatures,
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
     thread_view::render(ThreadTemplate {
         access_level: user_data.access_level,
         user_id: user_data.id,
+        tools,
         boards,
         current_board,
         thread,
+        quotes,
+        signatures,
     }).await
 }
 
+/// Logs a moderator action on a thread in the transaction that made it.
+async fn log_thread_action(
+    conn: &mut AsyncMysqlConnection,
+    moderator_id: u64,
+    kind: ModActionKind,
+    thread_id: u32,
+    board_id: u32,
+) -> Result<(), diesel::result::Error> {
+    ModActionModel {
+        item_id: Some(thread_id.into()),
+        board_id: Some(board_id),
+        ..ModActionModel::new(moderator_id, kind)
+    }
+    .insert_conn(conn)
+    .await
+}
+
 pub async fn handle_thread_pin(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> impl Responder {
     let thread_id = path.into_inner();
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    let board_id = match Thread::board_id(thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = perms.require(Capability::Pin, Some(board_id)) {
+        return e.error_response();
     }
 
-    if user_data.access_level < AccessLevel::Moderator as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
+    let moderator_id = perms.user.id;
 
-    match Thread::pin_thread(&conn_pool, thread_id, true).await {
+    let res = transaction(&conn_pool, |conn| async move {
+        Thread::pin_thread_conn(conn, thread_id, true).await?;
+        log_thread_action(conn, moderator_id, ModActionKind::PinThread, thread_id, board_id).await
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
@@ -199,26 +264,30 @@ pub async fn handle_thread_pin(
 
 pub async fn handle_thread_unpin(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> impl Responder {
     let thread_id = path.into_inner();
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    let board_id = match Thread::board_id(thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = perms.require(Capability::Pin, Some(board_id)) {
+        return e.error_response();
     }
 
-    if user_data.access_level < AccessLevel::Moderator as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
+    let moderator_id = perms.user.id;
 
-    match Thread::pin_thread(&conn_pool, thread_id, false).await {
+    let res = transaction(&conn_pool, |conn| async move {
+        Thread::pin_thread_conn(conn, thread_id, false).await?;
+        log_thread_action(conn, moderator_id, ModActionKind::UnpinThread, thread_id, board_id).await
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
@@ -231,82 +300,154 @@ pub struct ThreadLockInput {
 
 pub async fn handle_thread_lock(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
     input: web::Json<ThreadLockInput>,
-    req: HttpRequest,
 ) -> impl Responder {
     let thread_id = path.into_inner();
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    let board_id = match Thread::board_id(thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = perms.require(Capability::Lock, Some(board_id)) {
+        return e.error_response();
     }
 
-    if user_data.access_level < AccessLevel::Moderator as u8 {
-        return HttpResponse::Forbidden().finish();
-    }
+    let lock_status = input.lock_status;
+
+    let kind = match lock_status {
+        true => ModActionKind::LockThread,
+        false => ModActionKind::UnlockThread,
+    };
+
+    let moderator_id = perms.user.id;
 
-    match Thread::lock_thread(&conn_pool, thread_id, input.lock_status).await {
+    let res = transaction(&conn_pool, |conn| async move {
+        Thread::lock_thread_conn(conn, thread_id, lock_status).await?;
+        log_thread_action(conn, moderator_id, kind, thread_id, board_id).await
+    }.scope_boxed())
+    .await;
+
+    match res {
         Ok(_) => HttpResponse::Created().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
-pub async fn delete_thread(
+pub async fn handle_thread_unarchive(
     path: web::Path<u32>,
-    user: Option<Identity>,
+    perms: Permissions,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
-    req: HttpRequest,
 ) -> impl Responder {
     let thread_id = path.into_inner();
 
-    let user_data = match resolve_user(user, req, &conn_pool).await {
-        Ok(usr_data) => usr_data,
+    let board_id = match Thread::board_id(thread_id, &conn_pool).await {
+        Ok(board_id) => board_id,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    if user_data.banned.is_some() && user_data.access_level != AccessLevel::Root as u8 {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = perms.require(Capability::ManageBoards, Some(board_id)) {
+        return e.error_response();
+    }
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Thread::archive_thread_conn(conn, thread_id, false).await?;
+        log_thread_action(conn, moderator_id, ModActionKind::UnarchiveThread, thread_id, board_id).await
+    }.scope_boxed())
+    .await;
+
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
     }
+}
+
+pub async fn delete_thread(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let thread_id = path.into_inner();
 
     let thread_wrapper = match Thread::by_id(thread_id, &conn_pool).await {
         Ok(thread) => thread,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
     };
 
-    if user_data.access_level < AccessLevel::Moderator as u8 && user_data.id != thread_wrapper.thread.user_id {
-        return HttpResponse::Forbidden().finish();
+    // Users can delete their own threads unless banned.
+    if perms.user.id != thread_wrapper.thread.user_id || perms.user.banned.is_some() {
+        if let Err(e) = perms.require(Capability::DeletePost, Some(thread_wrapper.thread.board_id)) {
+            return e.error_response();
+        }
     }
 
-    // Delete files
-    thread_wrapper.posts.iter().for_each(|post| {
-        if let Some(attachment) = &post.attachment {
-            let file_location = format!("{}/{}", &attachment.file_location, &attachment.file_name);
-            let thumbnail_location = format!("{}/{}", &attachment.thumbnail_location, &attachment.file_name);
+    // Attachments are removed when the thread is purged.
+    let deleted_by = perms.user.id;
 
-            match remove_file(file_location) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("Error while removing file: {:?}", e);
-                },
-            };
+    let res = transaction(&conn_pool, |conn| {
+        moderation::delete_thread(conn, &thread_wrapper, deleted_by).scope_boxed()
+    })
+    .await;
 
-            match remove_file(thumbnail_location) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("Error while removing file: {:?}", e);
-                },
-            };
+    match res {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn restore_thread(
+    path: web::Path<u32>,
+    perms: Permissions,
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+) -> impl Responder {
+    let thread_id = path.into_inner();
+
+    let thread = match Thread::thread_by_id(thread_id, &conn_pool).await {
+        Ok(thread) => thread,
+        Err(e) => match e {
+            diesel::result::Error::NotFound => return HttpResponse::NotFound().finish(),
+            _ => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    if let Err(e) = perms.require(Capability::DeletePost, Some(thread.board_id)) {
+        return e.error_response();
+    }
+
+    if thread.deleted_at.is_none() {
+        return HttpResponse::Conflict().json(UserError {
+            error: "Lankaa ei ole poistettu!".to_owned(),
+        });
+    }
+
+    let moderator_id = perms.user.id;
+
+    let res = transaction(&conn_pool, |conn| async move {
+        Thread::restore_thread_conn(conn, thread_id).await?;
+
+        ModActionModel {
+            item_id: Some(thread_id.into()),
+            target_id: Some(thread.user_id),
+            board_id: Some(thread.board_id),
+            details: Some(&thread.title),
+            ..ModActionModel::new(moderator_id, ModActionKind::RestoreThread)
         }
-    });
+        .insert_conn(conn)
+        .await
+    }.scope_boxed())
+    .await;
 
-    match Thread::delete_thread(&conn_pool, thread_id).await {
-        Ok(_) => HttpResponse::Created().finish(),
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
\ No newline at end of file
@@ -1,15 +1,27 @@
+use std::sync::LazyLock;
+
 use actix_identity::Identity;
 use actix_web::{http::StatusCode, web::{self, Redirect}, HttpRequest, HttpResponse, Responder};
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use diesel::result::Error;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::{services::authentication::{login_by_email, login_by_username}, views::login_view::{self, LoginTemplate}};
+use crate::{config::Config, middleware::csrf::CsrfToken, models::users::User, services::{authentication::{login_needs_captcha, login_with_password, LoginError}, flood::display_wait, ip::client_ip}, views::login_view::{self, LoginTemplate}};
+
 
+pub async fn login(
+    conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    config: web::Data<Config>,
+    csrf: CsrfToken,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let ip_address = request_ip(&req, &config);
 
-pub async fn login() -> actix_web::Result<HttpResponse> {
     login_view::render(LoginTemplate {
         errors: vec![],
+        captcha: login_needs_captcha(&ip_address, &config.login, &conn_pool).await.unwrap_or(false),
+        csrf_token: csrf.0,
     }).await
 }
 
@@ -17,35 +29,81 @@ pub async fn login() -> actix_web::Result<HttpResponse> {
 pub struct LoginForm {
     username: String,
     pwd: String,
+    #[serde(default)]
+    captcha: Option<String>,
+    #[serde(default)]
+    captcha_id: Option<String>,
 }
 
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\.[a-zA-Z0-9-.]+$").unwrap()
+});
+
 pub async fn handle_login(
     input: web::Form<LoginForm>,
     conn_pool: web::Data<Pool<AsyncMysqlConnection>>,
+    config: web::Data<Config>,
+    csrf: CsrfToken,
     req: HttpRequest,
 ) -> actix_web::Result<HttpResponse> {
-    let email_re = Regex::new(r"^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\.[a-zA-Z0-9-.]+$").unwrap();
-    let is_email = email_re.is_match(&input.username);
-    
-    let result = match is_email {
-        true => login_by_email(&input.username, &input.pwd, &conn_pool, req).await,
-        false => login_by_username(&input.username, &input.pwd, &conn_pool, req).await,
+    let ip_address = request_ip(&req, &config);
+
+    let captcha = match (&input.captcha_id, &input.captcha) {
+        (Some(id), Some(answer)) => id.parse::<u64>().ok().map(|id| (id, answer.clone())),
+        _ => None,
     };
 
-    match result {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/")).finish()),
-        Err(err) => {
-            let template = LoginTemplate {
-                errors: match err {
-                    StatusCode::FORBIDDEN => vec!["Virheellinen salasana!".to_string()],
-                    StatusCode::NOT_FOUND => vec!["Käyttäjää ei ole olemassa!".to_string()],
-                    _ => vec!["Palvelin virhe!".to_string()],
-                },
-            };
-
-            login_view::render(template).await
+    // Email addresses log in to the account's username, unknown ones as
+    // they were typed.
+    let username = match EMAIL.is_match(&input.username) {
+        true => match User::by_email(&input.username, &conn_pool).await {
+            Ok(user) => user.username,
+            Err(Error::NotFound) => None,
+            Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
         },
+        false => None,
     }
+    .unwrap_or_else(|| input.username.clone());
+
+    let result = login_with_password(
+        &username,
+        &input.pwd,
+        captcha,
+        &ip_address,
+        &config,
+        &conn_pool,
+        req,
+    ).await;
+
+    let error = match result {
+        Ok(_) => return Ok(HttpResponse::Found().append_header(("Location", "/")).finish()),
+        Err(err) => err,
+    };
+
+    let captcha = match error {
+        LoginError::CaptchaRequired(_) => true,
+        _ => login_needs_captcha(&ip_address, &config.login, &conn_pool).await.unwrap_or(false),
+    };
+
+    let error = match error {
+        LoginError::InvalidCredentials => "Virheellinen käyttäjänimi tai salasana!".to_owned(),
+        LoginError::LockedOut(wait) => format!("Liian monta epäonnistunutta kirjautumisyritystä! Odota vielä {}.", display_wait(wait)),
+        LoginError::CaptchaRequired(None) => "Ratkaise captcha kirjautuaksesi!".to_owned(),
+        LoginError::CaptchaRequired(Some(err)) => err,
+        LoginError::Server => "Palvelin virhe!".to_owned(),
+    };
+
+    login_view::render(LoginTemplate {
+        errors: vec![error],
+        captcha,
+        csrf_token: csrf.0,
+    }).await
+}
+
+fn request_ip(req: &HttpRequest, config: &Config) -> String {
+    client_ip(req, &config.server.trusted_proxies)
+    .map(|ip| ip.to_string())
+    .unwrap_or_default()
 }
 
 pub async fn handle_logout(
@@ -0,0 +1,342 @@
+use actix_files::Files;
+use actix_web::web;
+use controllers::{admin_controller, api_controller, application_controller, board_controller, captcha_controller, chat_controller, file_controller, index_controller, post_controller, report_controller, search_controller, thread_controller, user_controller};
+use middleware::rate_limit::{RateLimit, RouteGroup};
+use views::not_found_view;
+
+
+pub mod config;
+
+pub mod chat {
+    pub mod handler;
+    pub mod server;
+}
+
+pub mod controllers {
+    pub mod admin_controller;
+    pub mod api_controller;
+    pub mod application_controller;
+    pub mod board_controller;
+    pub mod captcha_controller;
+    pub mod chat_controller;
+    pub mod file_controller;
+    pub mod index_controller;
+    pub mod post_controller;
+    pub mod report_controller;
+    pub mod search_controller;
+    pub mod thread_controller;
+    pub mod user_controller;
+}
+
+pub mod views {
+    pub mod admin_view;
+    pub mod application_list_view;
+    pub mod application_review_view;
+    pub mod application_view;
+    pub mod archive_view;
+    pub mod banned_view;
+    pub mod board_view;
+    pub mod chat_log_view;
+    pub mod chat_view;
+    pub mod forbidden_view;
+    pub mod index_view;
+    pub mod login_view;
+    pub mod mod_log_view;
+    pub mod not_found_view;
+    pub mod register_view;
+    pub mod report_list_view;
+    pub mod search_view;
+    pub mod thread_view;
+    pub mod user_view;
+    pub mod users_view;
+}
+
+pub mod middleware {
+    pub mod csrf;
+    pub mod rate_limit;
+}
+
+pub mod models {
+    pub mod applications;
+    pub mod bans;
+    pub mod boards;
+    pub mod chat_messages;
+    pub mod chat_moderation;
+    pub mod chat_rooms;
+    pub mod files;
+    pub mod login_failures;
+    pub mod mod_actions;
+    pub mod users;
+    pub mod reports;
+    pub mod roles;
+    pub mod threads;
+    pub mod posts;
+    pub mod captchas;
+    pub mod error;
+}
+
+pub mod services {
+    pub mod authentication;
+    pub mod applications;
+    pub mod captchas;
+    pub mod files;
+    pub mod flood;
+    pub mod geoip;
+    pub mod ip;
+    pub mod markup;
+    pub mod moderation;
+    pub mod permissions;
+    pub mod retention;
+    pub mod users;
+    pub mod time;
+    pub mod threads;
+    pub mod posts;
+}
+
+pub mod schema;
+
+
+/// Registers every kapchan route and the static file service.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/")
+            .route(web::get().to(index_controller::index))
+    )
+    .service(
+        web::resource("/ws")
+            .route(web::get().to(chat_controller::chat_ws))
+    )
+    .service(
+        web::resource("/chat")
+            .route(web::get().to(chat_controller::chat))
+    )
+    .service(
+        web::resource("/login")
+            .route(web::get().to(user_controller::login))
+            .route(web::post().to(user_controller::handle_login))
+            .wrap(RateLimit::new(RouteGroup::Login))
+    )
+    .service(
+        web::resource("/logout")
+            .route(web::post().to(user_controller::handle_logout))
+    )
+    .service(
+        web::resource("/register")
+            .route(web::get().to(application_controller::register))
+            .route(web::post().to(application_controller::handle_registration))
+            .wrap(RateLimit::new(RouteGroup::Register))
+    )
+    .service(
+        web::resource("/apply")
+            .route(web::get().to(application_controller::application))
+            .route(web::post().to(application_controller::handle_application))
+            .wrap(RateLimit::new(RouteGroup::Register))
+    )
+    .service(
+        web::resource("/admin")
+            .route(web::get().to(admin_controller::admin))
+    )
+    .service(
+        web::resource("/applications/{page}")
+            .route(web::get().to(admin_controller::applications_list))
+    )
+    .service(
+        web::resource("/users/{page}")
+            .route(web::get().to(admin_controller::users_list))
+    )
+    .service(
+        web::resource("/user/{user_id}")
+            .route(web::get().to(admin_controller::user))
+    )
+    .service(
+        web::resource("/application-review/{application_id}")
+            .route(web::get().to(admin_controller::application_review))
+    )
+    .service(
+        web::resource("/accept-application/{application_id}")
+            .route(web::post().to(admin_controller::handle_application_accept))
+    )
+    .service(
+        web::resource("/deny-application/{application_id}")
+            .route(web::post().to(admin_controller::handle_application_deny))
+    )
+    .service(
+        web::resource("/boards")
+            .route(web::post().to(admin_controller::handle_board_creation))
+    )
+    .service(
+        web::resource("/edit-board/{id}")
+            .route(web::post().to(admin_controller::handle_board_edit))
+    )
+    .service(
+        web::resource("/create-chat")
+            .route(web::post().to(chat_controller::create_chat_room))
+    )
+    .service(
+        web::resource("/chat-log/{page}")
+            .route(web::get().to(chat_controller::chat_log))
+    )
+    .service(
+        web::resource("/mod-log/{page}")
+            .route(web::get().to(admin_controller::mod_log))
+    )
+    .service(
+        web::resource("/edit-chat/{id}")
+            .route(web::post().to(chat_controller::edit_chat_room))
+    )
+    .service(
+        web::resource("/delete-chat/{id}")
+            .route(web::post().to(chat_controller::delete_chat_room))
+    )
+    .service(
+        web::resource("/captcha")
+            .route(web::get().to(captcha_controller::captcha))
+            .wrap(RateLimit::new(RouteGroup::Captcha))
+    )
+    .service(
+        web::resource("/search")
+            .route(web::get().to(search_controller::search))
+    )
+    .service(
+        web::scope("/api/v1")
+            .service(
+                web::resource("/boards")
+                    .route(web::get().to(api_controller::boards))
+            )
+            .service(
+                web::resource("/post/{id}")
+                    .route(web::get().to(api_controller::post))
+            )
+            .service(
+                web::resource("/{handle}/catalog")
+                    .route(web::get().to(api_controller::catalog))
+            )
+            .service(
+                web::resource("/{handle}/thread/{id}")
+                    .route(web::get().to(api_controller::thread))
+            )
+    )
+    .service(
+        web::resource("/{handle}")
+            .route(web::get().to(board_controller::board))
+            .route(web::post().to(thread_controller::handle_thread_creation))
+            .wrap(RateLimit::new(RouteGroup::Post))
+    )
+    .service(
+        web::resource("/{handle}/archive")
+            .route(web::get().to(board_controller::archive))
+    )
+    .service(
+        web::resource("/{handle}/thread/{id}")
+            .route(web::get().to(thread_controller::thread))
+            .route(web::post().to(post_controller::handle_post_creation))
+            .wrap(RateLimit::new(RouteGroup::Post))
+    )
+    .service(
+        web::resource("/pin-thread/{id}")
+            .route(web::post().to(thread_controller::handle_thread_pin))
+    )
+    .service(
+        web::resource("/unpin-thread/{id}")
+            .route(web::post().to(thread_controller::handle_thread_unpin))
+    )
+    .service(
+        web::resource("/unarchive-thread/{id}")
+            .route(web::post().to(thread_controller::handle_thread_unarchive))
+    )
+    .service(
+        web::resource("/purge-archive/{handle}")
+            .route(web::post().to(board_controller::purge_archive))
+    )
+    .service(
+        web::resource("/lock-thread/{id}")
+            .route(web::post().to(thread_controller::handle_thread_lock))
+    )
+    .service(
+        web::resource("/modify-user/{id}")
+            .route(web::post().to(admin_controller::modify_user_by_id))
+    )
+    .service(
+        web::resource("/delete-board/{id}")
+            .route(web::post().to(board_controller::delete_board))
+    )
+    .service(
+        web::resource("/delete-thread/{id}")
+            .route(web::post().to(thread_controller::delete_thread))
+    )
+    .service(
+        web::resource("/restore-thread/{id}")
+            .route(web::post().to(thread_controller::restore_thread))
+    )
+    .service(
+        web::resource("/delete-ban/{id}")
+            .route(web::post().to(admin_controller::handle_ban_deletion))
+    )
+    .service(
+        web::resource("/user-roles/{user_id}")
+            .route(web::post().to(admin_controller::handle_user_role_creation))
+    )
+    .service(
+        web::resource("/delete-user-role/{id}")
+            .route(web::post().to(admin_controller::handle_user_role_deletion))
+    )
+    .service(
+        web::resource("/delete-post/{id}")
+            .route(web::post().to(post_controller::delete_post))
+    )
+    .service(
+        web::resource("/restore-post/{id}")
+            .route(web::post().to(post_controller::restore_post))
+    )
+    .service(
+        web::resource("/report-post")
+            .route(web::post().to(post_controller::report_post))
+            .wrap(RateLimit::new(RouteGroup::Report))
+    )
+    .service(
+        web::resource("/reports/{page}")
+            .route(web::get().to(report_controller::reports_list))
+    )
+    .service(
+        web::resource("/dismiss-report/{id}")
+            .route(web::post().to(report_controller::handle_report_dismiss))
+    )
+    .service(
+        web::resource("/resolve-report/{id}")
+            .route(web::post().to(report_controller::handle_report_resolve))
+    )
+    .service(
+        web::resource("/ban-user-by-post/{id}")
+            .route(web::post().to(post_controller::ban_user_by_post_id))
+    )
+    .service(
+        web::resource("/post-note/{id}")
+            .route(web::post().to(post_controller::update_private_note))
+    )
+    .service(
+        web::resource("/ban-user-by-id/{id}")
+            .route(web::post().to(admin_controller::ban_user_by_id))
+    )
+    .service(
+        web::resource("/post-details/{id}")
+            .route(web::get().to(post_controller::handle_post_details))
+    )
+    .service(
+        web::resource("/full-post/{id}")
+            .route(web::get().to(post_controller::get_post_by_id))
+    )
+    .service(
+        web::resource("/files/{id}")
+        .route(web::get().to(file_controller::serve_files))
+    )
+    .service(
+        web::resource("/thumbnails/{id}")
+        .route(web::get().to(file_controller::serve_thumbnails))
+    )
+    .service(
+        Files::new("/static", "./static")
+            .show_files_listing()
+            .use_last_modified(true),
+    )
+    .default_service(web::to(not_found_view::render));
+}
\ No newline at end of file
@@ -0,0 +1,233 @@
+use std::{
+    future::{ready, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_session::SessionExt;
+use actix_web::{
+    body::EitherBody,
+    cookie::{Cookie, SameSite},
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{ErrorInternalServerError, ErrorPayloadTooLarge, PayloadError},
+    http::{header, Method},
+    web, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use futures_util::{future::LocalBoxFuture, stream, Stream, StreamExt};
+use rand_core::{OsRng, RngCore};
+
+use crate::{config::Config, models::error::UserError};
+
+
+/// Stream type a [`Payload`] can be rebuilt from.
+type BoxedPayloadStream = Pin<Box<dyn Stream<Item = Result<web::Bytes, PayloadError>>>>;
+
+/// Session key of the token.
+const SESSION_KEY: &str = "csrf_token";
+
+/// Cookie the scripts read the token from. Not `HttpOnly` on purpose, the
+/// token is no secret to the page itself.
+pub const CSRF_COOKIE: &str = "kapchan-csrf";
+
+/// Header the scripts send the token in.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Field the token is sent in by plain HTML forms.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// Largest urlencoded form searched for the token.
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+/// The CSRF token of the current session, for embedding in forms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrfToken(pub String);
+
+impl FromRequest for CsrfToken {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+            .get::<CsrfToken>()
+            .cloned()
+            .ok_or_else(|| ErrorInternalServerError("CSRF middleware is not registered")),
+        )
+    }
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn is_state_changing(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+/// Whether the `Origin`, or failing that the `Referer`, is this site.
+/// Requests with neither come from other than browsers and are left to the
+/// token check.
+fn is_same_origin(req: &ServiceRequest) -> bool {
+    let source = match req.headers().get(header::ORIGIN).or_else(|| req.headers().get(header::REFERER)) {
+        Some(source) => source,
+        None => return true,
+    };
+
+    let host = source.to_str()
+    .ok()
+    .and_then(|source| source.split_once("://"))
+    .and_then(|(_, rest)| rest.split('/').next());
+
+    match host {
+        Some(host) => host.eq_ignore_ascii_case(req.connection_info().host()),
+        None => false,
+    }
+}
+
+/// Compares without returning early, so that the token can't be guessed
+/// from response times.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Reads the token out of an urlencoded form and puts the body back for the
+/// handler. The token is URL-safe, so it needs no decoding.
+async fn form_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+
+        if body.len() + chunk.len() > MAX_FORM_BYTES {
+            return Err(ErrorPayloadTooLarge("form is too large"));
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    let token = body[..]
+    .split(|byte| *byte == b'&')
+    .find_map(|field| field.strip_prefix(format!("{}=", CSRF_FIELD).as_bytes()))
+    .map(|token| String::from_utf8_lossy(token).into_owned());
+
+    let body: BoxedPayloadStream = Box::pin(stream::once(ready(Ok(body.freeze()))));
+    req.set_payload(Payload::from(body));
+
+    Ok(token)
+}
+
+/// Issues a CSRF token for every session and, when
+/// `security.csrf_protection` is on, rejects state-changing requests from
+/// other origins or without the token. Forms send it in the `csrf_token`
+/// field, scripts in the `X-CSRF-Token` header.
+///
+/// Must be registered before the session middleware, so that it runs inside
+/// it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Csrf;
+
+impl<S, B> Transform<S, ServiceRequest> for Csrf
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let session = req.get_session();
+
+            let token = match session.get::<String>(SESSION_KEY) {
+                Ok(Some(token)) => token,
+                _ => {
+                    let token = new_token();
+                    session.insert(SESSION_KEY, &token)?;
+                    token
+                },
+            };
+
+            req.extensions_mut().insert(CsrfToken(token.clone()));
+
+            let enforce = req.app_data::<web::Data<Config>>()
+            .map(|config| config.security.csrf_protection)
+            .unwrap_or(true);
+
+            if enforce && is_state_changing(req.method()) {
+                if !is_same_origin(&req) {
+                    let res = HttpResponse::Forbidden().json(UserError {
+                        error: "Pyyntö tuli toiselta sivustolta!".to_owned(),
+                    });
+
+                    return Ok(req.into_response(res).map_into_right_body());
+                }
+
+                let header_token = req.headers()
+                .get(CSRF_HEADER)
+                .map(|value| value.to_str().unwrap_or_default().to_owned());
+
+                let sent = match header_token {
+                    Some(token) => Some(token),
+                    None if req.content_type() == "application/x-www-form-urlencoded" => form_token(&mut req).await?,
+                    None => None,
+                };
+
+                if !sent.is_some_and(|sent| tokens_match(&sent, &token)) {
+                    let res = HttpResponse::Forbidden().json(UserError {
+                        error: "Istunto on vanhentunut! Lataa sivu uudelleen.".to_owned(),
+                    });
+
+                    return Ok(req.into_response(res).map_into_right_body());
+                }
+            }
+
+            let has_cookie = req.cookie(CSRF_COOKIE).is_some_and(|cookie| cookie.value() == token);
+            let mut res = service.call(req).await?;
+
+            // Keep the cookie of the scripts in sync with the session, which
+            // changes on logout.
+            if !has_cookie {
+                let cookie = Cookie::build(CSRF_COOKIE, token)
+                .path("/")
+                .same_site(SameSite::Strict)
+                .finish();
+
+                res.response_mut().add_cookie(&cookie)?;
+            }
+
+            Ok(res.map_into_left_body())
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    future::{ready, Ready},
+    net::IpAddr,
+    rc::Rc,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_identity::IdentityExt;
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method},
+    web, HttpResponse,
+};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use futures_util::future::LocalBoxFuture;
+use ipnet::Ipv6Net;
+
+use crate::{
+    config::{Config, RateLimitConfig, TokenBucket},
+    models::{error::UserError, users::{AccessLevel, User}},
+    services::ip::client_ip,
+};
+
+
+/// How often idle buckets are dropped.
+pub const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Endpoints sharing one limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Captcha,
+    Login,
+    Register,
+    Report,
+    Post,
+}
+
+impl RouteGroup {
+    /// Only requests with this method are limited, so that e.g. the login
+    /// page itself can always be loaded.
+    fn method(&self) -> Method {
+        match self {
+            RouteGroup::Captcha => Method::GET,
+            _ => Method::POST,
+        }
+    }
+}
+
+/// Who a bucket belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Client {
+    /// An IPv4 address or an IPv6 /64, which usually is a single subscriber.
+    Ip(IpAddr),
+    User(u64),
+}
+
+impl Client {
+    fn ip(ip: IpAddr) -> Client {
+        match ip.to_canonical() {
+            IpAddr::V6(ip) => Client::Ip(IpAddr::V6(Ipv6Net::new(ip, 64).unwrap().network())),
+            ip => Client::Ip(ip),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &TokenBucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * limit.per_minute as f64 / 60.0).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// Seconds until the next request is allowed, if it isn't already.
+    fn wait(&self, limit: &TokenBucket) -> Option<u64> {
+        match self.tokens >= 1.0 {
+            true => None,
+            false => Some((((1.0 - self.tokens) * 60.0 / limit.per_minute as f64).ceil() as u64).max(1)),
+        }
+    }
+}
+
+/// In-memory token buckets of every route group, shared by the workers
+/// through `web::Data<RateLimiter>`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<(RouteGroup, Client), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn limit(&self, group: RouteGroup) -> &TokenBucket {
+        match group {
+            RouteGroup::Captcha => &self.config.captcha,
+            RouteGroup::Login => &self.config.login,
+            RouteGroup::Register => &self.config.register,
+            RouteGroup::Report => &self.config.report,
+            RouteGroup::Post => &self.config.post,
+        }
+    }
+
+    /// Takes a request from the buckets of the address and the user. When
+    /// either is empty nothing is taken and the seconds to wait are returned.
+    pub fn check(
+        &self,
+        group: RouteGroup,
+        ip: Option<IpAddr>,
+        user_id: Option<u64>,
+    ) -> Result<(), u64> {
+        let limit = *self.limit(group);
+
+        if !limit.is_enabled() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let keys: Vec<(RouteGroup, Client)> = ip.map(Client::ip)
+        .into_iter()
+        .chain(user_id.map(Client::User))
+        .map(|client| (group, client))
+        .collect();
+
+        let mut wait = 0;
+
+        for key in &keys {
+            let bucket = buckets.entry(*key).or_insert_with(|| Bucket {
+                tokens: limit.burst as f64,
+                updated: now,
+            });
+
+            bucket.refill(&limit, now);
+
+            if let Some(bucket_wait) = bucket.wait(&limit) {
+                wait = wait.max(bucket_wait);
+            }
+        }
+
+        if wait > 0 {
+            return Err(wait);
+        }
+
+        for key in &keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops the buckets that have refilled, they are the same as new ones.
+    pub fn evict(&self) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        buckets.retain(|(group, _), bucket| {
+            let limit = self.limit(*group);
+
+            bucket.refill(limit, now);
+            bucket.tokens < limit.burst as f64
+        });
+    }
+
+    /// Number of buckets in memory.
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// Evicts idle buckets every [`EVICTION_INTERVAL`], run on startup.
+    pub async fn run_eviction(limiter: web::Data<RateLimiter>) {
+        let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            limiter.evict();
+        }
+    }
+}
+
+/// Limits the requests to a resource with the buckets of `group`. Requests
+/// pass through when no [`RateLimiter`] is registered.
+///
+/// ```ignore
+/// web::resource("/login")
+///     .route(web::post().to(user_controller::handle_login))
+///     .wrap(RateLimit::new(RouteGroup::Login))
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    group: RouteGroup,
+}
+
+impl RateLimit {
+    pub fn new(group: RouteGroup) -> Self {
+        Self { group }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            group: self.group,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    group: RouteGroup,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let group = self.group;
+
+        Box::pin(async move {
+            let limiter = match req.app_data::<web::Data<RateLimiter>>().cloned() {
+                Some(limiter) if *req.method() == group.method() => limiter,
+                _ => return service.call(req).await.map(ServiceResponse::map_into_left_body),
+            };
+
+            let ip = match req.app_data::<web::Data<Config>>() {
+                Some(config) => client_ip(req.request(), &config.server.trusted_proxies),
+                None => req.peer_addr().map(|addr| addr.ip()),
+            };
+
+            let user_id = req.request()
+            .get_identity()
+            .ok()
+            .and_then(|identity| identity.id().ok())
+            .and_then(|id| id.parse::<u64>().ok());
+
+            let wait = match limiter.check(group, ip, user_id) {
+                Ok(_) => return service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(wait) => wait,
+            };
+
+            // Staff is exempt. Only looked up once a limit is hit to spare
+            // the database on ordinary requests.
+            let conn_pool = req.app_data::<web::Data<Pool<AsyncMysqlConnection>>>().cloned();
+
+            if let (Some(user_id), Some(conn_pool)) = (user_id, conn_pool) {
+                if let Ok(user) = User::by_id(user_id, &conn_pool).await {
+                    if user.access_level >= AccessLevel::Moderator as u8 {
+                        return service.call(req).await.map(ServiceResponse::map_into_left_body);
+                    }
+                }
+            }
+
+            let res = HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, wait.to_string()))
+            .json(UserError {
+                error: format!("Liian monta pyyntöä! Yritä uudelleen {} sekunnin kuluttua.", wait),
+            });
+
+            Ok(req.into_response(res).map_into_right_body())
+        })
+    }
+}
\ No newline at end of file
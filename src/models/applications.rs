@@ -45,28 +45,9 @@ impl Application {
     ) -> Result<Application, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-
-                    let _ = diesel::update(applications::table.find(application_id))
-                    .set((applications::accepted.eq(accept), applications::closed_at.eq(Some(review_time))))
-                    .execute(conn)
-                    .await?;
-
-                    let _ = diesel::insert_into(application_reviews::table)
-                    .values(ApplicationReviewModel {
-                        reviewer_id,
-                        application_id,
-                    })
-                    .execute(conn)
-                    .await?;
-
-                    let application = applications::table
-                    .find(application_id)
-                    .first::<Application>(conn)
-                    .await?;
-
-                    Ok(application)
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    Application::review_conn(conn, application_id, reviewer_id, accept, review_time).scope_boxed()
+                })
                 .await
             },
 
@@ -74,6 +55,35 @@ impl Application {
         }
     }
 
+    /// [`Application::review`] on an open connection.
+    pub async fn review_conn(
+        conn: &mut AsyncMysqlConnection,
+        application_id: u32,
+        reviewer_id: u64,
+        accept: bool,
+        review_time: NaiveDateTime,
+    ) -> Result<Application, Error> {
+        let _ = diesel::update(applications::table.find(application_id))
+        .set((applications::accepted.eq(accept), applications::closed_at.eq(Some(review_time))))
+        .execute(conn)
+        .await?;
+
+        let _ = diesel::insert_into(application_reviews::table)
+        .values(ApplicationReviewModel {
+            reviewer_id,
+            application_id,
+        })
+        .execute(conn)
+        .await?;
+
+        let application = applications::table
+        .find(application_id)
+        .first::<Application>(conn)
+        .await?;
+
+        Ok(application)
+    }
+
     pub async fn closed_at(
         conn_pool: &Pool<AsyncMysqlConnection>,
         application_id: u32,
@@ -16,7 +16,7 @@ use diesel_async::{
 };
 use serde::Serialize;
 
-use crate::schema::{bans::{self, expires_at}, users};
+use crate::{schema::{bans::{self, expires_at}, users}, services::ip::ip_key};
 
 use super::users::User;
 
@@ -33,6 +33,11 @@ pub struct Ban {
     pub ip_address: String,
     pub expires_at: NaiveDateTime,
     pub created_at: NaiveDateTime,
+    pub ip_range: Option<String>,
+    #[serde(skip_serializing)]
+    pub range_start: Option<Vec<u8>>,
+    #[serde(skip_serializing)]
+    pub range_end: Option<Vec<u8>>,
 }
 
 impl Ban {
@@ -41,12 +46,24 @@ impl Ban {
         user_id: u64,
         ip_address: String,
     ) -> Result<Option<Ban>, Error> {
+        let key = ip_address.parse().ok().map(|ip| ip_key(&ip));
+
         match conn_pool.get().await {
             Ok(mut conn) => {
                 conn.transaction::<_, Error, _>(|conn| async move {
                 
-                    let mut ban = bans::table
+                    let mut query = bans::table
                     .filter(bans::user_id.eq(user_id).or(bans::ip_address.eq(ip_address)))
+                    .into_boxed();
+
+                    if let Some(key) = key {
+                        query = query.or_filter(
+                            bans::range_start.le(key.clone())
+                            .and(bans::range_end.ge(key))
+                        );
+                    }
+
+                    let mut ban = query
                     .order(expires_at.desc())
                     .limit(1)
                     .load::<Ban>(conn)
@@ -67,6 +84,28 @@ impl Ban {
         }
     }
 
+    pub async fn by_id(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        ban_id: u32,
+    ) -> Result<Ban, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let ban = bans::table
+                    .find(ban_id)
+                    .select(Ban::as_select())
+                    .first(conn)
+                    .await?;
+
+                    Ok(ban)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
     pub async fn get_bans_by_user(
         conn_pool: &Pool<AsyncMysqlConnection>,
         user_id: u64,
@@ -97,22 +136,30 @@ impl Ban {
     ) -> Result<(), Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-
-                    diesel::delete(
-                        bans::table.find(ban_id)
-                    )
-                    .execute(conn)
-                    .await?;
-
-                    Ok(())
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    Ban::delete_ban_conn(conn, ban_id).scope_boxed()
+                })
                 .await
             },
 
             Err(_) => Err(Error::BrokenTransactionManager),
         }
     }
+
+    /// [`Ban::delete_ban`] on an open connection, so that lifting the ban is
+    /// logged together with it.
+    pub async fn delete_ban_conn(
+        conn: &mut AsyncMysqlConnection,
+        ban_id: u32,
+    ) -> Result<(), Error> {
+        diesel::delete(
+            bans::table.find(ban_id)
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -124,6 +171,9 @@ pub struct BanModel<'a> {
     pub reason: Option<&'a str>,
     pub ip_address: &'a str,
     pub expires_at: NaiveDateTime,
+    pub ip_range: Option<&'a str>,
+    pub range_start: Option<Vec<u8>>,
+    pub range_end: Option<Vec<u8>>,
 }
 
 impl BanModel<'_> {
@@ -133,25 +183,30 @@ impl BanModel<'_> {
     ) -> Result<Ban, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-                    let _ = diesel::insert_into(bans::table)
-                    .values(self)
-                    .execute(conn)
-                    .await?;
-                
-                    let ban = bans::table
-                    .find(last_insert_id())
-                    .first::<Ban>(conn)
-                    .await?;
-            
-                    Ok(ban)
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| self.insert_conn(conn).scope_boxed())
                 .await
             },
 
             Err(_) => Err(Error::BrokenTransactionManager),
         }
     }
+
+    /// [`BanModel::insert`] on an open connection, so that the ban can be
+    /// logged in the same transaction.
+    pub async fn insert_conn(
+        &self,
+        conn: &mut AsyncMysqlConnection,
+    ) -> Result<Ban, Error> {
+        let _ = diesel::insert_into(bans::table)
+        .values(self)
+        .execute(conn)
+        .await?;
+
+        bans::table
+        .find(last_insert_id())
+        .first::<Ban>(conn)
+        .await
+    }
 }
 
 sql_function!(fn last_insert_id() -> Unsigned<Integer>);
\ No newline at end of file
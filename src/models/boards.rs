@@ -33,6 +33,8 @@ pub struct Board {
     pub thread_size_limit: u32,
     pub captcha: bool,
     pub nsfw: bool,
+    /// Show the country flags of posters.
+    pub flags: bool,
 }
 
 impl Board {
@@ -149,16 +151,9 @@ impl Board {
     ) -> Result<(), Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-
-                    diesel::delete(
-                        boards::table.find(board_id)
-                    )
-                    .execute(conn)
-                    .await?;
-
-                    Ok(())
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    Board::delete_board_conn(conn, board_id).scope_boxed()
+                })
                 .await
             },
 
@@ -166,6 +161,20 @@ impl Board {
         }
     }
 
+    /// [`Board::delete_board`] on an open connection, so that the deletion can be logged with it.
+    pub async fn delete_board_conn(
+        conn: &mut AsyncMysqlConnection,
+        board_id: u32,
+    ) -> Result<(), Error> {
+        diesel::delete(
+            boards::table.find(board_id)
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn update_board<'a>(
         conn_pool: &Pool<AsyncMysqlConnection>,
         board_id: u32,
@@ -173,23 +182,31 @@ impl Board {
     ) -> Result<(), Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-
-                    diesel::update(
-                        boards::table.find(board_id)
-                    )
-                    .set(model)
-                    .execute(conn)
-                    .await?;
-
-                    Ok(())
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    Board::update_board_conn(conn, board_id, model).scope_boxed()
+                })
                 .await
             },
 
             Err(_) => Err(Error::BrokenTransactionManager),
         }
     }
+
+    /// [`Board::update_board`] on an open connection.
+    pub async fn update_board_conn<'a>(
+        conn: &mut AsyncMysqlConnection,
+        board_id: u32,
+        model: BoardModel<'a>,
+    ) -> Result<(), Error> {
+        diesel::update(
+            boards::table.find(board_id)
+        )
+        .set(model)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -203,6 +220,8 @@ pub struct BoardModel<'a> {
     pub thread_size_limit: u32,
     pub captcha: bool,
     pub nsfw: bool,
+    /// Show the country flags of posters.
+    pub flags: bool,
 }
 
 impl BoardModel<'_> {
@@ -212,25 +231,34 @@ impl BoardModel<'_> {
     ) -> Result<Board, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-                    let _ = diesel::insert_into(boards::table)
-                    .values(self)
-                    .execute(conn)
-                    .await?;
-                
-                    let application = boards::table
-                    .find(last_insert_id())
-                    .first::<Board>(conn)
-                    .await?;
-            
-                    Ok(application)
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    self.insert_conn(conn).scope_boxed()
+                })
                 .await
             },
 
             Err(_) => Err(Error::BrokenTransactionManager),
         }
     }
+
+    /// [`BoardModel::insert`] on an open connection, for logging the new board
+    /// in the same transaction.
+    pub async fn insert_conn(
+        &self,
+        conn: &mut AsyncMysqlConnection,
+    ) -> Result<Board, Error> {
+        let _ = diesel::insert_into(boards::table)
+        .values(self)
+        .execute(conn)
+        .await?;
+    
+        let application = boards::table
+        .find(last_insert_id())
+        .first::<Board>(conn)
+        .await?;
+
+        Ok(application)
+    }
 }
 
 sql_function!(fn last_insert_id() -> Unsigned<Integer>);
\ No newline at end of file
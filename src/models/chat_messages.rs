@@ -0,0 +1,148 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*, 
+    result::Error, 
+    sql_function, 
+    ExpressionMethods, 
+    QueryDsl, 
+    SelectableHelper
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, 
+    scoped_futures::ScopedFutureExt, 
+    AsyncConnection, 
+    AsyncMysqlConnection, 
+    RunQueryDsl
+};
+use serde::Serialize;
+
+use crate::schema::chat_messages;
+
+
+#[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Clone)]
+#[diesel(table_name = chat_messages)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct ChatMessage {
+    pub id: u64,
+    pub room_id: u32,
+    pub user_id: u64,
+    pub display_name: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl ChatMessage {
+    /// Loads up to `limit` messages of a room older than `before_id`, or the
+    /// latest messages if `before_id` is not given. Messages are returned in
+    /// chronological order.
+    pub async fn list_by_room(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        room_id: u32,
+        before_id: Option<u64>,
+        limit: i64,
+    ) -> Result<Vec<ChatMessage>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let mut query = chat_messages::table
+                    .filter(chat_messages::room_id.eq(room_id))
+                    .into_boxed();
+
+                    if let Some(before_id) = before_id {
+                        query = query.filter(chat_messages::id.lt(before_id));
+                    }
+
+                    let mut messages = query
+                    .order(chat_messages::id.desc())
+                    .limit(limit)
+                    .select(ChatMessage::as_select())
+                    .load(conn)
+                    .await?;
+
+                    messages.reverse();
+
+                    Ok(messages)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Deletes the messages a user has sent in the room since `since` and
+    /// returns the ids of the deleted messages.
+    pub async fn delete_recent_by_user(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        room_id: u32,
+        user_id: u64,
+        since: NaiveDateTime,
+    ) -> Result<Vec<u64>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let ids = chat_messages::table
+                    .filter(chat_messages::room_id.eq(room_id))
+                    .filter(chat_messages::user_id.eq(user_id))
+                    .filter(chat_messages::created_at.ge(since))
+                    .select(chat_messages::id)
+                    .load::<u64>(conn)
+                    .await?;
+
+                    diesel::delete(
+                        chat_messages::table
+                        .filter(chat_messages::id.eq_any(&ids))
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    Ok(ids)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = chat_messages)]
+pub struct ChatMessageModel<'a> {
+    pub room_id: u32,
+    pub user_id: u64,
+    pub display_name: &'a str,
+    pub body: &'a str,
+}
+
+impl ChatMessageModel<'_> {
+    pub async fn insert(
+        &self, 
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<ChatMessage, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let _ = diesel::insert_into(chat_messages::table)
+                    .values(self)
+                    .execute(conn)
+                    .await?;
+                
+                    let chat_message = chat_messages::table
+                    .find(last_insert_id())
+                    .first::<ChatMessage>(conn)
+                    .await?;
+            
+                    Ok(chat_message)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+sql_function!(fn last_insert_id() -> Unsigned<BigInt>);
\ No newline at end of file
@@ -0,0 +1,190 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*, 
+    result::Error, 
+    ExpressionMethods, 
+    QueryDsl, 
+    SelectableHelper
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, 
+    scoped_futures::ScopedFutureExt, 
+    AsyncConnection, 
+    AsyncMysqlConnection, 
+    RunQueryDsl
+};
+use serde::Serialize;
+
+use crate::schema::{chat_mod_actions, chat_timeouts};
+
+
+#[derive(Debug, Queryable, Identifiable, Selectable, Insertable, AsChangeset, Serialize, Clone)]
+#[diesel(table_name = chat_timeouts)]
+#[diesel(primary_key(user_id))]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct ChatTimeout {
+    pub user_id: u64,
+    pub moderator_id: u64,
+    pub expires_at: NaiveDateTime,
+}
+
+impl ChatTimeout {
+    pub async fn list_active(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        now: NaiveDateTime,
+    ) -> Result<Vec<ChatTimeout>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let timeouts = chat_timeouts::table
+                    .filter(chat_timeouts::expires_at.gt(now))
+                    .select(ChatTimeout::as_select())
+                    .load(conn)
+                    .await?;
+
+                    Ok(timeouts)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Inserts the timeout or replaces an earlier timeout of the same user.
+    pub async fn upsert(
+        &self,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    diesel::replace_into(chat_timeouts::table)
+                    .values(self)
+                    .execute(conn)
+                    .await?;
+
+                    Ok(())
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn delete_by_user_id(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        user_id: u64,
+    ) -> Result<usize, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let res = diesel::delete(
+                        chat_timeouts::table.find(user_id)
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    Ok(res)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Clone)]
+#[diesel(table_name = chat_mod_actions)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct ChatModAction {
+    pub id: u32,
+    pub moderator_id: u64,
+    pub target_id: Option<u64>,
+    pub room: Option<String>,
+    pub action: String,
+    pub details: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ChatModAction {
+    pub async fn count_actions(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<i64, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let count = chat_mod_actions::table
+                    .count()
+                    .get_result(conn)
+                    .await?;
+
+                    Ok(count)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn load_actions(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        page_size: i64,
+        offset: i64,
+    ) -> Result<Vec<ChatModAction>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let actions = chat_mod_actions::table
+                    .order(chat_mod_actions::created_at.desc())
+                    .limit(page_size)
+                    .offset(offset)
+                    .select(ChatModAction::as_select())
+                    .load(conn)
+                    .await?;
+
+                    Ok(actions)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = chat_mod_actions)]
+pub struct ChatModActionModel<'a> {
+    pub moderator_id: u64,
+    pub target_id: Option<u64>,
+    pub room: Option<&'a str>,
+    pub action: &'a str,
+    pub details: Option<&'a str>,
+}
+
+impl ChatModActionModel<'_> {
+    pub async fn insert(
+        &self, 
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    diesel::insert_into(chat_mod_actions::table)
+                    .values(self)
+                    .execute(conn)
+                    .await?;
+
+                    Ok(())
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
\ No newline at end of file
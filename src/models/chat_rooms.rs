@@ -25,6 +25,7 @@ pub struct ChatRoom {
     pub id: u32,
     pub name: String,
     pub access_level: u8,
+    pub slow_mode_seconds: u32,
 }
 
 impl ChatRoom {
@@ -48,17 +49,79 @@ impl ChatRoom {
         }
     }
 
-    pub async fn delete_chat_room(
+    pub async fn by_id(
+        id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<ChatRoom, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let chat_room = chat_rooms::table
+                    .find(id)
+                    .first::<ChatRoom>(conn)
+                    .await?;
+
+                    Ok(chat_room)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Updates the room. Its stored messages follow by id.
+    pub async fn update_chat_room<'a>(
         conn_pool: &Pool<AsyncMysqlConnection>,
         id: u32,
+        model: ChatRoomModel<'a>,
+    ) -> Result<ChatRoom, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    ChatRoom::update_chat_room_conn(conn, id, model).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`ChatRoom::update_chat_room`] on an open connection.
+    pub async fn update_chat_room_conn<'a>(
+        conn: &mut AsyncMysqlConnection,
+        id: u32,
+        model: ChatRoomModel<'a>,
+    ) -> Result<ChatRoom, Error> {
+        diesel::update(
+            chat_rooms::table.find(id)
+        )
+        .set(&model)
+        .execute(conn)
+        .await?;
+
+        let chat_room = chat_rooms::table
+        .find(id)
+        .first::<ChatRoom>(conn)
+        .await?;
+
+        Ok(chat_room)
+    }
+
+    pub async fn update_slow_mode(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        id: u32,
+        slow_mode_seconds: u32,
     ) -> Result<(), Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
                 conn.transaction::<_, Error, _>(|conn| async move {
 
-                    diesel::delete(
+                    diesel::update(
                         chat_rooms::table.find(id)
                     )
+                    .set(chat_rooms::slow_mode_seconds.eq(slow_mode_seconds))
                     .execute(conn)
                     .await?;
 
@@ -70,6 +133,36 @@ impl ChatRoom {
             Err(_) => Err(Error::BrokenTransactionManager),
         }
     }
+
+    pub async fn delete_chat_room(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        id: u32,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    ChatRoom::delete_chat_room_conn(conn, id).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`ChatRoom::delete_chat_room`] on an open connection.
+    pub async fn delete_chat_room_conn(
+        conn: &mut AsyncMysqlConnection,
+        id: u32,
+    ) -> Result<(), Error> {
+        diesel::delete(
+            chat_rooms::table.find(id)
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -86,25 +179,33 @@ impl ChatRoomModel<'_> {
     ) -> Result<ChatRoom, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-                    let _ = diesel::insert_into(chat_rooms::table)
-                    .values(self)
-                    .execute(conn)
-                    .await?;
-                
-                    let chat_room = chat_rooms::table
-                    .find(last_insert_id())
-                    .first::<ChatRoom>(conn)
-                    .await?;
-            
-                    Ok(chat_room)
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    self.insert_conn(conn).scope_boxed()
+                })
                 .await
             },
 
             Err(_) => Err(Error::BrokenTransactionManager),
         }
     }
+
+    /// [`ChatRoomModel::insert`] on an open connection.
+    pub async fn insert_conn(
+        &self,
+        conn: &mut AsyncMysqlConnection,
+    ) -> Result<ChatRoom, Error> {
+        let _ = diesel::insert_into(chat_rooms::table)
+        .values(self)
+        .execute(conn)
+        .await?;
+    
+        let chat_room = chat_rooms::table
+        .find(last_insert_id())
+        .first::<ChatRoom>(conn)
+        .await?;
+
+        Ok(chat_room)
+    }
 }
 
 sql_function!(fn last_insert_id() -> Unsigned<Integer>);
\ No newline at end of file
@@ -0,0 +1,165 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    dsl::{self, count_star},
+    prelude::*,
+    result::Error,
+    ExpressionMethods,
+    QueryDsl,
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool,
+    scoped_futures::ScopedFutureExt,
+    AsyncConnection,
+    AsyncMysqlConnection,
+    RunQueryDsl
+};
+
+use crate::schema::login_failures;
+
+
+/// Failed logins within a time window.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FailureCount {
+    pub count: i64,
+    pub last_failure: Option<NaiveDateTime>,
+}
+
+/// A failed login attempt. Attempts on unknown accounts are kept by the
+/// given username or email so that they are limited the same way.
+#[derive(Debug, Queryable, Identifiable, Selectable)]
+#[diesel(table_name = login_failures)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct LoginFailure {
+    pub id: u64,
+    pub user_id: Option<u64>,
+    pub login: String,
+    pub ip_address: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl LoginFailure {
+    /// Failures of an existing account, or of an unknown `login`, since `since`.
+    pub async fn count_by_account(
+        user_id: Option<u64>,
+        login: &str,
+        since: NaiveDateTime,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<FailureCount, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let query = login_failures::table
+                    .filter(login_failures::created_at.ge(since))
+                    .select((count_star(), dsl::max(login_failures::created_at)));
+
+                    let (count, last_failure) = match user_id {
+                        Some(user_id) => query
+                        .filter(login_failures::user_id.eq(user_id))
+                        .first::<(i64, Option<NaiveDateTime>)>(conn)
+                        .await?,
+                        None => query
+                        .filter(login_failures::user_id.is_null())
+                        .filter(login_failures::login.eq(login))
+                        .first::<(i64, Option<NaiveDateTime>)>(conn)
+                        .await?,
+                    };
+
+                    Ok(FailureCount { count, last_failure })
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Failures from an address since `since`.
+    pub async fn count_by_ip(
+        ip_address: &str,
+        since: NaiveDateTime,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<FailureCount, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let (count, last_failure) = login_failures::table
+                    .filter(login_failures::ip_address.eq(ip_address))
+                    .filter(login_failures::created_at.ge(since))
+                    .select((count_star(), dsl::max(login_failures::created_at)))
+                    .first::<(i64, Option<NaiveDateTime>)>(conn)
+                    .await?;
+
+                    Ok(FailureCount { count, last_failure })
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Forgets the failures of an account after a successful login.
+    pub async fn clear_account(
+        user_id: u64,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    diesel::delete(
+                        login_failures::table
+                        .filter(login_failures::user_id.eq(user_id))
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    Ok(())
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = login_failures)]
+pub struct LoginFailureModel<'a> {
+    pub user_id: Option<u64>,
+    pub login: &'a str,
+    pub ip_address: &'a str,
+}
+
+impl LoginFailureModel<'_> {
+    /// Records the failure and drops the ones older than `expired`, which
+    /// no longer count towards any limit.
+    pub async fn insert(
+        &self,
+        expired: NaiveDateTime,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    diesel::delete(
+                        login_failures::table
+                        .filter(login_failures::created_at.lt(expired))
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    diesel::insert_into(login_failures::table)
+                    .values(self)
+                    .execute(conn)
+                    .await?;
+
+                    Ok(())
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,308 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    result::Error,
+    ExpressionMethods,
+    QueryDsl,
+    SelectableHelper
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool,
+    scoped_futures::ScopedFutureExt,
+    AsyncConnection,
+    AsyncMysqlConnection,
+    RunQueryDsl
+};
+use serde::{Deserialize, Serialize};
+
+use crate::schema::mod_actions;
+
+use super::posts::PostData;
+
+
+/// What a staff member did. The item of an action is what its name says,
+/// e.g. the post of `delete_post` and the ban of `lift_ban`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModActionKind {
+    BanUser,
+    LiftBan,
+    DeletePost,
+    DeleteThread,
+    RestorePost,
+    RestoreThread,
+    PinThread,
+    UnpinThread,
+    LockThread,
+    UnlockThread,
+    UnarchiveThread,
+    PostNote,
+    CreateBoard,
+    EditBoard,
+    DeleteBoard,
+    PurgeArchive,
+    ModifyUser,
+    AssignRole,
+    RemoveRole,
+    AcceptApplication,
+    DenyApplication,
+    DismissReport,
+    ResolveReport,
+    CreateChatRoom,
+    EditChatRoom,
+    DeleteChatRoom,
+}
+
+impl ModActionKind {
+    pub const ALL: [ModActionKind; 26] = [
+        ModActionKind::BanUser,
+        ModActionKind::LiftBan,
+        ModActionKind::DeletePost,
+        ModActionKind::DeleteThread,
+        ModActionKind::RestorePost,
+        ModActionKind::RestoreThread,
+        ModActionKind::PinThread,
+        ModActionKind::UnpinThread,
+        ModActionKind::LockThread,
+        ModActionKind::UnlockThread,
+        ModActionKind::UnarchiveThread,
+        ModActionKind::PostNote,
+        ModActionKind::CreateBoard,
+        ModActionKind::EditBoard,
+        ModActionKind::DeleteBoard,
+        ModActionKind::PurgeArchive,
+        ModActionKind::ModifyUser,
+        ModActionKind::AssignRole,
+        ModActionKind::RemoveRole,
+        ModActionKind::AcceptApplication,
+        ModActionKind::DenyApplication,
+        ModActionKind::DismissReport,
+        ModActionKind::ResolveReport,
+        ModActionKind::CreateChatRoom,
+        ModActionKind::EditChatRoom,
+        ModActionKind::DeleteChatRoom,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModActionKind::BanUser => "ban_user",
+            ModActionKind::LiftBan => "lift_ban",
+            ModActionKind::DeletePost => "delete_post",
+            ModActionKind::DeleteThread => "delete_thread",
+            ModActionKind::RestorePost => "restore_post",
+            ModActionKind::RestoreThread => "restore_thread",
+            ModActionKind::PinThread => "pin_thread",
+            ModActionKind::UnpinThread => "unpin_thread",
+            ModActionKind::LockThread => "lock_thread",
+            ModActionKind::UnlockThread => "unlock_thread",
+            ModActionKind::UnarchiveThread => "unarchive_thread",
+            ModActionKind::PostNote => "post_note",
+            ModActionKind::CreateBoard => "create_board",
+            ModActionKind::EditBoard => "edit_board",
+            ModActionKind::DeleteBoard => "delete_board",
+            ModActionKind::PurgeArchive => "purge_archive",
+            ModActionKind::ModifyUser => "modify_user",
+            ModActionKind::AssignRole => "assign_role",
+            ModActionKind::RemoveRole => "remove_role",
+            ModActionKind::AcceptApplication => "accept_application",
+            ModActionKind::DenyApplication => "deny_application",
+            ModActionKind::DismissReport => "dismiss_report",
+            ModActionKind::ResolveReport => "resolve_report",
+            ModActionKind::CreateChatRoom => "create_chat_room",
+            ModActionKind::EditChatRoom => "edit_chat_room",
+            ModActionKind::DeleteChatRoom => "delete_chat_room",
+        }
+    }
+}
+
+/// A deleted post as it was when it was deleted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostSnapshot {
+    pub id: u32,
+    pub thread_id: u32,
+    pub user_id: u64,
+    pub ip_address: String,
+    pub message: String,
+    pub file_name: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<&PostData> for PostSnapshot {
+    fn from(post_data: &PostData) -> Self {
+        PostSnapshot {
+            id: post_data.post.id,
+            thread_id: post_data.post.thread_id,
+            user_id: post_data.post.user_id,
+            ip_address: post_data.post.ip_address.clone(),
+            message: post_data.post.message.clone(),
+            file_name: post_data.attachment.as_ref().map(|attachment| attachment.file_name.clone()),
+            created_at: post_data.post.created_at,
+        }
+    }
+}
+
+/// Serializes the posts for [`ModActionModel::snapshot`].
+pub fn snapshot_posts<'a>(posts: impl IntoIterator<Item = &'a PostData>) -> String {
+    let snapshots = posts.into_iter().map(PostSnapshot::from).collect::<Vec<PostSnapshot>>();
+
+    serde_json::to_string(&snapshots).unwrap_or_default()
+}
+
+#[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Clone)]
+#[diesel(table_name = mod_actions)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct ModAction {
+    pub id: u32,
+    pub moderator_id: u64,
+    pub action: String,
+    /// Id of the post, thread, board etc. the action was about.
+    pub item_id: Option<u64>,
+    /// User the action was aimed at, e.g. the author of a deleted post.
+    pub target_id: Option<u64>,
+    pub board_id: Option<u32>,
+    pub details: Option<String>,
+    /// Deleted posts as JSON.
+    pub snapshot: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ModAction {
+    /// The deleted posts of the action, if any.
+    pub fn snapshot_posts(&self) -> Vec<PostSnapshot> {
+        self.snapshot
+        .as_deref()
+        .and_then(|snapshot| serde_json::from_str(snapshot).ok())
+        .unwrap_or_default()
+    }
+
+    pub async fn count_actions(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        filter: &ModActionFilter,
+    ) -> Result<i64, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let count = filtered(filter)
+                    .count()
+                    .get_result(conn)
+                    .await?;
+
+                    Ok(count)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn load_actions(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        filter: &ModActionFilter,
+        page_size: i64,
+        offset: i64,
+    ) -> Result<Vec<ModAction>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let actions = filtered(filter)
+                    .order((mod_actions::created_at.desc(), mod_actions::id.desc()))
+                    .limit(page_size)
+                    .offset(offset)
+                    .select(ModAction::as_select())
+                    .load(conn)
+                    .await?;
+
+                    Ok(actions)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ModActionFilter {
+    pub moderator_id: Option<u64>,
+    pub action: Option<String>,
+    pub target_id: Option<u64>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+fn filtered(filter: &ModActionFilter) -> mod_actions::BoxedQuery<'_, diesel::mysql::Mysql> {
+    let mut query = mod_actions::table.into_boxed();
+
+    if let Some(moderator_id) = filter.moderator_id {
+        query = query.filter(mod_actions::moderator_id.eq(moderator_id));
+    }
+
+    if let Some(ref action) = filter.action {
+        query = query.filter(mod_actions::action.eq(action));
+    }
+
+    if let Some(target_id) = filter.target_id {
+        query = query.filter(mod_actions::target_id.eq(target_id));
+    }
+
+    if let Some(from) = filter.from {
+        query = query.filter(mod_actions::created_at.ge(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(mod_actions::created_at.lt(to));
+    }
+
+    query
+}
+
+#[derive(Debug, Default, Insertable)]
+#[diesel(table_name = mod_actions)]
+pub struct ModActionModel<'a> {
+    pub moderator_id: u64,
+    pub action: &'a str,
+    pub item_id: Option<u64>,
+    pub target_id: Option<u64>,
+    pub board_id: Option<u32>,
+    pub details: Option<&'a str>,
+    pub snapshot: Option<&'a str>,
+}
+
+impl<'a> ModActionModel<'a> {
+    pub fn new(moderator_id: u64, kind: ModActionKind) -> Self {
+        ModActionModel {
+            moderator_id,
+            action: kind.as_str(),
+            ..Default::default()
+        }
+    }
+
+    pub async fn insert(
+        &self,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| self.insert_conn(conn).scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`ModActionModel::insert`] on an open connection, so that the entry is
+    /// written in the same transaction as the action it records.
+    pub async fn insert_conn(
+        &self,
+        conn: &mut AsyncMysqlConnection,
+    ) -> Result<(), Error> {
+        diesel::insert_into(mod_actions::table)
+        .values(self)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -1,7 +1,9 @@
 use diesel::{
+    dsl::{self, sql}, 
     prelude::*, 
     result::Error, 
     sql_function, 
+    sql_types::{Bool, Text}, 
     ExpressionMethods, 
     QueryDsl
 };
@@ -13,7 +15,7 @@ use diesel_async::{
     RunQueryDsl
 };
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::{models::threads::Thread, schema::{attachments, boards, posts, replies, threads}};
@@ -27,17 +29,33 @@ use super::boards::Board;
 #[diesel(primary_key(id))]
 pub struct Post {
     pub id: u32,
+    #[serde(skip_serializing)]
     pub user_id: u64,
     pub thread_id: u32,
     pub access_level: u8,
     pub show_username: bool,
     pub sage: bool,
     pub message: String,
+    #[serde(skip_serializing)]
     pub message_hash: String,
+    #[serde(skip_serializing)]
     pub ip_address: String,
     pub country_code: Option<String>,
+    /// Public moderator note shown under the message, e.g. a ban marker.
+    #[serde(skip_serializing)]
     pub mod_note: Option<String>,
     pub created_at: NaiveDateTime,
+    /// Moderator annotation only shown to moderators.
+    #[serde(skip_serializing)]
+    pub private_note: Option<String>,
+    /// Set when the post is deleted. Deleted posts are only shown to
+    /// moderators until they're restored or purged.
+    #[serde(skip_serializing)]
+    pub deleted_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub deleted_by: Option<u64>,
+    /// Staff title of a signed post, fixed when the post was made.
+    pub capcode: Option<String>,
 }
 
 impl Post {
@@ -62,6 +80,143 @@ impl Post {
         }
     }
 
+    /// Address of the most recent post of a user, if any.
+    pub async fn last_ip_by_user(
+        user_id: u64,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Option<String>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let ip_address = posts::table
+                    .filter(posts::user_id.eq(user_id))
+                    .order(posts::id.desc())
+                    .select(posts::ip_address)
+                    .first::<String>(conn)
+                    .await
+                    .optional()?;
+        
+                    Ok(ip_address)
+                }.scope_boxed())
+                .await
+            },
+    
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Creation time of the latest post by the user or from the address,
+    /// made after `since`.
+    pub async fn last_post_time(
+        user_id: u64,
+        ip_address: &str,
+        since: NaiveDateTime,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Option<NaiveDateTime>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let created_at = posts::table
+                    .filter(posts::user_id.eq(user_id).or(posts::ip_address.eq(ip_address)))
+                    .filter(posts::created_at.ge(since))
+                    .select(dsl::max(posts::created_at))
+                    .first::<Option<NaiveDateTime>>(conn)
+                    .await?;
+
+                    Ok(created_at)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Creation time of the latest thread started by the user or from the
+    /// address after `since`. A thread is started by its first post.
+    pub async fn last_thread_time(
+        user_id: u64,
+        ip_address: &str,
+        since: NaiveDateTime,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Option<NaiveDateTime>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let thread_ids = posts::table
+                    .filter(posts::user_id.eq(user_id).or(posts::ip_address.eq(ip_address)))
+                    .filter(posts::created_at.ge(since))
+                    .select(posts::thread_id)
+                    .distinct()
+                    .load::<u32>(conn)
+                    .await?;
+
+                    if thread_ids.is_empty() {
+                        return Ok(None);
+                    }
+
+                    let first_post_ids: Vec<u32> = posts::table
+                    .filter(posts::thread_id.eq_any(&thread_ids))
+                    .group_by(posts::thread_id)
+                    .select(dsl::min(posts::id))
+                    .load::<Option<u32>>(conn)
+                    .await?
+                    .into_iter()
+                    .flatten()
+                    .collect();
+
+                    let created_at = posts::table
+                    .filter(posts::id.eq_any(&first_post_ids))
+                    .filter(posts::user_id.eq(user_id).or(posts::ip_address.eq(ip_address)))
+                    .filter(posts::created_at.ge(since))
+                    .select(dsl::max(posts::created_at))
+                    .first::<Option<NaiveDateTime>>(conn)
+                    .await?;
+
+                    Ok(created_at)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Creation time of the latest post made after `since` with the same
+    /// message hash, on the given board or anywhere.
+    pub async fn last_duplicate_time(
+        message_hash: &str,
+        board_id: Option<u32>,
+        since: NaiveDateTime,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Option<NaiveDateTime>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let mut query = posts::table
+                    .inner_join(threads::table)
+                    .filter(posts::message_hash.eq(message_hash))
+                    .filter(posts::created_at.ge(since))
+                    .into_boxed();
+
+                    if let Some(board_id) = board_id {
+                        query = query.filter(threads::board_id.eq(board_id));
+                    }
+
+                    let created_at = query
+                    .select(dsl::max(posts::created_at))
+                    .first::<Option<NaiveDateTime>>(conn)
+                    .await?;
+
+                    Ok(created_at)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
     pub async fn full_post_by_id(
         id: u32,
         conn_pool: &Pool<AsyncMysqlConnection>,
@@ -96,6 +251,50 @@ impl Post {
         }
     }
 
+    /// Posts among `ids` that exist and are visible at `access_level`,
+    /// with the thread and board they belong to.
+    pub async fn quote_targets(
+        ids: &[u32],
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        access_level: u8,
+    ) -> Result<Vec<QuoteTarget>, Error> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let targets = posts::table
+                    .inner_join(
+                        threads::table
+                        .inner_join(boards::table)
+                    )
+                    .filter(posts::id.eq_any(ids))
+                    .filter(posts::deleted_at.is_null())
+                    .filter(threads::deleted_at.is_null())
+                    .filter(posts::access_level.le(access_level))
+                    .filter(boards::access_level.le(access_level))
+                    .select((posts::id, posts::thread_id, boards::handle))
+                    .load::<(u32, u32, String)>(conn)
+                    .await?
+                    .into_iter()
+                    .map(|(post_id, thread_id, board_handle)| QuoteTarget {
+                        post_id,
+                        thread_id,
+                        board_handle,
+                    })
+                    .collect();
+
+                    Ok(targets)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
     pub async fn insert_post_by_thread_id(
         thread_id: u32,
         conn_pool: &Pool<AsyncMysqlConnection>,
@@ -117,6 +316,7 @@ impl Post {
                         access_level: input.access_level,
                         sage: input.sage,
                         mod_note: input.mod_note.as_deref(),
+                        capcode: input.capcode.as_deref(),
                     })
                     .execute(conn)
                     .await?;
@@ -158,12 +358,14 @@ impl Post {
                 conn.transaction::<_, Error, _>(|conn| async move {
                     let posts: Vec<(Post, (Thread, Board))> = posts::table
                     .filter(posts::access_level.le(access_level))
+                    .filter(posts::deleted_at.is_null())
                     .order(posts::created_at.desc())
                     .limit(limit)
                     .inner_join(
                         threads::table
                         .inner_join(boards::table)
                     )
+                    .filter(threads::deleted_at.is_null())
                     .load::<(Post, (Thread, Board))>(conn)
                     .await?;
 
@@ -186,6 +388,328 @@ impl Post {
         }
     }
 
+    pub async fn count_search(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        filter: &SearchFilter,
+        access_level: u8,
+    ) -> Result<i64, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let mut query = posts::table
+                    .inner_join(
+                        threads::table
+                        .inner_join(boards::table)
+                    )
+                    .left_join(attachments::table)
+                    .filter(posts::access_level.le(access_level))
+                    .filter(boards::access_level.le(access_level))
+                    .filter(posts::deleted_at.is_null())
+                    .filter(threads::deleted_at.is_null())
+                    .filter(
+                        sql::<Bool>("(MATCH (posts.message) AGAINST (")
+                        .bind::<Text, _>(filter.query.clone())
+                        .sql(" IN NATURAL LANGUAGE MODE) OR MATCH (threads.title) AGAINST (")
+                        .bind::<Text, _>(filter.query.clone())
+                        .sql(" IN NATURAL LANGUAGE MODE))")
+                    )
+                    .into_boxed();
+
+                    if let Some(board_id) = filter.board_id {
+                        query = query.filter(threads::board_id.eq(board_id));
+                    }
+
+                    if let Some(from) = filter.from {
+                        query = query.filter(posts::created_at.ge(from));
+                    }
+
+                    if let Some(to) = filter.to {
+                        query = query.filter(posts::created_at.lt(to));
+                    }
+
+                    if filter.has_attachment {
+                        query = query.filter(attachments::id.is_not_null());
+                    }
+
+                    let count = query
+                    .count()
+                    .get_result(conn)
+                    .await?;
+
+                    Ok(count)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Full-text search over post messages and thread titles, limited to
+    /// posts and boards the given access level can see. Newest posts first.
+    pub async fn search(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        filter: &SearchFilter,
+        access_level: u8,
+        page_size: i64,
+        offset: i64,
+    ) -> Result<Vec<SearchResult>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let mut query = posts::table
+                    .inner_join(
+                        threads::table
+                        .inner_join(boards::table)
+                    )
+                    .left_join(attachments::table)
+                    .filter(posts::access_level.le(access_level))
+                    .filter(boards::access_level.le(access_level))
+                    .filter(posts::deleted_at.is_null())
+                    .filter(threads::deleted_at.is_null())
+                    .filter(
+                        sql::<Bool>("(MATCH (posts.message) AGAINST (")
+                        .bind::<Text, _>(filter.query.clone())
+                        .sql(" IN NATURAL LANGUAGE MODE) OR MATCH (threads.title) AGAINST (")
+                        .bind::<Text, _>(filter.query.clone())
+                        .sql(" IN NATURAL LANGUAGE MODE))")
+                    )
+                    .into_boxed();
+
+                    if let Some(board_id) = filter.board_id {
+                        query = query.filter(threads::board_id.eq(board_id));
+                    }
+
+                    if let Some(from) = filter.from {
+                        query = query.filter(posts::created_at.ge(from));
+                    }
+
+                    if let Some(to) = filter.to {
+                        query = query.filter(posts::created_at.lt(to));
+                    }
+
+                    if filter.has_attachment {
+                        query = query.filter(attachments::id.is_not_null());
+                    }
+
+                    let results = query
+                    .order(posts::created_at.desc())
+                    .limit(page_size)
+                    .offset(offset)
+                    .select((
+                        Post::as_select(),
+                        Thread::as_select(),
+                        Board::as_select(),
+                        Option::<Attachment>::as_select(),
+                    ))
+                    .load::<(Post, Thread, Board, Option<Attachment>)>(conn)
+                    .await?;
+
+                    let results = results.into_iter()
+                    .map(|(post, thread, board, attachment)| SearchResult {
+                        post,
+                        attachment,
+                        thread_id: thread.id,
+                        thread_title: thread.title,
+                        board_handle: board.handle,
+                        board_title: board.title,
+                    })
+                    .collect();
+
+                    Ok(results)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn update_mod_note(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        post_id: u32,
+        mod_note: Option<&str>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Post::update_mod_note_conn(conn, post_id, mod_note).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Post::update_mod_note`] on an open connection, so that the note is
+    /// written with the ban it explains.
+    pub async fn update_mod_note_conn(
+        conn: &mut AsyncMysqlConnection,
+        post_id: u32,
+        mod_note: Option<&str>,
+    ) -> Result<(), Error> {
+        diesel::update(
+            posts::table.find(post_id)
+        )
+        .set(posts::mod_note.eq(mod_note))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_private_note(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        post_id: u32,
+        private_note: Option<&str>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Post::update_private_note_conn(conn, post_id, private_note).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Post::update_private_note`] on an open connection.
+    pub async fn update_private_note_conn(
+        conn: &mut AsyncMysqlConnection,
+        post_id: u32,
+        private_note: Option<&str>,
+    ) -> Result<(), Error> {
+        diesel::update(
+            posts::table.find(post_id)
+        )
+        .set(posts::private_note.eq(private_note))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Hides the post until it is restored or purged. Already deleted posts
+    /// keep their original deletion time.
+    pub async fn soft_delete_post(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        post_id: u32,
+        deleted_by: u64,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Post::soft_delete_post_conn(conn, post_id, deleted_by).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Post::soft_delete_post`] on an open connection, so that the deletion
+    /// can be logged in the same transaction.
+    pub async fn soft_delete_post_conn(
+        conn: &mut AsyncMysqlConnection,
+        post_id: u32,
+        deleted_by: u64,
+    ) -> Result<(), Error> {
+        let current_time = Utc::now().naive_utc();
+
+        diesel::update(
+            posts::table
+            .find(post_id)
+            .filter(posts::deleted_at.is_null())
+        )
+        .set((
+            posts::deleted_at.eq(current_time),
+            posts::deleted_by.eq(deleted_by),
+        ))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn restore_post(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        post_id: u32,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Post::restore_post_conn(conn, post_id).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Post::restore_post`] on an open connection, so that the restore can be
+    /// logged in the same transaction.
+    pub async fn restore_post_conn(
+        conn: &mut AsyncMysqlConnection,
+        post_id: u32,
+    ) -> Result<(), Error> {
+        diesel::update(
+            posts::table.find(post_id)
+        )
+        .set((
+            posts::deleted_at.eq(None::<NaiveDateTime>),
+            posts::deleted_by.eq(None::<u64>),
+        ))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Permanently deletes posts deleted before `before`. Returns their
+    /// attachments so that the files can be removed.
+    pub async fn purge_deleted(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        before: NaiveDateTime,
+    ) -> Result<Vec<Attachment>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let post_ids = posts::table
+                    .filter(posts::deleted_at.lt(before))
+                    .select(posts::id)
+                    .load::<u32>(conn)
+                    .await?;
+
+                    let attachments = attachments::table
+                    .filter(attachments::id.eq_any(&post_ids))
+                    .select(Attachment::as_select())
+                    .load::<Attachment>(conn)
+                    .await?;
+
+                    diesel::delete(
+                        posts::table.filter(posts::id.eq_any(&post_ids))
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    Ok(attachments)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Removes the post for good, e.g. when its attachment couldn't be saved.
     pub async fn delete_post(
         conn_pool: &Pool<AsyncMysqlConnection>,
         post_id: u32,
@@ -223,6 +747,7 @@ pub struct PostModel<'a> {
     pub ip_address: &'a str,
     pub country_code: Option<&'a str>,
     pub mod_note: Option<&'a str>,
+    pub capcode: Option<&'a str>,
 }
 
 #[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Deserialize, Clone)]
@@ -353,6 +878,7 @@ pub struct PostInput {
     pub ip_address: String,
     pub country_code: Option<String>,
     pub mod_note: Option<String>,
+    pub capcode: Option<String>,
     pub reply_ids: Vec<u32>,
 }
 
@@ -360,6 +886,7 @@ pub struct PostInput {
 pub struct PostOutput {
     pub id: u32,
     pub show_username: bool,
+    pub capcode: Option<String>,
     pub message: String,
     pub country_code: Option<String>,
     pub attachment: Option<Attachment>,
@@ -372,6 +899,25 @@ pub struct PostData {
     pub replies: Vec<u32>,
 }
 
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    pub query: String,
+    pub board_id: Option<u32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub has_attachment: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub post: Post,
+    pub attachment: Option<Attachment>,
+    pub thread_id: u32,
+    pub thread_title: String,
+    pub board_handle: String,
+    pub board_title: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PostPreview {
     pub post_id: u32,
@@ -381,4 +927,12 @@ pub struct PostPreview {
     pub message: String,
 }
 
+/// A post that can be quoted, see [`Post::quote_targets`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QuoteTarget {
+    pub post_id: u32,
+    pub thread_id: u32,
+    pub board_handle: String,
+}
+
 sql_function!(fn last_insert_id() -> Unsigned<Integer>);
\ No newline at end of file
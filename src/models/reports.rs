@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use diesel::{
     prelude::*, 
     result::Error, 
@@ -15,7 +16,9 @@ use diesel_async::{
 };
 use serde::Serialize;
 
-use crate::schema::reports;
+use crate::schema::{boards, posts, reports, threads};
+
+use super::{boards::Board, posts::Post, threads::Thread};
 
 
 #[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Clone)]
@@ -23,14 +26,202 @@ use crate::schema::reports;
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct Report {
     pub id: u32,
-    pub post_id: u32,
+    pub post_id: Option<u32>,
     pub reason: String,
+    pub created_at: NaiveDateTime,
+    pub resolved_by: Option<u64>,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+impl Report {
+    pub async fn by_id(
+        id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Report, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let report = reports::table
+                    .find(id)
+                    .first::<Report>(conn)
+                    .await?;
+
+                    Ok(report)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn count_reports(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        board_id: Option<u32>,
+        resolved: bool,
+    ) -> Result<i64, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let mut query = reports::table
+                    .left_join(posts::table.inner_join(threads::table))
+                    .into_boxed();
+
+                    query = match resolved {
+                        true => query.filter(reports::resolved_at.is_not_null()),
+                        false => query.filter(reports::resolved_at.is_null()),
+                    };
+
+                    if let Some(board_id) = board_id {
+                        query = query.filter(threads::board_id.eq(board_id));
+                    }
+
+                    let count = query
+                    .count()
+                    .get_result(conn)
+                    .await?;
+
+                    Ok(count)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn load_reports(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        board_id: Option<u32>,
+        resolved: bool,
+        page_size: i64,
+        offset: i64,
+    ) -> Result<Vec<ReportView>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let mut query = reports::table
+                    .left_join(
+                        posts::table
+                        .inner_join(
+                            threads::table
+                            .inner_join(boards::table)
+                        )
+                    )
+                    .into_boxed();
+
+                    query = match resolved {
+                        true => query.filter(reports::resolved_at.is_not_null()),
+                        false => query.filter(reports::resolved_at.is_null()),
+                    };
+
+                    if let Some(board_id) = board_id {
+                        query = query.filter(threads::board_id.eq(board_id));
+                    }
+
+                    let reports = query
+                    .order(reports::created_at.desc())
+                    .limit(page_size)
+                    .offset(offset)
+                    .load::<(Report, Option<(Post, (Thread, Board))>)>(conn)
+                    .await?;
+
+                    let reports = reports.into_iter()
+                    .map(|(report, post)| match post {
+                        Some((post, (thread, board))) => ReportView {
+                            report,
+                            post: Some(post),
+                            thread_id: Some(thread.id),
+                            board_handle: Some(board.handle),
+                            board_title: Some(board.title),
+                        },
+                        None => ReportView {
+                            report,
+                            post: None,
+                            thread_id: None,
+                            board_handle: None,
+                            board_title: None,
+                        },
+                    })
+                    .collect();
+
+                    Ok(reports)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Marks an open report as resolved. Returns the number of updated rows,
+    /// which is 0 if another moderator has already handled the report.
+    pub async fn resolve(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        report_id: u32,
+        moderator_id: u64,
+        resolve_time: NaiveDateTime,
+    ) -> Result<usize, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Report::resolve_conn(conn, report_id, moderator_id, resolve_time).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Report::resolve`] on an open connection, so that it can be part of
+    /// the transaction of the action that resolves the report.
+    pub async fn resolve_conn(
+        conn: &mut AsyncMysqlConnection,
+        report_id: u32,
+        moderator_id: u64,
+        resolve_time: NaiveDateTime,
+    ) -> Result<usize, Error> {
+        diesel::update(
+            reports::table
+            .find(report_id)
+            .filter(reports::resolved_at.is_null())
+        )
+        .set((
+            reports::resolved_by.eq(Some(moderator_id)),
+            reports::resolved_at.eq(Some(resolve_time)),
+        ))
+        .execute(conn)
+        .await
+    }
+
+    /// Resolves the other open reports of a post on an open connection.
+    pub async fn resolve_by_post_id_conn(
+        conn: &mut AsyncMysqlConnection,
+        post_id: u32,
+        moderator_id: u64,
+        resolve_time: NaiveDateTime,
+    ) -> Result<usize, Error> {
+        diesel::update(
+            reports::table
+            .filter(reports::post_id.eq(post_id))
+            .filter(reports::resolved_at.is_null())
+        )
+        .set((
+            reports::resolved_by.eq(Some(moderator_id)),
+            reports::resolved_at.eq(Some(resolve_time)),
+        ))
+        .execute(conn)
+        .await
+    }
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
 #[diesel(table_name = reports)]
 pub struct ReportModel<'a> {
-    pub post_id: u32,
+    pub post_id: Option<u32>,
     pub reason: &'a str,
 }
 
@@ -46,12 +237,12 @@ impl ReportModel<'_> {
                     .values(self)
                     .execute(conn)
                     .await?;
-                
+
                     let report = reports::table
                     .find(last_insert_id())
                     .first::<Report>(conn)
                     .await?;
-            
+
                     Ok(report)
                 }.scope_boxed())
                 .await
@@ -62,4 +253,13 @@ impl ReportModel<'_> {
     }
 }
 
-sql_function!(fn last_insert_id() -> Unsigned<Integer>);
+#[derive(Debug, Serialize)]
+pub struct ReportView {
+    pub report: Report,
+    pub post: Option<Post>,
+    pub thread_id: Option<u32>,
+    pub board_handle: Option<String>,
+    pub board_title: Option<String>,
+}
+
+sql_function!(fn last_insert_id() -> Unsigned<Integer>);
\ No newline at end of file
@@ -0,0 +1,261 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    result::Error,
+    ExpressionMethods,
+    QueryDsl,
+    SelectableHelper
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool,
+    scoped_futures::ScopedFutureExt,
+    AsyncConnection,
+    AsyncMysqlConnection,
+    RunQueryDsl
+};
+use serde::Serialize;
+
+use crate::schema::{boards, role_capabilities, roles, user_roles};
+
+
+/// A named set of capabilities, e.g. "janitor".
+#[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Clone)]
+#[diesel(table_name = roles)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct Role {
+    pub id: u32,
+    pub name: String,
+}
+
+impl Role {
+    pub async fn list_all(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Vec<Role>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let roles = roles::table
+                    .order(roles::id.asc())
+                    .select(Role::as_select())
+                    .load(conn)
+                    .await?;
+
+                    Ok(roles)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn by_id(
+        role_id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Role, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let role = roles::table
+                    .find(role_id)
+                    .select(Role::as_select())
+                    .first(conn)
+                    .await?;
+
+                    Ok(role)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Names of the capabilities of the role.
+    pub async fn capabilities(
+        role_id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Vec<String>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let capabilities = role_capabilities::table
+                    .filter(role_capabilities::role_id.eq(role_id))
+                    .select(role_capabilities::capability)
+                    .load::<String>(conn)
+                    .await?;
+
+                    Ok(capabilities)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+}
+
+/// A role given to a user, on a single board or on all of them.
+#[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Clone)]
+#[diesel(table_name = user_roles)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct UserRole {
+    pub id: u32,
+    pub user_id: u64,
+    pub role_id: u32,
+    pub board_id: Option<u32>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A role of a user with the names needed to show it.
+#[derive(Debug, Serialize, Clone)]
+pub struct UserRoleView {
+    pub user_role: UserRole,
+    pub role_name: String,
+    /// Handle of the board the role is limited to.
+    pub board_handle: Option<String>,
+}
+
+impl UserRole {
+    /// Every capability of the user's roles with the board it is limited to.
+    pub async fn grants_by_user(
+        user_id: u64,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Vec<(String, Option<u32>)>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let grants = user_roles::table
+                    .inner_join(role_capabilities::table.on(role_capabilities::role_id.eq(user_roles::role_id)))
+                    .filter(user_roles::user_id.eq(user_id))
+                    .select((role_capabilities::capability, user_roles::board_id))
+                    .load::<(String, Option<u32>)>(conn)
+                    .await?;
+
+                    Ok(grants)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn list_by_user(
+        user_id: u64,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Vec<UserRoleView>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let roles = user_roles::table
+                    .inner_join(roles::table)
+                    .left_join(boards::table)
+                    .filter(user_roles::user_id.eq(user_id))
+                    .order(user_roles::id.asc())
+                    .select((UserRole::as_select(), roles::name, boards::handle.nullable()))
+                    .load::<(UserRole, String, Option<String>)>(conn)
+                    .await?;
+
+                    Ok(roles
+                    .into_iter()
+                    .map(|(user_role, role_name, board_handle)| UserRoleView { user_role, role_name, board_handle })
+                    .collect())
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn by_id(
+        user_role_id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<UserRole, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let user_role = user_roles::table
+                    .find(user_role_id)
+                    .select(UserRole::as_select())
+                    .first(conn)
+                    .await?;
+
+                    Ok(user_role)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    pub async fn delete(
+        user_role_id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    UserRole::delete_conn(conn, user_role_id).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`UserRole::delete`] on an open connection.
+    pub async fn delete_conn(
+        conn: &mut AsyncMysqlConnection,
+        user_role_id: u32,
+    ) -> Result<(), Error> {
+        diesel::delete(user_roles::table.find(user_role_id))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = user_roles)]
+pub struct UserRoleModel {
+    pub user_id: u64,
+    pub role_id: u32,
+    pub board_id: Option<u32>,
+}
+
+impl UserRoleModel {
+    pub async fn insert(
+        &self,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    self.insert_conn(conn).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`UserRoleModel::insert`] on an open connection, so that granting the role
+    /// is logged with it.
+    pub async fn insert_conn(
+        &self,
+        conn: &mut AsyncMysqlConnection,
+    ) -> Result<(), Error> {
+        diesel::insert_into(user_roles::table)
+        .values(self)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
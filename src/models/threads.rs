@@ -27,6 +27,7 @@ use super::posts::{Attachment, Post, PostData, PostInput, PostModel, PostOutput,
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct Thread {
     pub id: u32,
+    #[serde(skip_serializing)]
     pub user_id: u64,
     pub board_id: u32,
     pub title: String,
@@ -34,6 +35,12 @@ pub struct Thread {
     pub locked: bool,
     pub archived: bool,
     pub bump_time: NaiveDateTime,
+    /// Set when the thread is deleted. Deleted threads are only shown to
+    /// moderators until they're restored or purged.
+    #[serde(skip_serializing)]
+    pub deleted_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub deleted_by: Option<u64>,
 }
 
 impl Thread {
@@ -46,6 +53,7 @@ impl Thread {
                 conn.transaction::<_, Error, _>(|conn| async move {
                     let count = posts::table
                     .filter(posts::thread_id.eq(thread_id))
+                    .filter(posts::deleted_at.is_null())
                     .count()
                     .get_result(conn)
                     .await?;
@@ -59,6 +67,29 @@ impl Thread {
         }
     }
 
+    /// Board of the thread, for checking board-scoped permissions.
+    pub async fn board_id(
+        thread_id: u32,
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<u32, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let board_id = threads::table
+                    .find(thread_id)
+                    .select(threads::board_id)
+                    .first::<u32>(conn)
+                    .await?;
+
+                    Ok(board_id)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
     pub async fn by_id(
         thread_id: u32,
         conn_pool: &Pool<AsyncMysqlConnection>,
@@ -172,6 +203,7 @@ impl Thread {
                         access_level: input.post.access_level,
                         sage: input.post.sage,
                         mod_note: input.post.mod_note.as_deref(),
+                        capcode: input.post.capcode.as_deref(),
                     })
                     .execute(conn)
                     .await?;
@@ -198,6 +230,7 @@ impl Thread {
                     let inactive_thread = threads::table
                     .filter(threads::board_id.eq(input.board_id))
                     .filter(threads::archived.eq(false))
+                    .filter(threads::deleted_at.is_null())
                     .order((threads::pinned.eq(false), threads::bump_time.desc()))
                     .limit(1)
                     .offset(active_threads_limit.into())
@@ -233,11 +266,13 @@ impl Thread {
                     let threads = threads::table
                     .filter(threads::board_id.eq(board_id))
                     .filter(threads::archived.eq(false))
+                    .filter(threads::deleted_at.is_null())
                     .order((threads::pinned.eq(false), threads::bump_time.desc()))
                     .load::<Thread>(conn)
                     .await?;
 
                     let thread_posts: Vec<(Post, Option<Attachment>)> = Post::belonging_to(&threads)
+                    .filter(posts::deleted_at.is_null())
                     .left_join(attachments::table)
                     .order_by(posts::id)
                     .select((
@@ -264,6 +299,7 @@ impl Thread {
                             op_post: PostOutput {
                                 id: op_post.0.id,
                                 show_username: op_post.0.show_username,
+                                capcode: op_post.0.capcode.clone(),
                                 message: op_post.0.message.clone(),
                                 country_code: op_post.0.country_code.clone(),
                                 attachment: op_post.1.clone(),
@@ -313,18 +349,176 @@ impl Thread {
         thread_id: u32,
         pin_status: bool,
     ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Thread::pin_thread_conn(conn, thread_id, pin_status).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Thread::pin_thread`] on an open connection.
+    pub async fn pin_thread_conn(
+        conn: &mut AsyncMysqlConnection,
+        thread_id: u32,
+        pin_status: bool,
+    ) -> Result<(), Error> {
+        diesel::update(
+            threads::table.find(thread_id)
+        )
+        .set(threads::pinned.eq(pin_status))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn lock_thread(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        thread_id: u32,
+        lock_status: bool,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Thread::lock_thread_conn(conn, thread_id, lock_status).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Thread::lock_thread`] on an open connection.
+    pub async fn lock_thread_conn(
+        conn: &mut AsyncMysqlConnection,
+        thread_id: u32,
+        lock_status: bool,
+    ) -> Result<(), Error> {
+        diesel::update(
+            threads::table.find(thread_id)
+        )
+        .set(threads::locked.eq(lock_status))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Hides the thread and its posts until it is restored or purged. An
+    /// already deleted thread keeps its original deletion time.
+    pub async fn soft_delete_thread(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        thread_id: u32,
+        deleted_by: u64,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Thread::soft_delete_thread_conn(conn, thread_id, deleted_by).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Thread::soft_delete_thread`] on an open connection, so that the
+    /// deletion can be logged in the same transaction.
+    pub async fn soft_delete_thread_conn(
+        conn: &mut AsyncMysqlConnection,
+        thread_id: u32,
+        deleted_by: u64,
+    ) -> Result<(), Error> {
+        let current_time = Utc::now().naive_utc();
+
+        diesel::update(
+            threads::table
+            .find(thread_id)
+            .filter(threads::deleted_at.is_null())
+        )
+        .set((
+            threads::deleted_at.eq(current_time),
+            threads::deleted_by.eq(deleted_by),
+        ))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn restore_thread(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        thread_id: u32,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Thread::restore_thread_conn(conn, thread_id).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Thread::restore_thread`] on an open connection, so that the restore
+    /// can be logged in the same transaction.
+    pub async fn restore_thread_conn(
+        conn: &mut AsyncMysqlConnection,
+        thread_id: u32,
+    ) -> Result<(), Error> {
+        diesel::update(
+            threads::table.find(thread_id)
+        )
+        .set((
+            threads::deleted_at.eq(None::<NaiveDateTime>),
+            threads::deleted_by.eq(None::<u64>),
+        ))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Permanently deletes threads deleted before `before` with all of their
+    /// posts. Returns the attachments of the posts so that the files can be
+    /// removed.
+    pub async fn purge_deleted(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        before: NaiveDateTime,
+    ) -> Result<Vec<Attachment>, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
                 conn.transaction::<_, Error, _>(|conn| async move {
 
-                    diesel::update(
-                        threads::table.find(thread_id)
+                    let thread_ids = threads::table
+                    .filter(threads::deleted_at.lt(before))
+                    .select(threads::id)
+                    .load::<u32>(conn)
+                    .await?;
+
+                    let attachments = attachments::table
+                    .inner_join(posts::table)
+                    .filter(posts::thread_id.eq_any(&thread_ids))
+                    .select(Attachment::as_select())
+                    .load::<Attachment>(conn)
+                    .await?;
+
+                    diesel::delete(
+                        threads::table.filter(threads::id.eq_any(&thread_ids))
                     )
-                    .set(threads::pinned.eq(pin_status))
                     .execute(conn)
                     .await?;
 
-                    Ok(())
+                    Ok(attachments)
                 }.scope_boxed())
                 .await
             },
@@ -333,19 +527,19 @@ impl Thread {
         }
     }
 
-    pub async fn lock_thread(
+    /// Removes the thread and its posts for good, e.g. when the attachment of
+    /// the op post couldn't be saved.
+    pub async fn delete_thread(
         conn_pool: &Pool<AsyncMysqlConnection>,
         thread_id: u32,
-        lock_status: bool,
     ) -> Result<(), Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
                 conn.transaction::<_, Error, _>(|conn| async move {
 
-                    diesel::update(
+                    diesel::delete(
                         threads::table.find(thread_id)
                     )
-                    .set(threads::locked.eq(lock_status))
                     .execute(conn)
                     .await?;
 
@@ -358,21 +552,72 @@ impl Thread {
         }
     }
 
-    pub async fn delete_thread(
+    pub async fn archive_thread(
         conn_pool: &Pool<AsyncMysqlConnection>,
         thread_id: u32,
+        archive_status: bool,
+    ) -> Result<(), Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Thread::archive_thread_conn(conn, thread_id, archive_status).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Thread::archive_thread`] on an open connection.
+    pub async fn archive_thread_conn(
+        conn: &mut AsyncMysqlConnection,
+        thread_id: u32,
+        archive_status: bool,
     ) -> Result<(), Error> {
+        diesel::update(
+            threads::table.find(thread_id)
+        )
+        .set(threads::archived.eq(archive_status))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn count_archived(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        board_id: u32,
+        filter: &ArchiveFilter,
+    ) -> Result<i64, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
                 conn.transaction::<_, Error, _>(|conn| async move {
 
-                    diesel::delete(
-                        threads::table.find(thread_id)
-                    )
-                    .execute(conn)
+                    let mut query = threads::table
+                    .filter(threads::board_id.eq(board_id))
+                    .filter(threads::archived.eq(true))
+                    .filter(threads::deleted_at.is_null())
+                    .into_boxed();
+
+                    if let Some(ref title) = filter.title {
+                        query = query.filter(threads::title.like(like_pattern(title)));
+                    }
+
+                    if let Some(from) = filter.from {
+                        query = query.filter(threads::bump_time.ge(from));
+                    }
+
+                    if let Some(to) = filter.to {
+                        query = query.filter(threads::bump_time.lt(to));
+                    }
+
+                    let count = query
+                    .count()
+                    .get_result(conn)
                     .await?;
 
-                    Ok(())
+                    Ok(count)
                 }.scope_boxed())
                 .await
             },
@@ -381,6 +626,146 @@ impl Thread {
         }
     }
 
+    /// Lists archived threads of a board, newest first, with their op posts.
+    pub async fn list_archived(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        board_id: u32,
+        filter: &ArchiveFilter,
+        page_size: i64,
+        offset: i64,
+    ) -> Result<Vec<ArchivedThreadOutput>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+
+                    let mut query = threads::table
+                    .filter(threads::board_id.eq(board_id))
+                    .filter(threads::archived.eq(true))
+                    .filter(threads::deleted_at.is_null())
+                    .into_boxed();
+
+                    if let Some(ref title) = filter.title {
+                        query = query.filter(threads::title.like(like_pattern(title)));
+                    }
+
+                    if let Some(from) = filter.from {
+                        query = query.filter(threads::bump_time.ge(from));
+                    }
+
+                    if let Some(to) = filter.to {
+                        query = query.filter(threads::bump_time.lt(to));
+                    }
+
+                    let threads = query
+                    .order(threads::bump_time.desc())
+                    .limit(page_size)
+                    .offset(offset)
+                    .load::<Thread>(conn)
+                    .await?;
+
+                    let thread_posts: Vec<(Post, Option<Attachment>)> = Post::belonging_to(&threads)
+                    .filter(posts::deleted_at.is_null())
+                    .left_join(attachments::table)
+                    .order_by(posts::id)
+                    .select((
+                        Post::as_select(),
+                        Option::<Attachment>::as_select()
+                    ))
+                    .load::<(Post, Option<Attachment>)>(conn)
+                    .await?;
+
+                    let mut archived = Vec::new();
+
+                    for (posts, thread) in thread_posts.grouped_by(&threads).into_iter().zip(threads) {
+                        let replies = posts.len().saturating_sub(1);
+
+                        let (op_post, attachment) = match posts.into_iter().next() {
+                            Some(op_post) => op_post,
+                            None => continue,
+                        };
+
+                        let backlinks = Reply::belonging_to(&op_post)
+                        .select(Reply::as_select())
+                        .load::<Reply>(conn)
+                        .await?;
+
+                        archived.push(ArchivedThreadOutput {
+                            thread,
+                            op_post: PostData {
+                                post: op_post,
+                                attachment,
+                                replies: backlinks.into_iter().map(|reply| reply.reply_id).collect(),
+                            },
+                            replies,
+                        });
+                    }
+
+                    Ok(archived)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// Deletes the archived threads of a board last bumped before `before`, or
+    /// all of them. Returns the attachments of the deleted posts so that their
+    /// files can be removed.
+    pub async fn purge_archived(
+        conn_pool: &Pool<AsyncMysqlConnection>,
+        board_id: u32,
+        before: Option<NaiveDateTime>,
+    ) -> Result<Vec<Attachment>, Error> {
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| {
+                    Thread::purge_archived_conn(conn, board_id, before).scope_boxed()
+                })
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
+    /// [`Thread::purge_archived`] on an open connection. The attachments should
+    /// only be removed from disk once the transaction has committed.
+    pub async fn purge_archived_conn(
+        conn: &mut AsyncMysqlConnection,
+        board_id: u32,
+        before: Option<NaiveDateTime>,
+    ) -> Result<Vec<Attachment>, Error> {
+        let mut query = threads::table
+        .filter(threads::board_id.eq(board_id))
+        .filter(threads::archived.eq(true))
+        .select(threads::id)
+        .into_boxed();
+
+        if let Some(before) = before {
+            query = query.filter(threads::bump_time.lt(before));
+        }
+
+        let thread_ids = query
+        .load::<u32>(conn)
+        .await?;
+
+        let attachments = attachments::table
+        .inner_join(posts::table)
+        .filter(posts::thread_id.eq_any(&thread_ids))
+        .select(Attachment::as_select())
+        .load::<Attachment>(conn)
+        .await?;
+
+        diesel::delete(
+            threads::table.filter(threads::id.eq_any(&thread_ids))
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(attachments)
+    }
+
     pub async fn get_op_post(
         conn_pool: &Pool<AsyncMysqlConnection>,
         thread_id: u32,
@@ -433,6 +818,7 @@ pub struct ThreadInput {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ThreadCatalogOutput {
     pub id: u32,
+    #[serde(skip_serializing)]
     pub user_id: u64,
     pub title: String,
     pub pinned: bool,
@@ -454,4 +840,54 @@ pub struct ThreadData {
     pub posts: Vec<PostData>,
 }
 
+impl ThreadData {
+    /// Drops deleted posts and the replies they made, for users who
+    /// can't see deleted content.
+    pub fn hide_deleted(&mut self) {
+        self.hide_posts(|postdata| postdata.post.deleted_at.is_some());
+    }
+
+    /// Drops posts above the user's access level and the replies they made.
+    pub fn hide_restricted(&mut self, access_level: u8) {
+        self.hide_posts(|postdata| postdata.post.access_level > access_level);
+    }
+
+    fn hide_posts(&mut self, hidden: impl Fn(&PostData) -> bool) {
+        let hidden_ids: Vec<u32> = self.posts
+        .iter()
+        .filter(|postdata| hidden(postdata))
+        .map(|postdata| postdata.post.id)
+        .collect();
+
+        self.posts.retain(|postdata| !hidden(postdata));
+
+        for postdata in self.posts.iter_mut() {
+            postdata.replies.retain(|reply_id| !hidden_ids.contains(reply_id));
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedThreadOutput {
+    pub thread: Thread,
+    pub op_post: PostData,
+    pub replies: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ArchiveFilter {
+    pub title: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+    .replace('\\', "\\\\")
+    .replace('%', "\\%")
+    .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
 sql_function!(fn last_insert_id() -> Unsigned<Integer>);
\ No newline at end of file
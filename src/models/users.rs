@@ -54,6 +54,31 @@ impl User {
         }
     }
 
+    pub async fn by_ids(
+        ids: &[u64],
+        conn_pool: &Pool<AsyncMysqlConnection>,
+    ) -> Result<Vec<User>, Error> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        match conn_pool.get().await {
+            Ok(mut conn) => {
+                conn.transaction::<_, Error, _>(|conn| async move {
+                    let users = users::table
+                    .filter(users::id.eq_any(ids))
+                    .load::<User>(conn)
+                    .await?;
+
+                    Ok(users)
+                }.scope_boxed())
+                .await
+            },
+
+            Err(_) => Err(Error::BrokenTransactionManager),
+        }
+    }
+
     pub async fn by_username(
         username: &str,
         conn_pool: &Pool<AsyncMysqlConnection>,
@@ -103,14 +128,9 @@ impl User {
     ) -> Result<usize, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-                    let res = diesel::update(users::table.find(target_user_id))
-                    .set(users::access_level.eq(access_lvl))
-                    .execute(conn)
-                    .await?;
-            
-                    Ok(res)
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    User::update_access_level_conn(conn, target_user_id, access_lvl).scope_boxed()
+                })
                 .await
             },
 
@@ -118,6 +138,20 @@ impl User {
         }
     }
 
+    /// [`User::update_access_level`] on an open connection.
+    pub async fn update_access_level_conn(
+        conn: &mut AsyncMysqlConnection,
+        target_user_id: u64,
+        access_lvl: u8,
+    ) -> Result<usize, Error> {
+        let res = diesel::update(users::table.find(target_user_id))
+        .set(users::access_level.eq(access_lvl))
+        .execute(conn)
+        .await?;
+
+        Ok(res)
+    }
+
     pub async fn update_user(
         target_user_id: u64,
         access_lvl: u8,
@@ -127,28 +161,9 @@ impl User {
     ) -> Result<usize, Error> {
         match conn_pool.get().await {
             Ok(mut conn) => {
-                conn.transaction::<_, Error, _>(|conn| async move {
-                    let res = diesel::update(users::table.find(target_user_id))
-                    .set(users::access_level.eq(access_lvl))
-                    .execute(conn)
-                    .await?;
-
-                    if let Some(username) = username {
-                        let _ = diesel::update(users::table.find(target_user_id))
-                        .set(users::username.eq(username))
-                        .execute(conn)
-                        .await?;
-                    }
-
-                    if let Some(email) = email {
-                        let _ = diesel::update(users::table.find(target_user_id))
-                        .set(users::email.eq(email))
-                        .execute(conn)
-                        .await?;
-                    }
-            
-                    Ok(res)
-                }.scope_boxed())
+                conn.transaction::<_, Error, _>(|conn| {
+                    User::update_user_conn(conn, target_user_id, access_lvl, username, email).scope_boxed()
+                })
                 .await
             },
 
@@ -156,6 +171,37 @@ impl User {
         }
     }
 
+    /// [`User::update_user`] on an open connection, so that the change can
+    /// be logged in the same transaction.
+    pub async fn update_user_conn(
+        conn: &mut AsyncMysqlConnection,
+        target_user_id: u64,
+        access_lvl: u8,
+        username: Option<String>,
+        email: Option<String>,
+    ) -> Result<usize, Error> {
+        let res = diesel::update(users::table.find(target_user_id))
+        .set(users::access_level.eq(access_lvl))
+        .execute(conn)
+        .await?;
+
+        if let Some(username) = username {
+            let _ = diesel::update(users::table.find(target_user_id))
+            .set(users::username.eq(username))
+            .execute(conn)
+            .await?;
+        }
+
+        if let Some(email) = email {
+            let _ = diesel::update(users::table.find(target_user_id))
+            .set(users::email.eq(email))
+            .execute(conn)
+            .await?;
+        }
+
+        Ok(res)
+    }
+
     pub async fn count_users(
         conn_pool: &Pool<AsyncMysqlConnection>,
         target_username: Option<String>,
@@ -294,6 +340,24 @@ pub enum AccessLevel {
     Root = 255,
 }
 
+impl TryFrom<u8> for AccessLevel {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            10 => Ok(AccessLevel::Anonymous),
+            20 => Ok(AccessLevel::Registered),
+            30 => Ok(AccessLevel::PendingMember),
+            40 => Ok(AccessLevel::Member),
+            90 => Ok(AccessLevel::Moderator),
+            100 => Ok(AccessLevel::Admin),
+            200 => Ok(AccessLevel::Owner),
+            255 => Ok(AccessLevel::Root),
+            _ => Err(value),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UserData {
     pub id: u64,
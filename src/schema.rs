@@ -47,6 +47,12 @@ diesel::table! {
         ip_address -> Varchar,
         expires_at -> Datetime,
         created_at -> Datetime,
+        #[max_length = 49]
+        ip_range -> Nullable<Varchar>,
+        #[max_length = 16]
+        range_start -> Nullable<Varbinary>,
+        #[max_length = 16]
+        range_end -> Nullable<Varbinary>,
     }
 }
 
@@ -62,6 +68,7 @@ diesel::table! {
         thread_size_limit -> Unsigned<Integer>,
         captcha -> Bool,
         nsfw -> Bool,
+        flags -> Bool,
     }
 }
 
@@ -74,12 +81,74 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    chat_messages (id) {
+        id -> Unsigned<Bigint>,
+        room_id -> Unsigned<Integer>,
+        user_id -> Unsigned<Bigint>,
+        #[max_length = 255]
+        display_name -> Varchar,
+        body -> Text,
+        created_at -> Datetime,
+    }
+}
+
+diesel::table! {
+    chat_mod_actions (id) {
+        id -> Unsigned<Integer>,
+        moderator_id -> Unsigned<Bigint>,
+        target_id -> Nullable<Unsigned<Bigint>>,
+        #[max_length = 255]
+        room -> Nullable<Varchar>,
+        #[max_length = 16]
+        action -> Varchar,
+        details -> Nullable<Text>,
+        created_at -> Datetime,
+    }
+}
+
 diesel::table! {
     chat_rooms (id) {
         id -> Unsigned<Integer>,
         #[max_length = 255]
         name -> Varchar,
         access_level -> Unsigned<Tinyint>,
+        slow_mode_seconds -> Unsigned<Integer>,
+    }
+}
+
+diesel::table! {
+    chat_timeouts (user_id) {
+        user_id -> Unsigned<Bigint>,
+        moderator_id -> Unsigned<Bigint>,
+        expires_at -> Datetime,
+    }
+}
+
+diesel::table! {
+    login_failures (id) {
+        id -> Unsigned<Bigint>,
+        user_id -> Nullable<Unsigned<Bigint>>,
+        #[max_length = 255]
+        login -> Varchar,
+        #[max_length = 45]
+        ip_address -> Varchar,
+        created_at -> Datetime,
+    }
+}
+
+diesel::table! {
+    mod_actions (id) {
+        id -> Unsigned<Integer>,
+        moderator_id -> Unsigned<Bigint>,
+        #[max_length = 32]
+        action -> Varchar,
+        item_id -> Nullable<Unsigned<Bigint>>,
+        target_id -> Nullable<Unsigned<Bigint>>,
+        board_id -> Nullable<Unsigned<Integer>>,
+        details -> Nullable<Text>,
+        snapshot -> Nullable<Mediumtext>,
+        created_at -> Datetime,
     }
 }
 
@@ -100,6 +169,11 @@ diesel::table! {
         country_code -> Nullable<Varchar>,
         mod_note -> Nullable<Text>,
         created_at -> Datetime,
+        private_note -> Nullable<Text>,
+        deleted_at -> Nullable<Datetime>,
+        deleted_by -> Nullable<Unsigned<Bigint>>,
+        #[max_length = 32]
+        capcode -> Nullable<Varchar>,
     }
 }
 
@@ -113,8 +187,27 @@ diesel::table! {
 diesel::table! {
     reports (id) {
         id -> Unsigned<Integer>,
-        post_id -> Unsigned<Integer>,
+        post_id -> Nullable<Unsigned<Integer>>,
         reason -> Text,
+        created_at -> Datetime,
+        resolved_by -> Nullable<Unsigned<Bigint>>,
+        resolved_at -> Nullable<Datetime>,
+    }
+}
+
+diesel::table! {
+    role_capabilities (role_id, capability) {
+        role_id -> Unsigned<Integer>,
+        #[max_length = 32]
+        capability -> Varchar,
+    }
+}
+
+diesel::table! {
+    roles (id) {
+        id -> Unsigned<Integer>,
+        #[max_length = 32]
+        name -> Varchar,
     }
 }
 
@@ -128,6 +221,18 @@ diesel::table! {
         locked -> Bool,
         archived -> Bool,
         bump_time -> Datetime,
+        deleted_at -> Nullable<Datetime>,
+        deleted_by -> Nullable<Unsigned<Bigint>>,
+    }
+}
+
+diesel::table! {
+    user_roles (id) {
+        id -> Unsigned<Integer>,
+        user_id -> Unsigned<Bigint>,
+        role_id -> Unsigned<Integer>,
+        board_id -> Nullable<Unsigned<Integer>>,
+        created_at -> Datetime,
     }
 }
 
@@ -150,11 +255,19 @@ diesel::joinable!(application_reviews -> users (reviewer_id));
 diesel::joinable!(applications -> users (user_id));
 diesel::joinable!(attachments -> posts (id));
 diesel::joinable!(bans -> posts (post_id));
+diesel::joinable!(chat_messages -> chat_rooms (room_id));
+diesel::joinable!(chat_messages -> users (user_id));
+diesel::joinable!(login_failures -> users (user_id));
+diesel::joinable!(mod_actions -> boards (board_id));
 diesel::joinable!(posts -> threads (thread_id));
 diesel::joinable!(posts -> users (user_id));
 diesel::joinable!(reports -> posts (post_id));
+diesel::joinable!(role_capabilities -> roles (role_id));
 diesel::joinable!(threads -> boards (board_id));
 diesel::joinable!(threads -> users (user_id));
+diesel::joinable!(user_roles -> boards (board_id));
+diesel::joinable!(user_roles -> roles (role_id));
+diesel::joinable!(user_roles -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     application_reviews,
@@ -163,10 +276,18 @@ diesel::allow_tables_to_appear_in_same_query!(
     bans,
     boards,
     captchas,
+    chat_messages,
+    chat_mod_actions,
     chat_rooms,
+    chat_timeouts,
+    login_failures,
+    mod_actions,
     posts,
     replies,
     reports,
+    role_capabilities,
+    roles,
     threads,
+    user_roles,
     users,
 );
@@ -35,15 +35,26 @@ pub async fn submit_application(
     }
 }
 
+/// Closes the application and gives the applicant the access level it grants,
+/// on the caller's transaction so that the review can be logged with it.
 pub async fn review_application (
-    conn_pool: &Pool<AsyncMysqlConnection>,
+    conn: &mut AsyncMysqlConnection,
     application_id: u32,
     reviewer_id: u64,
     accept: bool,
 ) -> Result<Application, Error> {
     let timestamp = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap().naive_utc();
 
-    Application::review(conn_pool, application_id, reviewer_id, accept, timestamp).await
+    let application = Application::review_conn(conn, application_id, reviewer_id, accept, timestamp).await?;
+
+    let access_level = match accept {
+        true => AccessLevel::Member,
+        false => AccessLevel::Registered,
+    };
+
+    User::update_access_level_conn(conn, application.user_id, access_level as u8).await?;
+
+    Ok(application)
 }
 
 pub async fn is_reviewed(
@@ -1,6 +1,8 @@
+use std::sync::OnceLock;
+
 use actix_identity::Identity;
-use actix_web::{http::StatusCode, HttpMessage, HttpRequest};
-use chrono::Utc;
+use actix_web::{web, HttpMessage, HttpRequest};
+use chrono::{Duration, Utc};
 use diesel::result::Error;
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
 use password_hash::{Output, PasswordHash, PasswordVerifier, Salt, SaltString};
@@ -8,9 +10,9 @@ use pbkdf2::{pbkdf2_hmac, Algorithm, Params, Pbkdf2};
 use rand_core::{OsRng, RngCore};
 use sha2::{Digest, Sha256};
 
-use crate::models::{bans::Ban, users::{User, UserData}};
+use crate::{config::{Config, LoginConfig}, models::{bans::Ban, login_failures::{FailureCount, LoginFailure, LoginFailureModel}, users::{User, UserData}}};
 
-use super::users::create_anonymous_user;
+use super::{captchas::verify_captcha, ip::client_ip, users::create_anonymous_user};
 
 
 pub async fn resolve_user(
@@ -35,8 +37,12 @@ pub async fn resolve_user(
         },
     };
 
-    //TODO: configure for proxy
-    let ip_addr = request.peer_addr().unwrap().ip().to_string();
+    let ip_addr = match request.app_data::<web::Data<Config>>() {
+        Some(config) => client_ip(&request, &config.server.trusted_proxies),
+        None => request.peer_addr().map(|addr| addr.ip()),
+    }
+    .unwrap()
+    .to_string();
     let user_agent = request.headers().get("User-Agent")
     .map(|agent| agent.to_str())
     .map(|val| val.unwrap_or("").to_string())
@@ -62,67 +68,152 @@ pub async fn resolve_user(
     })
 }
 
-pub async fn login_by_username(
-    username: &str,
+/// Why a login was refused.
+#[derive(Debug, PartialEq)]
+pub enum LoginError {
+    /// Unknown account or wrong password, deliberately not told apart.
+    InvalidCredentials,
+    /// Too many failures on the account or from the address, with the
+    /// seconds left of the lockout.
+    LockedOut(i64),
+    /// A captcha has to be solved first, with the verification error when a
+    /// captcha was given.
+    CaptchaRequired(Option<String>),
+    Server,
+}
+
+impl From<Error> for LoginError {
+    fn from(_: Error) -> Self {
+        LoginError::Server
+    }
+}
+
+/// Checked against when the account doesn't exist, so that a login takes
+/// about as long either way. Hashed on first use with the configured
+/// iteration count.
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+/// Logs in with a username.
+///
+/// Failures are counted per account and per address. After
+/// `captcha_after` of them a captcha is required, and past the lockout
+/// limits attempts are refused for a time that doubles with every further
+/// failure. Failures on unknown accounts are kept by the given name, so
+/// they are indistinguishable from existing ones.
+pub async fn login_with_password(
+    login: &str,
     password: &str,
+    captcha: Option<(u64, String)>,
+    ip_address: &str,
+    config: &Config,
     conn_pool: &Pool<AsyncMysqlConnection>,
     request: HttpRequest,
-) -> Result<(), StatusCode> {
-    let user = User::by_username(username, conn_pool)
-    .await
-    .map_err(|err| match err {
-            Error::NotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    )?;
+) -> Result<(), LoginError> {
+    let user = match User::by_username(login, conn_pool).await {
+        Ok(user) => Some(user),
+        Err(Error::NotFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let security = &config.security;
+    let config = &config.login;
+
+    let user_id = user.as_ref().map(|user| user.id);
+    let login_key: String = login.to_lowercase().chars().take(255).collect();
+
+    let since = (Utc::now() - Duration::minutes(config.window_minutes)).naive_utc();
+    let account_failures = LoginFailure::count_by_account(user_id, &login_key, since, conn_pool).await?;
+    let ip_failures = LoginFailure::count_by_ip(ip_address, since, conn_pool).await?;
+
+    let lockout = lockout_wait(account_failures, config.lockout_after, config)
+    .max(lockout_wait(ip_failures, config.ip_lockout_after, config));
+
+    if let Some(wait) = lockout {
+        return Err(LoginError::LockedOut(wait));
+    }
+
+    if config.captcha_after > 0 && account_failures.count.max(ip_failures.count) >= config.captcha_after {
+        let (captcha_id, answer) = captcha.ok_or(LoginError::CaptchaRequired(None))?;
+
+        verify_captcha(conn_pool, captcha_id, answer)
+        .await
+        .map_err(|e| LoginError::CaptchaRequired(Some(e)))?;
+    }
+
+    let valid = match user.as_ref().and_then(|user| user.password_hash.as_deref()) {
+        Some(hash) => validate_password_pbkdf2(hash, password),
+        None => {
+            let dummy_hash = DUMMY_HASH.get_or_init(|| {
+                hash_password_pbkdf2("", security.pbkdf2_iterations)
+            });
 
-    let hash = match user.password_hash {
-        Some(pwd_hash) => pwd_hash,
-        None => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+            validate_password_pbkdf2(dummy_hash, password);
+            false
+        },
     };
 
-    match validate_password_pbkdf2(&hash, password) {
-        true => {
-            Identity::login(&request.extensions(), user.id.to_string()).unwrap();
-            Ok(())
+    let user = match (user, valid) {
+        (Some(user), true) => user,
+        _ => {
+            LoginFailureModel {
+                user_id,
+                login: &login_key,
+                ip_address,
+            }
+            .insert(since, conn_pool)
+            .await?;
+
+            return Err(LoginError::InvalidCredentials);
         },
-        false => Err(StatusCode::FORBIDDEN),
-    }
+    };
+
+    LoginFailure::clear_account(user.id, conn_pool).await?;
+    Identity::login(&request.extensions(), user.id.to_string()).unwrap();
+
+    Ok(())
 }
 
-pub async fn login_by_email(
-    email: &str,
-    password: &str,
+/// Whether the login form should ask for a captcha from this address.
+pub async fn login_needs_captcha(
+    ip_address: &str,
+    config: &LoginConfig,
     conn_pool: &Pool<AsyncMysqlConnection>,
-    request: HttpRequest,
-) -> Result<(), StatusCode> {
-    let user = User::by_email(email, conn_pool)
-    .await
-    .map_err(|err| match err {
-            Error::NotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    )?;
+) -> Result<bool, Error> {
+    if config.captcha_after == 0 {
+        return Ok(false);
+    }
 
-    let hash = match user.password_hash {
-        Some(pwd_hash) => pwd_hash,
-        None => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    };
+    let since = (Utc::now() - Duration::minutes(config.window_minutes)).naive_utc();
+    let ip_failures = LoginFailure::count_by_ip(ip_address, since, conn_pool).await?;
 
-    match validate_password_pbkdf2(&hash, password) {
-        true => {
-            Identity::login(&request.extensions(), user.id.to_string()).unwrap();
-            Ok(())
-        },
-        false => Err(StatusCode::FORBIDDEN),
+    Ok(ip_failures.count >= config.captcha_after)
+}
+
+/// Seconds left of the lockout caused by `failures`, if any. The first
+/// lockout starts at `limit` failures and each further one doubles it.
+fn lockout_wait(
+    failures: FailureCount,
+    limit: i64,
+    config: &LoginConfig,
+) -> Option<i64> {
+    if limit == 0 || failures.count < limit {
+        return None;
     }
+
+    let doublings = (failures.count - limit).min(30) as u32;
+    let lockout = config.lockout_seconds
+    .saturating_mul(1 << doublings)
+    .min(config.max_lockout_minutes * 60);
+
+    let wait = failures.last_failure?.and_utc().timestamp() + lockout - Utc::now().timestamp();
+
+    (wait > 0).then_some(wait)
 }
 
-pub fn hash_password_pbkdf2(password: &str) -> String {
+pub fn hash_password_pbkdf2(password: &str, iterations: u32) -> String {
     let mut salt_bytes = [0u8; Salt::RECOMMENDED_LENGTH];
     OsRng.fill_bytes(&mut salt_bytes);
 
-    let iterations = 5000;
     let password = password.as_bytes();
 
     let output = Output::init_with(Sha256::output_size(), |out| {
@@ -8,10 +8,11 @@ use crate::models::captchas::{Captcha, CaptchaModel};
 pub async fn new_captcha(
     conn_pool: &Pool<AsyncMysqlConnection>,
     answer: String,
+    expiry_minutes: i64,
 ) -> Result<Captcha, Error> {
     CaptchaModel {
         answer: &answer,
-        expires: &(Utc::now() + Duration::minutes(5)).naive_utc(),
+        expires: &(Utc::now() + Duration::minutes(expiry_minutes)).naive_utc(),
     }
     .insert(conn_pool)
     .await
@@ -11,6 +11,7 @@ pub async fn create_attachment(
     conn_pool: &Pool<AsyncMysqlConnection>,
     post_id: u32,
     attachment: TempFile,
+    thumbnail_size: u32,
 ) -> Option<()> {    
     let mime = match &attachment.content_type {
         Some(mime) => mime,
@@ -80,7 +81,7 @@ pub async fn create_attachment(
                 Err(_) => return None,
             };
             
-            let thumbnail = img.thumbnail(300, 300);
+            let thumbnail = img.thumbnail(thumbnail_size, thumbnail_size);
             let _ = thumbnail.save(&thumbnail_location);
             
             Some(())
@@ -132,4 +133,25 @@ pub fn display_filesize(
     } else {
         return format!("{} B", bytes);
     }
+}
+
+pub fn remove_attachment_files(
+    attachment: &Attachment,
+) {
+    let file_location = format!("{}/{}", &attachment.file_location, &attachment.file_name);
+    let thumbnail_location = format!("{}/{}", &attachment.thumbnail_location, &attachment.file_name);
+
+    match remove_file(file_location) {
+        Ok(_) => (),
+        Err(e) => {
+            println!("Error while removing file: {:?}", e);
+        },
+    };
+
+    match remove_file(thumbnail_location) {
+        Ok(_) => (),
+        Err(e) => {
+            println!("Error while removing file: {:?}", e);
+        },
+    };
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use serde::Deserialize;
+
+use crate::{config::FloodConfig, models::{error::UserError, posts::Post, users::AccessLevel}};
+
+use super::posts::PostError;
+
+
+/// Where identical messages are looked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateScope {
+    /// Posts on the same board.
+    #[default]
+    Board,
+    /// Posts on every board.
+    Global,
+}
+
+impl FromStr for DuplicateScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "board" => Ok(DuplicateScope::Board),
+            "global" => Ok(DuplicateScope::Global),
+            _ => Err(format!("unknown duplicate scope `{}`", s)),
+        }
+    }
+}
+
+/// A post about to be created.
+pub struct FloodCheck<'a> {
+    pub user_id: u64,
+    pub ip_address: &'a str,
+    pub message_hash: &'a str,
+    pub board_id: u32,
+    /// The post starts a new thread.
+    pub new_thread: bool,
+    pub user_access_level: u8,
+}
+
+/// Rejects posts made too soon after the previous post or thread of the same
+/// user or address, and messages identical to a recent post. Staff is exempt.
+pub async fn check_flood(
+    conn_pool: &Pool<AsyncMysqlConnection>,
+    config: &FloodConfig,
+    check: FloodCheck<'_>,
+) -> Result<(), PostError> {
+    if check.user_access_level >= AccessLevel::Moderator as u8 {
+        return Ok(());
+    }
+
+    if config.post_cooldown_seconds > 0 {
+        let last_post = Post::last_post_time(
+            check.user_id,
+            check.ip_address,
+            since(config.post_cooldown_seconds),
+            conn_pool,
+        ).await?;
+
+        if let Some(wait) = remaining(last_post, config.post_cooldown_seconds) {
+            return Err(flood_error(format!("Lähetät viestejä liian nopeasti! Odota vielä {}.", display_wait(wait))));
+        }
+    }
+
+    if check.new_thread && config.thread_cooldown_seconds > 0 {
+        let last_thread = Post::last_thread_time(
+            check.user_id,
+            check.ip_address,
+            since(config.thread_cooldown_seconds),
+            conn_pool,
+        ).await?;
+
+        if let Some(wait) = remaining(last_thread, config.thread_cooldown_seconds) {
+            return Err(flood_error(format!("Aloitat lankoja liian nopeasti! Odota vielä {}.", display_wait(wait))));
+        }
+    }
+
+    if config.duplicate_window_seconds > 0 {
+        let board_id = match config.duplicate_scope {
+            DuplicateScope::Board => Some(check.board_id),
+            DuplicateScope::Global => None,
+        };
+
+        let last_duplicate = Post::last_duplicate_time(
+            check.message_hash,
+            board_id,
+            since(config.duplicate_window_seconds),
+            conn_pool,
+        ).await?;
+
+        if let Some(wait) = remaining(last_duplicate, config.duplicate_window_seconds) {
+            return Err(flood_error(format!("Sama viesti on lähetetty äskettäin! Odota vielä {} ennen kuin lähetät sen uudelleen.", display_wait(wait))));
+        }
+    }
+
+    Ok(())
+}
+
+/// Waiting time in Finnish, rounded up to whole seconds, minutes or hours.
+pub fn display_wait(seconds: i64) -> String {
+    let (amount, one, many) = match seconds {
+        ..=59 => (seconds.max(1), "sekunti", "sekuntia"),
+        60..=3599 => ((seconds + 59) / 60, "minuutti", "minuuttia"),
+        _ => ((seconds + 3599) / 3600, "tunti", "tuntia"),
+    };
+
+    match amount {
+        1 => format!("1 {}", one),
+        _ => format!("{} {}", amount, many),
+    }
+}
+
+fn since(seconds: i64) -> NaiveDateTime {
+    (Utc::now() - Duration::seconds(seconds)).naive_utc()
+}
+
+/// Seconds left of a `window` that started at `last`, if any.
+fn remaining(last: Option<NaiveDateTime>, window: i64) -> Option<i64> {
+    let wait = last?.and_utc().timestamp() + window - Utc::now().timestamp();
+
+    (wait > 0).then_some(wait)
+}
+
+fn flood_error(error: String) -> PostError {
+    PostError::Flood(UserError { error })
+}
\ No newline at end of file
@@ -0,0 +1,52 @@
+use std::net::IpAddr;
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+
+use crate::config::GeoIpConfig;
+
+
+/// Country lookups against a local MaxMind format database.
+///
+/// Lookups are disabled when no database is configured, in which case every
+/// lookup returns `None`.
+#[derive(Default)]
+pub struct GeoIp {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Opens the database configured in `geoip.database`, if any.
+    pub fn open(config: &GeoIpConfig) -> Result<GeoIp, MaxMindDBError> {
+        let reader = match config.database {
+            Some(ref path) => Some(Reader::open_readfile(path)?),
+            None => None,
+        };
+
+        Ok(GeoIp { reader })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    /// ISO 3166-1 alpha-2 code of the country `ip_address` is located in.
+    pub fn country_code(&self, ip_address: &str) -> Option<String> {
+        let reader = self.reader.as_ref()?;
+        let ip = ip_address.parse::<IpAddr>().ok()?.to_canonical();
+
+        reader.lookup::<geoip2::Country>(ip).ok()?
+        .country?
+        .iso_code
+        .filter(|code| code.len() == 2 && code.bytes().all(|b| b.is_ascii_alphabetic()))
+        .map(|code| code.to_ascii_uppercase())
+    }
+}
+
+/// Flag emoji of a country code, made of two regional indicator symbols.
+pub fn flag(country_code: &str) -> String {
+    country_code
+    .chars()
+    .filter(|c| c.is_ascii_alphabetic())
+    .filter_map(|c| char::from_u32(0x1F1E6 + (c.to_ascii_uppercase() as u32 - 'A' as u32)))
+    .collect()
+}
\ No newline at end of file
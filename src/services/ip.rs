@@ -0,0 +1,210 @@
+use std::{net::{IpAddr, SocketAddr}, str::FromStr};
+
+use actix_web::{http::header::HeaderMap, HttpRequest};
+use ipnet::IpNet;
+use serde::Deserialize;
+
+
+/// Parses an address range in CIDR notation. A plain address is treated as
+/// a range containing only that address.
+pub fn parse_ip_range(range: &str) -> Result<IpNet, String> {
+    let range = range.trim();
+
+    if let Ok(net) = range.parse::<IpNet>() {
+        return Ok(net.trunc());
+    }
+
+    range.parse::<IpAddr>()
+    .map(IpNet::from)
+    .map_err(|_| format!("`{}` is not an IP address or CIDR range", range))
+}
+
+/// Reverse proxies whose forwarding headers are trusted.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<Vec<String>> for TrustedProxies {
+    type Error = String;
+
+    fn try_from(ranges: Vec<String>) -> Result<Self, Self::Error> {
+        ranges.iter()
+        .map(|range| parse_ip_range(range))
+        .collect::<Result<Vec<IpNet>, String>>()
+        .map(TrustedProxies)
+    }
+}
+
+/// Comma separated list, as used by environment overrides.
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+        .map(|range| range.trim())
+        .filter(|range| !range.is_empty())
+        .map(|range| range.to_owned())
+        .collect::<Vec<String>>()
+        .try_into()
+    }
+}
+
+/// Resolves the address of the client that made the request.
+///
+/// Forwarding headers are only honored when the request comes from a trusted
+/// proxy. The hop chain is then walked from the nearest hop outwards and the
+/// first address that is not a trusted proxy is the client, so addresses a
+/// client prepends to the headers itself are never used.
+pub fn client_ip(
+    request: &HttpRequest,
+    trusted_proxies: &TrustedProxies,
+) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+
+    Some(resolve_client_ip(peer, request.headers(), trusted_proxies))
+}
+
+pub fn resolve_client_ip(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted_proxies: &TrustedProxies,
+) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+
+    let mut hops = forwarded_hops(headers);
+
+    if hops.is_empty() {
+        hops = forwarded_for_hops(headers);
+    }
+
+    if hops.is_empty() {
+        return headers.get("X-Real-IP")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_hop)
+        .unwrap_or(peer);
+    }
+
+    let mut client = peer;
+
+    for hop in hops.iter().rev() {
+        match hop {
+            Some(ip) => {
+                client = *ip;
+
+                if !trusted_proxies.contains(ip) {
+                    break;
+                }
+            },
+            // An unparseable hop (e.g. `unknown`) can't be attributed to anyone
+            // further out, so the last known address is used.
+            None => break,
+        }
+    }
+
+    client
+}
+
+/// `for` parameters of RFC 7239 `Forwarded` headers, nearest hop last.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers.get_all("Forwarded")
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(|element| {
+        element.split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+        .map(|(_, value)| parse_hop(value))
+    })
+    .collect()
+}
+
+/// Entries of `X-Forwarded-For` headers, nearest hop last.
+fn forwarded_for_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers.get_all("X-Forwarded-For")
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(parse_hop)
+    .collect()
+}
+
+/// Parses a single hop, which may be quoted and carry a port:
+/// `192.0.2.1`, `192.0.2.1:4711`, `"[2001:db8::1]:4711"` or `2001:db8::1`.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(ip);
+    }
+
+    if let Ok(addr) = hop.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+
+    hop.strip_prefix('[')
+    .and_then(|hop| hop.strip_suffix(']'))
+    .and_then(|ip| ip.parse::<IpAddr>().ok())
+}
+
+/// Width of a range ban around the banned address.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanRange {
+    /// IPv4 /24 or IPv6 /64, usually a single subscriber.
+    Narrow,
+    /// IPv4 /16 or IPv6 /48, usually a whole site or ISP pool.
+    Wide,
+}
+
+impl BanRange {
+    pub fn prefix_len(&self, ip: &IpAddr) -> u8 {
+        match (self, ip) {
+            (BanRange::Narrow, IpAddr::V4(_)) => 24,
+            (BanRange::Wide, IpAddr::V4(_)) => 16,
+            (BanRange::Narrow, IpAddr::V6(_)) => 64,
+            (BanRange::Wide, IpAddr::V6(_)) => 48,
+        }
+    }
+}
+
+/// Bounds of a range ban in the form stored in `bans.range_start` and
+/// `bans.range_end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeBan {
+    pub cidr: String,
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+}
+
+/// Builds the range ban covering `ip_address`, or `None` when the address
+/// can't be parsed.
+pub fn range_ban(ip_address: &str, range: BanRange) -> Option<RangeBan> {
+    let ip = ip_address.parse::<IpAddr>().ok()?.to_canonical();
+    let net = IpNet::new(ip, range.prefix_len(&ip)).ok()?.trunc();
+
+    Some(RangeBan {
+        cidr: net.to_string(),
+        start: ip_key(&net.network()),
+        end: ip_key(&net.broadcast()),
+    })
+}
+
+/// Sortable 16-byte key of an address. IPv4 addresses are stored as
+/// IPv4-mapped IPv6 addresses so both families compare within one column.
+pub fn ip_key(ip: &IpAddr) -> Vec<u8> {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
\ No newline at end of file
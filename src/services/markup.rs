@@ -0,0 +1,305 @@
+use std::{collections::{HashMap, HashSet}, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::models::posts::QuoteTarget;
+
+
+/// `>>>/handle/` and `>>>/handle/123` at the start of the matched text.
+static BOARD_QUOTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^>>>/([a-zA-Z0-9]{1,8})/(\d{1,10})?").unwrap()
+});
+
+/// `>>123` at the start of the matched text.
+static POST_QUOTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^>>(\d{1,10})").unwrap()
+});
+
+/// Renders a post message as HTML for the thread, board and archive pages.
+///
+/// Everything is escaped first and only the markup below produces tags:
+/// - ```` ``` ```` fenced code blocks and `` `inline code` ``
+/// - `>greentext` lines
+/// - `[spoiler]spoilers[/spoiler]`, `**bold**` and `*italic*`
+/// - `http(s)://` links
+/// - `>>123` quotes and `>>>/handle/123` or `>>>/handle/` cross-board links
+///
+/// Quotes are only linked when found in `quotes`, anything else is rendered
+/// as a dead link.
+pub fn format_message(message: &str, quotes: &Quotes) -> String {
+    render(message, Some(quotes))
+}
+
+/// Same as [`format_message`] but without links or block elements, for
+/// previews that are already wrapped in a link.
+pub fn format_preview(message: &str) -> String {
+    render(message, None)
+}
+
+/// Posts and boards that can be linked to from the messages being rendered.
+#[derive(Debug, Default)]
+pub struct Quotes {
+    posts: HashMap<u32, QuoteTarget>,
+    boards: HashSet<String>,
+}
+
+impl Quotes {
+    pub fn new(
+        targets: Vec<QuoteTarget>,
+        board_handles: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            posts: targets.into_iter().map(|target| (target.post_id, target)).collect(),
+            boards: board_handles.into_iter().collect(),
+        }
+    }
+
+    /// Whether `>>post_id`, or `>>>/handle/post_id` when a handle is given,
+    /// points to a visible post.
+    pub fn has_post(&self, post_id: u32, handle: Option<&str>) -> bool {
+        match (self.posts.get(&post_id), handle) {
+            (Some(target), Some(handle)) => target.board_handle == handle,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    pub fn has_board(&self, handle: &str) -> bool {
+        self.boards.contains(handle)
+    }
+}
+
+/// A clickable `>>123` quote link.
+pub fn quote_link(post_id: u32) -> String {
+    format!(
+        r#"<span class="backlink" onClick="showPost({0})" onpointerenter="hintPost(this, {0})" onpointerleave="unhintPost(this)">&gt;&gt;{0}</span>"#,
+        post_id,
+    )
+}
+
+fn render(message: &str, quotes: Option<&Quotes>) -> String {
+    let lines: Vec<&str> = message.lines().collect();
+    let mut html = String::with_capacity(message.len() * 2);
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        // Fenced code block, only when it is closed.
+        if line.trim_start().starts_with("```") {
+            if let Some(end) = lines[i + 1..].iter().position(|l| l.trim() == "```") {
+                let code = lines[i + 1..i + 1 + end].join("\n");
+
+                match quotes {
+                    Some(_) => {
+                        html.push_str(r#"<pre class="code-block"><code>"#);
+                        html.push_str(&escape(&code));
+                        html.push_str("</code></pre>");
+                    },
+                    None => {
+                        if i > 0 {
+                            html.push('\n');
+                        }
+
+                        html.push_str("<code>");
+                        html.push_str(&escape(&code));
+                        html.push_str("</code>");
+                    },
+                }
+
+                i += end + 2;
+                continue;
+            }
+        }
+
+        if i > 0 && !html.ends_with("</pre>") {
+            html.push('\n');
+        }
+
+        if line.starts_with('>') && !line.starts_with(">>") {
+            html.push_str(r#"<span class="implying">"#);
+            html.push_str(&render_inline(line, quotes));
+            html.push_str("</span>");
+        } else {
+            html.push_str(&render_inline(line, quotes));
+        }
+
+        i += 1;
+    }
+
+    html
+}
+
+fn render_inline(text: &str, quotes: Option<&Quotes>) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+
+        if let Some((inner, len)) = delimited(rest, "`", "`") {
+            html.push_str("<code>");
+            html.push_str(&escape(inner));
+            html.push_str("</code>");
+            i += len;
+            continue;
+        }
+
+        if let Some((inner, len)) = delimited(rest, "[spoiler]", "[/spoiler]") {
+            html.push_str(r#"<span class="spoiler">"#);
+            html.push_str(&render_inline(inner, quotes));
+            html.push_str("</span>");
+            i += len;
+            continue;
+        }
+
+        if let Some((inner, len)) = emphasis(rest, "**") {
+            html.push_str("<b>");
+            html.push_str(&render_inline(inner, quotes));
+            html.push_str("</b>");
+            i += len;
+            continue;
+        }
+
+        if let Some((inner, len)) = emphasis(rest, "*") {
+            html.push_str("<i>");
+            html.push_str(&render_inline(inner, quotes));
+            html.push_str("</i>");
+            i += len;
+            continue;
+        }
+
+        if let Some(caps) = BOARD_QUOTE.captures(rest) {
+            let handle = &caps[1];
+            let label = escape(&caps[0]);
+
+            let post_id = caps.get(2).and_then(|id| id.as_str().parse::<u32>().ok());
+
+            match (quotes, post_id) {
+                (Some(quotes), Some(post_id)) if quotes.has_post(post_id, Some(handle)) => html.push_str(&format!(
+                    r#"<span class="backlink" onClick="showPost({0})" onpointerenter="hintPost(this, {0})" onpointerleave="unhintPost(this)">{1}</span>"#,
+                    post_id, label,
+                )),
+                (Some(quotes), None) if caps.get(2).is_none() && quotes.has_board(handle) => html.push_str(&format!(
+                    r#"<a class="backlink" href="/{}">{}</a>"#,
+                    handle, label,
+                )),
+                (Some(_), _) => html.push_str(&dead_link(&label)),
+                (None, _) => html.push_str(&format!(r#"<span class="backlink">{}</span>"#, label)),
+            }
+
+            i += caps[0].len();
+            continue;
+        }
+
+        if let Some(caps) = POST_QUOTE.captures(rest) {
+            match (quotes, caps[1].parse::<u32>()) {
+                (Some(quotes), Ok(post_id)) if quotes.has_post(post_id, None) => html.push_str(&quote_link(post_id)),
+                (Some(_), _) => html.push_str(&dead_link(&escape(&caps[0]))),
+                (None, _) => html.push_str(&format!(r#"<span class="backlink">{}</span>"#, escape(&caps[0]))),
+            }
+
+            i += caps[0].len();
+            continue;
+        }
+
+        if let Some(len) = url_len(text, i) {
+            let url = escape(&rest[..len]);
+
+            match quotes {
+                Some(_) => html.push_str(&format!(
+                    r#"<a class="link" href="{0}" target="_blank" rel="nofollow noopener noreferrer">{0}</a>"#,
+                    url,
+                )),
+                None => html.push_str(&format!(r#"<span class="link">{}</span>"#, url)),
+            }
+
+            i += len;
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap();
+        push_escaped(&mut html, c);
+        i += c.len_utf8();
+    }
+
+    html
+}
+
+/// A quote whose target doesn't exist or can't be seen.
+fn dead_link(label: &str) -> String {
+    format!(r#"<span class="deadlink">{}</span>"#, label)
+}
+
+/// Text between `open` and the next `close`, and the length of the whole match.
+fn delimited<'a>(text: &'a str, open: &str, close: &str) -> Option<(&'a str, usize)> {
+    let inner = text.strip_prefix(open)?;
+    let end = inner.find(close)?;
+
+    match end {
+        0 => None,
+        _ => Some((&inner[..end], open.len() + end + close.len())),
+    }
+}
+
+/// Like [`delimited`], but the content can't start or end with whitespace so
+/// that e.g. `2 * 3 * 4` stays plain text.
+fn emphasis<'a>(text: &'a str, marker: &str) -> Option<(&'a str, usize)> {
+    let inner = text.strip_prefix(marker)?;
+
+    if inner.starts_with(char::is_whitespace) || inner.starts_with('*') {
+        return None;
+    }
+
+    let end = inner.find(marker)?;
+    let content = &inner[..end];
+
+    if end == 0 || content.ends_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some((content, marker.len() * 2 + end))
+}
+
+/// Length of a `http://` or `https://` URL starting at `start`, which must
+/// not be in the middle of a word.
+fn url_len(text: &str, start: usize) -> Option<usize> {
+    let rest = &text[start..];
+
+    if !(rest.starts_with("http://") || rest.starts_with("https://")) {
+        return None;
+    }
+
+    if text[..start].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+        return None;
+    }
+
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"', ']']);
+
+    match url.ends_with("://") {
+        true => None,
+        false => Some(url.len()),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        push_escaped(&mut escaped, c);
+    }
+
+    escaped
+}
+
+fn push_escaped(html: &mut String, c: char) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        _ => html.push(c),
+    }
+}
\ No newline at end of file
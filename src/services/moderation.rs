@@ -0,0 +1,151 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::result::Error;
+use diesel_async::{
+    pooled_connection::deadpool::Pool,
+    scoped_futures::ScopedBoxFuture,
+    AsyncConnection,
+    AsyncMysqlConnection
+};
+
+use crate::models::{
+    bans::{Ban, BanModel},
+    error::UserError,
+    mod_actions::{snapshot_posts, ModActionKind, ModActionModel},
+    posts::{Post, PostData},
+    threads::{Thread, ThreadData}
+};
+
+use super::time::fi_datetime;
+
+
+/// Runs moderator actions in one transaction, so that an action and its
+/// mod log entry are written together or not at all.
+pub async fn transaction<'a, T, F>(
+    conn_pool: &Pool<AsyncMysqlConnection>,
+    actions: F,
+) -> Result<T, Error>
+where
+    F: for<'r> FnOnce(&'r mut AsyncMysqlConnection) -> ScopedBoxFuture<'a, 'r, Result<T, Error>> + Send + 'a,
+    T: Send + 'a,
+{
+    match conn_pool.get().await {
+        Ok(mut conn) => {
+            let conn: &mut AsyncMysqlConnection = &mut conn;
+            conn.transaction::<_, Error, _>(actions).await
+        },
+        Err(_) => Err(Error::BrokenTransactionManager),
+    }
+}
+
+/// Longest ban a moderator can give, in days.
+pub const MAX_BAN_DAYS: i64 = 3650;
+
+/// Expiry of a ban of `days` starting now, `None` unless it is between one
+/// day and [`MAX_BAN_DAYS`].
+pub fn ban_expiry(days: i64) -> Option<NaiveDateTime> {
+    if !(1..=MAX_BAN_DAYS).contains(&days) {
+        return None;
+    }
+
+    Duration::try_days(days)
+    .and_then(|duration| Utc::now().checked_add_signed(duration))
+    .map(|expires_at| expires_at.naive_utc())
+}
+
+/// Answer to a ban duration that [`ban_expiry`] refuses.
+pub fn invalid_ban_duration() -> UserError {
+    UserError {
+        error: format!("Bannin kesto voi olla 1–{} päivää!", MAX_BAN_DAYS),
+    }
+}
+
+/// Mod log details of a ban.
+pub fn ban_details(ban: &Ban) -> String {
+    let mut details = format!(
+        "{} (päättyy {})",
+        ban.reason.as_deref().unwrap_or_default(),
+        fi_datetime(ban.expires_at),
+    );
+
+    if let Some(ref ip_range) = ban.ip_range {
+        details.push_str(&format!(", osoitealue {}", ip_range));
+    }
+
+    details
+}
+
+/// Inserts the ban and logs it.
+pub async fn ban(
+    conn: &mut AsyncMysqlConnection,
+    ban_model: &BanModel<'_>,
+    board_id: Option<u32>,
+) -> Result<Ban, Error> {
+    let ban = ban_model.insert_conn(conn).await?;
+    let details = ban_details(&ban);
+
+    ModActionModel {
+        item_id: Some(ban.id.into()),
+        target_id: ban.user_id,
+        board_id,
+        details: Some(&details),
+        ..ModActionModel::new(ban.moderator_id, ModActionKind::BanUser)
+    }
+    .insert_conn(conn)
+    .await?;
+
+    Ok(ban)
+}
+
+/// Soft deletes the post. Deleting the posts of others is logged with a
+/// snapshot of the post.
+pub async fn delete_post(
+    conn: &mut AsyncMysqlConnection,
+    post: &PostData,
+    board_id: Option<u32>,
+    deleted_by: u64,
+) -> Result<(), Error> {
+    Post::soft_delete_post_conn(conn, post.post.id, deleted_by).await?;
+
+    if deleted_by != post.post.user_id {
+        let snapshot = snapshot_posts([post]);
+
+        ModActionModel {
+            item_id: Some(post.post.id.into()),
+            target_id: Some(post.post.user_id),
+            board_id,
+            snapshot: Some(&snapshot),
+            ..ModActionModel::new(deleted_by, ModActionKind::DeletePost)
+        }
+        .insert_conn(conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Soft deletes the thread. Deleting the threads of others is logged with a
+/// snapshot of the posts.
+pub async fn delete_thread(
+    conn: &mut AsyncMysqlConnection,
+    thread: &ThreadData,
+    deleted_by: u64,
+) -> Result<(), Error> {
+    Thread::soft_delete_thread_conn(conn, thread.thread.id, deleted_by).await?;
+
+    if deleted_by != thread.thread.user_id {
+        let snapshot = snapshot_posts(&thread.posts);
+
+        ModActionModel {
+            item_id: Some(thread.thread.id.into()),
+            target_id: Some(thread.thread.user_id),
+            board_id: Some(thread.thread.board_id),
+            details: Some(&thread.thread.title),
+            snapshot: Some(&snapshot),
+            ..ModActionModel::new(deleted_by, ModActionKind::DeleteThread)
+        }
+        .insert_conn(conn)
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use std::{fmt, str::FromStr};
+
+use actix_identity::IdentityExt;
+use actix_web::{dev::Payload, error::ErrorInternalServerError, http::StatusCode, web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+use futures_util::future::LocalBoxFuture;
+
+use crate::models::{error::UserError, roles::UserRole, users::{AccessLevel, UserData}};
+
+use super::authentication::resolve_user;
+
+
+/// Why an action was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionError {
+    /// The actor's level is too low for the action or the target.
+    Forbidden,
+    /// The requested level is not an [`AccessLevel`].
+    InvalidLevel(u8),
+}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PermissionError::Forbidden => write!(f, "Sinulla ei ole oikeutta tähän!"),
+            PermissionError::InvalidLevel(level) => write!(f, "Virheellinen käyttöoikeustaso {}!", level),
+        }
+    }
+}
+
+impl ResponseError for PermissionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PermissionError::Forbidden => StatusCode::FORBIDDEN,
+            PermissionError::InvalidLevel(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(UserError {
+            error: self.to_string(),
+        })
+    }
+}
+
+/// Requires the actor to be at least at `level`.
+pub fn require(actor_level: u8, level: AccessLevel) -> Result<(), PermissionError> {
+    match actor_level >= level as u8 {
+        true => Ok(()),
+        false => Err(PermissionError::Forbidden),
+    }
+}
+
+/// Staff can only act on users strictly below themselves, so that equals
+/// can't ban or demote each other.
+pub fn require_above(actor_level: u8, target_level: u8) -> Result<(), PermissionError> {
+    match actor_level > target_level {
+        true => Ok(()),
+        false => Err(PermissionError::Forbidden),
+    }
+}
+
+/// Parses a level the actor wants to give a user. Only levels strictly below
+/// the actor's own can be handed out.
+pub fn grantable_level(actor_level: u8, level: u8) -> Result<AccessLevel, PermissionError> {
+    let level = AccessLevel::try_from(level).map_err(PermissionError::InvalidLevel)?;
+
+    require_above(actor_level, level as u8)?;
+
+    Ok(level)
+}
+
+/// Changing another user's details or level, once the actor may manage
+/// users. They can modify, promote and demote users below them, to levels
+/// below them.
+pub fn authorize_user_modification(
+    actor_level: u8,
+    target_level: u8,
+    new_level: u8,
+) -> Result<AccessLevel, PermissionError> {
+    require_above(actor_level, target_level)?;
+
+    grantable_level(actor_level, new_level)
+}
+
+/// Banning a user, or lifting their ban, once the actor may ban.
+pub fn authorize_ban(actor_level: u8, target_level: u8) -> Result<(), PermissionError> {
+    require_above(actor_level, target_level)
+}
+
+/// Creating or editing a board or a chat room readable from `level` up.
+/// Nobody can lock themselves out of what they create.
+pub fn authorize_area(actor_level: u8, level: u8) -> Result<AccessLevel, PermissionError> {
+    let level = AccessLevel::try_from(level).map_err(PermissionError::InvalidLevel)?;
+
+    match level as u8 <= actor_level {
+        true => Ok(level),
+        false => Err(PermissionError::Forbidden),
+    }
+}
+
+/// Something a role lets its holders do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    DeletePost,
+    Ban,
+    Pin,
+    Lock,
+    HandleReports,
+    ManageBoards,
+    ManageChats,
+    ManageUsers,
+    ReviewApplications,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 9] = [
+        Capability::DeletePost,
+        Capability::Ban,
+        Capability::Pin,
+        Capability::Lock,
+        Capability::HandleReports,
+        Capability::ManageBoards,
+        Capability::ManageChats,
+        Capability::ManageUsers,
+        Capability::ReviewApplications,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::DeletePost => "delete_post",
+            Capability::Ban => "ban",
+            Capability::Pin => "pin",
+            Capability::Lock => "lock",
+            Capability::HandleReports => "handle_reports",
+            Capability::ManageBoards => "manage_boards",
+            Capability::ManageChats => "manage_chats",
+            Capability::ManageUsers => "manage_users",
+            Capability::ReviewApplications => "review_applications",
+        }
+    }
+
+    /// Everyone from this level up has the capability on every board, as
+    /// before roles existed.
+    fn implied_by(&self) -> AccessLevel {
+        match self {
+            Capability::DeletePost
+            | Capability::Ban
+            | Capability::Pin
+            | Capability::Lock
+            | Capability::HandleReports => AccessLevel::Moderator,
+            _ => AccessLevel::Admin,
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::ALL
+        .into_iter()
+        .find(|capability| capability.as_str() == s)
+        .ok_or_else(|| format!("unknown capability `{}`", s))
+    }
+}
+
+/// The user of a request and what they may do, from their access level and
+/// roles. Roles can be limited to a single board.
+///
+/// ```ignore
+/// pub async fn handle_thread_pin(path: web::Path<u32>, perms: Permissions, ...) -> impl Responder {
+///     if let Err(e) = perms.require(Capability::Pin, Some(board_id)) {
+///         return e.error_response();
+///     }
+///     ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Permissions {
+    pub user: UserData,
+    grants: Vec<(Capability, Option<u32>)>,
+}
+
+impl Permissions {
+    /// `grants` are capabilities from roles with the board they're limited to.
+    pub fn new(user: UserData, grants: Vec<(Capability, Option<u32>)>) -> Self {
+        Self { user, grants }
+    }
+
+    /// Whether the user may use `capability` on `board_id`, or site-wide when
+    /// no board is given. Roles of a single board don't count site-wide.
+    pub fn can(&self, capability: Capability, board_id: Option<u32>) -> bool {
+        if self.user.access_level >= capability.implied_by() as u8 {
+            return true;
+        }
+
+        self.grants
+        .iter()
+        .any(|(granted, scope)| *granted == capability && (scope.is_none() || *scope == board_id))
+    }
+
+    /// Whether the user has `capability` on any board, e.g. to show the tools.
+    pub fn can_anywhere(&self, capability: Capability) -> bool {
+        self.can(capability, None) || self.grants.iter().any(|(granted, _)| *granted == capability)
+    }
+
+    /// The moderation tools to show on the pages of a board.
+    pub fn board_tools(&self, board_id: u32) -> BoardTools {
+        BoardTools {
+            delete_post: self.can(Capability::DeletePost, Some(board_id)),
+            ban: self.can(Capability::Ban, Some(board_id)),
+            pin: self.can(Capability::Pin, Some(board_id)),
+            lock: self.can(Capability::Lock, Some(board_id)),
+            private_note: self.can(Capability::DeletePost, Some(board_id)),
+        }
+    }
+
+    /// Like [`Permissions::can`], but banned users can't do anything. Root
+    /// keeps its powers so that it can't be locked out.
+    pub fn require(&self, capability: Capability, board_id: Option<u32>) -> Result<(), PermissionError> {
+        if self.user.banned.is_some() && self.user.access_level != AccessLevel::Root as u8 {
+            return Err(PermissionError::Forbidden);
+        }
+
+        match self.can(capability, board_id) {
+            true => Ok(()),
+            false => Err(PermissionError::Forbidden),
+        }
+    }
+}
+
+/// Moderation tools available on a board.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoardTools {
+    pub delete_post: bool,
+    pub ban: bool,
+    pub pin: bool,
+    pub lock: bool,
+    /// Private notes go with deleting posts.
+    pub private_note: bool,
+}
+
+impl FromRequest for Permissions {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            let conn_pool = req.app_data::<web::Data<Pool<AsyncMysqlConnection>>>()
+            .cloned()
+            .ok_or_else(|| ErrorInternalServerError("database pool is not registered"))?;
+
+            let identity = req.get_identity().ok();
+
+            let user = resolve_user(identity, req, &conn_pool)
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+            // Capabilities unknown to this version are ignored.
+            let grants = UserRole::grants_by_user(user.id, &conn_pool)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .into_iter()
+            .filter_map(|(capability, board_id)| capability.parse().ok().map(|capability| (capability, board_id)))
+            .collect();
+
+            Ok(Permissions::new(user, grants))
+        })
+    }
+}
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use actix_multipart::form::tempfile::TempFile;
 use diesel::result::Error;
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
@@ -5,68 +7,246 @@ use regex::Regex;
 use sha2::{Digest, Sha256};
 use itertools::Itertools;
 
-use crate::models::{posts::{Post, PostInput}, threads::Thread};
+use crate::{config::FloodConfig, models::{boards::Board, error::UserError, posts::{Post, PostInput}, threads::Thread, users::{AccessLevel, User}}};
+
+use super::{files::create_attachment, flood::{check_flood, FloodCheck}, geoip::GeoIp, markup::Quotes};
+
+
+/// Why a post or thread was not created.
+#[derive(Debug)]
+pub enum PostError {
+    Database(Error),
+    /// Rejected by the flood guard, with a message for the poster.
+    Flood(UserError),
+}
 
-use super::files::create_attachment;
+impl From<Error> for PostError {
+    fn from(e: Error) -> Self {
+        PostError::Database(e)
+    }
+}
 
+/// A post submitted from the post or thread form.
+pub struct NewPost<'a> {
+    pub user_id: u64,
+    pub user_access_level: u8,
+    pub board_id: u32,
+    /// Access level of the board, which the post inherits.
+    pub access_level: u8,
+    pub message: String,
+    pub ip_address: String,
+    pub attachment: TempFile,
+    pub options: PostOptions,
+    /// Set when the board shows country flags.
+    pub geoip: Option<&'a GeoIp>,
+}
 
 pub async fn create_post_by_thread_id(
     conn_pool: &Pool<AsyncMysqlConnection>,
-    user_id: u64,
     thread_id: u32,
-    message: String,
-    ip_address: String,
-    attachment: TempFile,
-    access_level: u8,
-) -> Result<(), Error> {
-    let reply_ids = parse_backlinks(&message);
+    post: NewPost<'_>,
+    thumbnail_size: u32,
+    flood: &FloodConfig,
+) -> Result<(), PostError> {
+    let NewPost {
+        user_id,
+        user_access_level,
+        board_id,
+        access_level,
+        message,
+        ip_address,
+        attachment,
+        options,
+        geoip,
+    } = post;
 
     let mut hasher = Sha256::new();
     hasher.update(message.clone());
 
     let message_hash = format!("{:X}", hasher.finalize());
 
+    check_flood(conn_pool, flood, FloodCheck {
+        user_id,
+        ip_address: &ip_address,
+        message_hash: &message_hash,
+        board_id,
+        new_thread: false,
+        user_access_level,
+    }).await?;
+
+    let reply_ids = resolve_backlinks(conn_pool, &message, user_access_level).await?;
+    let country_code = geoip.and_then(|geoip| geoip.country_code(&ip_address));
+
+    let show_username = options.show_username && user_access_level >= AccessLevel::Registered as u8;
+
     let post = match Post::insert_post_by_thread_id(thread_id, &conn_pool, PostInput {
         user_id,
-        show_username: false,
+        show_username,
+        capcode: signed_capcode(show_username, user_access_level),
         message,
         message_hash,
         ip_address,
-        country_code: None,
+        country_code,
         reply_ids,
-        sage: false,
+        sage: options.sage,
         mod_note: None,
         access_level,
     }).await {
         Ok(post) => post,
-        Err(e) => return Err(e),
+        Err(e) => return Err(e.into()),
     };
 
-    match create_attachment(&conn_pool, post.id, attachment).await {
+    match create_attachment(&conn_pool, post.id, attachment, thumbnail_size).await {
         Some(_) => (),
         None => {
             // Delete post if attachment fails
             let _ = Post::delete_post(&conn_pool, post.id).await;
-            return Err(Error::NotFound); //TODO: better error handling
+            return Err(Error::NotFound.into()); //TODO: better error handling
         },
     }
 
-    let _ = Thread::bump_thread(&conn_pool, thread_id).await;
+    if !options.sage {
+        let _ = Thread::bump_thread(&conn_pool, thread_id).await;
+    }
 
     Ok(())
 }
 
+/// Options given in the options field of the post form.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PostOptions {
+    /// Reply without bumping the thread.
+    pub sage: bool,
+    /// Sign the post with the username of a registered user.
+    pub show_username: bool,
+}
+
+impl PostOptions {
+    /// Parses a list of options separated by spaces or commas, e.g.
+    /// `sage nimi`. Unknown options are ignored.
+    pub fn parse(options: &str) -> PostOptions {
+        let mut parsed = PostOptions::default();
+
+        for option in options.split(|c: char| c.is_whitespace() || c == ',') {
+            match option.to_lowercase().as_str() {
+                "sage" => parsed.sage = true,
+                "nimi" | "name" => parsed.show_username = true,
+                _ => (),
+            }
+        }
+
+        parsed
+    }
+}
+
+/// IDs of the posts quoted with `>>123` or `>>>/handle/123`.
 pub fn parse_backlinks(
     message: &str,
 ) -> Vec<u32> {
-    let re = Regex::new(r">>(\d+)").unwrap();
+    let re = Regex::new(r">>(?:>/[a-zA-Z0-9]{1,8}/)?(\d+)").unwrap();
 
     let matches: Vec<u32> = re
-    .find_iter(message)
-    .map(|m| (&m.as_str()[2..]).parse::<u32>().unwrap_or(0))
+    .captures_iter(message)
+    .map(|caps| caps[1].parse::<u32>().unwrap_or(0))
     .unique()
     .filter(|x| *x > 0)
     .collect();
 
     return matches
+}
+
+/// Quoted posts that exist and are visible at `access_level`. Quotes of
+/// anything else are left out of the replies and render as dead links.
+pub async fn resolve_backlinks(
+    conn_pool: &Pool<AsyncMysqlConnection>,
+    message: &str,
+    access_level: u8,
+) -> Result<Vec<u32>, Error> {
+    let targets = Post::quote_targets(&parse_backlinks(message), conn_pool, access_level).await?;
+
+    Ok(targets.into_iter().map(|target| target.post_id).collect())
+}
+
+/// Resolves every quote in `messages` for a viewer with `access_level`.
+pub async fn resolve_quotes<'a>(
+    conn_pool: &Pool<AsyncMysqlConnection>,
+    messages: impl IntoIterator<Item = &'a str>,
+    boards: &[Board],
+    access_level: u8,
+) -> Result<Quotes, Error> {
+    let post_ids: Vec<u32> = messages
+    .into_iter()
+    .flat_map(parse_backlinks)
+    .unique()
+    .collect();
+
+    let targets = Post::quote_targets(&post_ids, conn_pool, access_level).await?;
+
+    let board_handles = boards
+    .iter()
+    .filter(|board| board.access_level <= access_level)
+    .map(|board| board.handle.clone());
+
+    Ok(Quotes::new(targets, board_handles))
+}
+
+/// Staff title shown next to the name on posts signed at `access_level`.
+pub fn capcode(access_level: u8) -> Option<&'static str> {
+    match access_level {
+        level if level == AccessLevel::Root as u8 => Some("root"),
+        level if level >= AccessLevel::Owner as u8 => Some("omistaja"),
+        level if level >= AccessLevel::Admin as u8 => Some("admin"),
+        level if level >= AccessLevel::Moderator as u8 => Some("moderaattori"),
+        _ => None,
+    }
+}
+
+/// Capcode stored on a new post, so that it doesn't follow later changes to
+/// the author's access level.
+pub fn signed_capcode(show_username: bool, user_access_level: u8) -> Option<String> {
+    match show_username {
+        true => capcode(user_access_level).map(str::to_owned),
+        false => None,
+    }
+}
+
+/// Name of a user who signed a post.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub username: String,
+}
+
+/// Signatures of the authors of signed posts, keyed by user ID.
+#[derive(Debug, Default)]
+pub struct Signatures(HashMap<u64, Signature>);
+
+impl Signatures {
+    /// Signature of a post, if it was signed.
+    pub fn get(&self, user_id: u64, show_username: bool) -> Option<&Signature> {
+        match show_username {
+            true => self.0.get(&user_id),
+            false => None,
+        }
+    }
+}
+
+/// Looks up the signatures of the given authors of signed posts.
+pub async fn resolve_signatures(
+    conn_pool: &Pool<AsyncMysqlConnection>,
+    user_ids: impl IntoIterator<Item = u64>,
+) -> Result<Signatures, Error> {
+    let user_ids: Vec<u64> = user_ids.into_iter().unique().collect();
+
+    let signatures = User::by_ids(&user_ids, conn_pool).await?
+    .into_iter()
+    .filter_map(|user| {
+        let username = user.username?;
+
+        Some((user.id, Signature {
+            username,
+        }))
+    })
+    .collect();
+
+    Ok(Signatures(signatures))
 }
\ No newline at end of file
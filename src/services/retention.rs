@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use chrono::{Days, Utc};
+use diesel::result::Error;
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
+
+use crate::{config::RetentionConfig, models::{posts::Post, threads::Thread}};
+
+use super::files::remove_attachment_files;
+
+
+/// Permanently deletes posts and threads that were deleted more than
+/// `deleted_days` ago and removes their files. Returns the number of
+/// removed attachments.
+pub async fn purge_deleted(
+    conn_pool: &Pool<AsyncMysqlConnection>,
+    deleted_days: i64,
+) -> Result<usize, Error> {
+    let before = Utc::now().naive_utc() - Days::new(deleted_days.max(0) as u64);
+
+    let mut attachments = Thread::purge_deleted(conn_pool, before).await?;
+    attachments.extend(Post::purge_deleted(conn_pool, before).await?);
+
+    attachments.iter().for_each(remove_attachment_files);
+
+    Ok(attachments.len())
+}
+
+/// Purges expired deleted content every `purge_interval_minutes`, run on
+/// startup.
+pub async fn run_purge(
+    conn_pool: Pool<AsyncMysqlConnection>,
+    config: RetentionConfig,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.purge_interval_minutes * 60));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = purge_deleted(&conn_pool, config.deleted_days).await {
+            println!("Error while purging deleted posts: {:?}", e);
+        }
+    }
+}
@@ -1,31 +1,51 @@
-use actix_multipart::form::tempfile::TempFile;
 use diesel::result::Error;
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncMysqlConnection};
 use sha2::{Digest, Sha256};
 
-use crate::models::{posts::PostInput, threads::{Thread, ThreadInput}};
+use crate::{config::FloodConfig, models::{posts::PostInput, threads::{Thread, ThreadInput}, users::AccessLevel}};
 
-use super::{files::create_attachment, posts::parse_backlinks};
+use super::{files::create_attachment, flood::{check_flood, FloodCheck}, posts::{resolve_backlinks, signed_capcode, NewPost, PostError}};
 
 
 pub async fn create_thread(
     conn_pool: &Pool<AsyncMysqlConnection>,
-    user_id: u64,
-    board_id: u32,
     topic: String,
-    message: String,
-    ip_address: String,
-    attachment: TempFile,
-    access_level: u8,
+    post: NewPost<'_>,
     active_threads_limit: u32,
-) -> Result<(), Error> {
-    let reply_ids = parse_backlinks(&message);
+    thumbnail_size: u32,
+    flood: &FloodConfig,
+) -> Result<(), PostError> {
+    let NewPost {
+        user_id,
+        user_access_level,
+        board_id,
+        access_level,
+        message,
+        ip_address,
+        attachment,
+        options,
+        geoip,
+    } = post;
 
     let mut hasher = Sha256::new();
     hasher.update(message.clone());
 
     let message_hash = format!("{:X}", hasher.finalize());
 
+    check_flood(conn_pool, flood, FloodCheck {
+        user_id,
+        ip_address: &ip_address,
+        message_hash: &message_hash,
+        board_id,
+        new_thread: true,
+        user_access_level,
+    }).await?;
+
+    let reply_ids = resolve_backlinks(conn_pool, &message, user_access_level).await?;
+    let country_code = geoip.and_then(|geoip| geoip.country_code(&ip_address));
+
+    let show_username = options.show_username && user_access_level >= AccessLevel::Registered as u8;
+
     let thread_input = ThreadInput {
         board_id,
         title: topic,
@@ -34,12 +54,14 @@ pub async fn create_thread(
         archived: false,
         post: PostInput {
             user_id,
-            show_username: false,
+            show_username,
+            capcode: signed_capcode(show_username, user_access_level),
             message,
             message_hash,
             ip_address,
-            country_code: None,
+            country_code,
             reply_ids,
+            // Saging only affects bumping, which a new thread doesn't do.
             sage: false,
             mod_note: None,
             access_level,
@@ -48,14 +70,14 @@ pub async fn create_thread(
 
     let thread_info = match Thread::insert_thread(&conn_pool, thread_input, active_threads_limit).await {
         Ok(thread_info) => thread_info,
-        Err(e) => return Err(e),
+        Err(e) => return Err(e.into()),
     };
 
-    match create_attachment(&conn_pool, thread_info.1.id, attachment).await {
+    match create_attachment(&conn_pool, thread_info.1.id, attachment, thumbnail_size).await {
         Some(_) => (),
         None => {
             let _ = Thread::delete_thread(&conn_pool, thread_info.0.id).await;
-            return Err(Error::NotFound); //TODO: better error handling
+            return Err(Error::NotFound.into()); //TODO: better error handling
         },
     }
 
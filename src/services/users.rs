@@ -22,8 +22,9 @@ pub async fn create_anonymous_user(
 pub async fn update_root_user(
     conn_pool: &Pool<AsyncMysqlConnection>,
     password: &str,
+    pbkdf2_iterations: u32,
 ) -> Result<(), Error> {
-    let password_hash = hash_password_pbkdf2(password);
+    let password_hash = hash_password_pbkdf2(password, pbkdf2_iterations);
 
     let root_model = UserModel {
         access_level: AccessLevel::Root as u8,
@@ -50,8 +51,9 @@ pub async fn register_user(
     username: &str,
     email: &str,
     password: &str,
+    pbkdf2_iterations: u32,
 ) -> Result<(), Error> {
-    let password_hash = hash_password_pbkdf2(password);
+    let password_hash = hash_password_pbkdf2(password, pbkdf2_iterations);
 
     UserModel {
         access_level: AccessLevel::Registered as u8,
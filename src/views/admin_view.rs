@@ -11,6 +11,8 @@ pub struct AdminTemplate {
     pub errors: Vec<String>,
     pub boards: Vec<Board>,
     pub chat_rooms: Vec<ChatRoom>,
+    /// Sent back with the forms of the page.
+    pub csrf_token: String,
 }
 
 pub async fn render(
@@ -10,6 +10,8 @@ pub struct ApplicationReviewTemplate {
     pub access_level: u8,
     pub boards: Vec<Board>,
     pub application: ApplicationView,
+    /// Sent back with the forms of the page.
+    pub csrf_token: String,
 }
 
 pub async fn render(
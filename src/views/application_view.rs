@@ -6,6 +6,8 @@ use sailfish::TemplateOnce;
 #[template(path = "apply.stpl")]
 pub struct ApplicationTemplate {
     pub errors: Vec<String>,
+    /// Sent back with the forms of the page.
+    pub csrf_token: String,
 }
 
 pub async fn render(
@@ -0,0 +1,34 @@
+use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
+use sailfish::TemplateOnce;
+
+use crate::models::{boards::Board, threads::ArchivedThreadOutput};
+use crate::services::time::fi_datetime;
+use crate::services::markup::{format_message, Quotes};
+
+
+#[derive(TemplateOnce)]
+#[template(path = "archive.stpl")]
+pub struct ArchiveTemplate {
+    pub access_level: u8,
+    pub handle: String,
+    pub boards: Vec<Board>,
+    pub current_board: Board,
+    pub threads: Vec<ArchivedThreadOutput>,
+    pub quotes: Quotes,
+    pub pages: u64,
+    pub title: String,
+    pub from: String,
+    pub to: String,
+}
+
+pub async fn render(
+    template: ArchiveTemplate,
+) -> actix_web::Result<HttpResponse> {
+    let body = template
+    .render_once()
+    .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(HttpResponse::Ok()
+    .content_type("text/html; charset=utf-8")
+    .body(body))
+}
\ No newline at end of file
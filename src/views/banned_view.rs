@@ -3,6 +3,7 @@ use sailfish::TemplateOnce;
 
 use crate::services::time::fi_datetime;
 use crate::models::{bans::Ban, posts::Post};
+use crate::services::markup::format_preview;
 
 
 #[derive(TemplateOnce)]
@@ -2,6 +2,10 @@ use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
 use sailfish::TemplateOnce;
 
 use crate::models::{boards::Board, threads::ThreadCatalogOutput};
+use crate::services::markup::{format_message, Quotes};
+use crate::services::posts::Signatures;
+use crate::services::geoip::flag;
+use crate::services::permissions::BoardTools;
 
 
 #[derive(TemplateOnce)]
@@ -9,10 +13,14 @@ use crate::models::{boards::Board, threads::ThreadCatalogOutput};
 pub struct BoardTemplate {
     pub access_level: u8,
     pub user_id: u64,
+    /// Moderation tools of the user on this board.
+    pub tools: BoardTools,
     pub handle: String,
     pub boards: Vec<Board>,
     pub current_board: Board,
     pub threads: Vec<ThreadCatalogOutput>,
+    pub quotes: Quotes,
+    pub signatures: Signatures,
 }
 
 pub async fn render(
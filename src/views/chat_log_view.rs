@@ -0,0 +1,27 @@
+use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
+use sailfish::TemplateOnce;
+
+use crate::models::{boards::Board, chat_moderation::ChatModAction};
+use crate::services::time::fi_datetime;
+
+
+#[derive(TemplateOnce)]
+#[template(path = "chat_log.stpl")]
+pub struct ChatLogTemplate {
+    pub access_level: u8,
+    pub boards: Vec<Board>,
+    pub actions: Vec<ChatModAction>,
+    pub pages: u64,
+}
+
+pub async fn render(
+    template: ChatLogTemplate,
+) -> actix_web::Result<HttpResponse> {
+    let body = template
+    .render_once()
+    .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(HttpResponse::Ok()
+    .content_type("text/html; charset=utf-8")
+    .body(body))
+}
\ No newline at end of file
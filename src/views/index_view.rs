@@ -2,6 +2,7 @@ use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
 use sailfish::{TemplateOnce};
 
 use crate::models::{boards::Board, posts::PostPreview};
+use crate::services::markup::format_preview;
 
 
 #[derive(TemplateOnce)]
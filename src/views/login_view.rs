@@ -6,6 +6,10 @@ use sailfish::TemplateOnce;
 #[template(path = "login.stpl")]
 pub struct LoginTemplate {
     pub errors: Vec<String>,
+    /// Ask for a captcha after repeated failed logins.
+    pub captcha: bool,
+    /// Sent back with the forms of the page.
+    pub csrf_token: String,
 }
 
 pub async fn render(
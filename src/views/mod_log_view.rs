@@ -0,0 +1,32 @@
+use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
+use sailfish::TemplateOnce;
+
+use crate::models::{boards::Board, mod_actions::{ModAction, ModActionKind}};
+use crate::services::time::fi_datetime;
+
+
+#[derive(TemplateOnce)]
+#[template(path = "mod_log.stpl")]
+pub struct ModLogTemplate {
+    pub access_level: u8,
+    pub boards: Vec<Board>,
+    pub actions: Vec<ModAction>,
+    pub pages: u64,
+    pub moderator: String,
+    pub action: String,
+    pub target: String,
+    pub from: String,
+    pub to: String,
+}
+
+pub async fn render(
+    template: ModLogTemplate,
+) -> actix_web::Result<HttpResponse> {
+    let body = template
+    .render_once()
+    .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(HttpResponse::Ok()
+    .content_type("text/html; charset=utf-8")
+    .body(body))
+}
\ No newline at end of file
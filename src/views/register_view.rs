@@ -6,6 +6,8 @@ use sailfish::TemplateOnce;
 #[template(path = "register.stpl")]
 pub struct RegisterTemplate {
     pub errors: Vec<String>,
+    /// Sent back with the forms of the page.
+    pub csrf_token: String,
 }
 
 pub async fn render(
@@ -0,0 +1,31 @@
+use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
+use sailfish::TemplateOnce;
+
+use crate::models::{boards::Board, reports::ReportView};
+use crate::services::time::fi_datetime;
+use crate::services::markup::{format_message, Quotes};
+
+
+#[derive(TemplateOnce)]
+#[template(path = "reports.stpl")]
+pub struct ReportListTemplate {
+    pub access_level: u8,
+    pub boards: Vec<Board>,
+    pub reports: Vec<ReportView>,
+    pub quotes: Quotes,
+    pub pages: u64,
+    pub board_filter: Option<String>,
+    pub resolved: bool,
+}
+
+pub async fn render(
+    template: ReportListTemplate,
+) -> actix_web::Result<HttpResponse> {
+    let body = template
+    .render_once()
+    .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(HttpResponse::Ok()
+    .content_type("text/html; charset=utf-8")
+    .body(body))
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
+use sailfish::TemplateOnce;
+
+use crate::models::{boards::Board, posts::SearchResult};
+use crate::services::time::fi_datetime;
+use crate::services::markup::{format_message, Quotes};
+
+
+#[derive(TemplateOnce)]
+#[template(path = "search.stpl")]
+pub struct SearchTemplate {
+    pub access_level: u8,
+    pub boards: Vec<Board>,
+    pub results: Vec<SearchResult>,
+    pub quotes: Quotes,
+    pub pages: u64,
+    pub query: String,
+    pub board: String,
+    pub from: String,
+    pub to: String,
+    pub has_attachment: bool,
+}
+
+pub async fn render(
+    template: SearchTemplate,
+) -> actix_web::Result<HttpResponse> {
+    let body = template
+    .render_once()
+    .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(HttpResponse::Ok()
+    .content_type("text/html; charset=utf-8")
+    .body(body))
+}
\ No newline at end of file
@@ -4,6 +4,10 @@ use sailfish::TemplateOnce;
 use crate::models::{boards::Board, threads::ThreadData};
 use crate::services::time::fi_datetime;
 use crate::services::files::display_filesize;
+use crate::services::markup::{format_message, quote_link, Quotes};
+use crate::services::posts::Signatures;
+use crate::services::geoip::flag;
+use crate::services::permissions::BoardTools;
 
 
 #[derive(TemplateOnce)]
@@ -11,9 +15,13 @@ use crate::services::files::display_filesize;
 pub struct ThreadTemplate {
     pub access_level: u8,
     pub user_id: u64,
+    /// Moderation tools of the user on this board.
+    pub tools: BoardTools,
     pub boards: Vec<Board>,
     pub current_board: Board,
     pub thread: ThreadData,
+    pub quotes: Quotes,
+    pub signatures: Signatures,
 }
 
 pub async fn render(
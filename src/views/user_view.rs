@@ -1,18 +1,24 @@
 use actix_web::{error::InternalError, http::StatusCode, HttpResponse};
 use sailfish::TemplateOnce;
 
-use crate::models::{bans::Ban, boards::Board, users::User};
+use crate::models::{bans::Ban, boards::Board, roles::{Role, UserRoleView}, users::User};
 
 use crate::services::time::fi_datetime;
+use crate::services::markup::quote_link;
 
 
 #[derive(TemplateOnce)]
 #[template(path = "user.stpl")]
 pub struct UserTemplate {
     pub access_level: u8,
+    /// Whether the viewer may change the user and their roles.
+    pub manage_users: bool,
     pub boards: Vec<Board>,
     pub user: User,
     pub bans: Vec<(Ban, User)>,
+    pub user_roles: Vec<UserRoleView>,
+    /// Roles that can be given.
+    pub roles: Vec<Role>,
 }
 
 pub async fn render(
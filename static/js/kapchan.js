@@ -4,6 +4,15 @@ const kapchanState = {
   timeout: null,
 };
 
+// Token of the session, required by every state-changing request.
+const csrfToken = () => {
+  const cookie = document.cookie
+  .split("; ")
+  .find(cookie => cookie.startsWith("kapchan-csrf="));
+
+  return cookie ? cookie.substring("kapchan-csrf=".length) : "";
+}
+
 const replyUser = (id) => {
   const textArea = document.getElementById("post-text-area");
 
@@ -64,6 +73,9 @@ const searchUser = () => {
 const deleteChat = (id) => {
   fetch(new Request("/delete-chat/" + id, {
     method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -85,6 +97,47 @@ const createChat = () => {
   fetch(new Request("/create-chat", {
     method: "POST",
     headers: {
+      'X-CSRF-Token': csrfToken(),
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify({
+      name: name,
+      access_level: access_level,
+    })
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const openChatEdit = (e) => {
+  const bf = document.getElementById("chat-edit-form");
+
+  bf.elements["id"].value = e.dataset.id;
+  bf.elements["title"].value = e.dataset.name;
+  bf.elements["access_level"].value = e.dataset.accessLevel;
+
+  toggleContainerById('chat-edit', 'flex');
+}
+
+const editChat = () => {
+  const bf = document.getElementById("chat-edit-form");
+  const data = new FormData(bf);
+
+  let id = Number(data.get("id"));
+  let access_level = Number(data.get("access_level"));
+  let name = data.get("title");
+
+  if (!id || !access_level || !name) return;
+
+  fetch(new Request("/edit-chat/" + id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
       'Accept': 'application/json',
       'Content-Type': 'application/json'
     },
@@ -113,12 +166,14 @@ const banUserById = (user_id) => {
   fetch(new Request("/ban-user-by-id/" + user_id, {
     method: "POST",
     headers: {
+      'X-CSRF-Token': csrfToken(),
       'Accept': 'application/json',
       'Content-Type': 'application/json'
     },
     body: JSON.stringify({
       ban_duration_days: ban_duration,
-      reason: reason
+      reason: reason,
+      ban_range: data.get("ban_range") || null
     })
   }))
   .then(res => {
@@ -146,6 +201,7 @@ const modifyUserById = (user_id) => {
   fetch(new Request("/modify-user/" + user_id, {
     method: "POST",
     headers: {
+      'X-CSRF-Token': csrfToken(),
       'Accept': 'application/json',
       'Content-Type': 'application/json'
     },
@@ -172,10 +228,102 @@ const banUserByPostId = () => {
   fetch(new Request("/ban-user-by-post/" + post_id, {
     method: "POST",
     headers: {
+      'X-CSRF-Token': csrfToken(),
       'Accept': 'application/json',
       'Content-Type': 'application/json'
     },
     body: JSON.stringify({
+      ban_duration_days: ban_duration,
+      reason: reason,
+      ban_range: data.get("ban_range") || null,
+      mod_note: data.get("public_note") ? data.get("mod_note") : null
+    })
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const reportPost = (post_id) => {
+  let reason = prompt("Ilmiannon syy:");
+
+  if (!reason) return;
+
+  fetch(new Request("/report-post", {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify({
+      post_id: post_id,
+      reason: reason
+    })
+  }))
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const editPrivateNote = (post_id, e) => {
+  let note = prompt("Muistiinpano (vain ylläpidolle):", e.dataset.note);
+
+  if (note === null) return;
+
+  fetch(new Request("/post-note/" + post_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify({
+      note: note
+    })
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const dismissReport = (report_id) => {
+  fetch(new Request("/dismiss-report/" + report_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const resolveReport = (report_id) => {
+  const rf = document.getElementById("report-form-" + report_id);
+  const data = new FormData(rf);
+
+  let ban_duration = Number(data.get("ban_duration"));
+  let reason = data.get("reason");
+
+  fetch(new Request("/resolve-report/" + report_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify({
+      delete_post: data.has("delete_post"),
       ban_duration_days: ban_duration,
       reason: reason
     })
@@ -219,11 +367,11 @@ const hintPost = (element, id) => {
       const container = document.createElement("div");
       container.classList.add("highlight-container");
   
-      container.innerHTML = `<div class="thread-post"><div class="thread-post-info"><p class="post-info"><span class="username">Anonyymi</span><span class="time"></span> <span class="post-id-column">No. <span class="post-id"></span></span></p></div><div class="thread-post-file-info" hidden><p class="file-info"></p></div><div class="thread-post-content"><div class="thread-post-file" hidden></div><p class="post-message soft-render"></p></div></div>`;
+      container.innerHTML = `<div class="thread-post"><div class="thread-post-info"><p class="post-info"><span class="username">Anonyymi</span><span class="time"></span> <span class="post-id-column">No. <span class="post-id"></span></span></p></div><div class="thread-post-file-info" hidden><p class="file-info"></p></div><div class="thread-post-content"><div class="thread-post-file" hidden></div><div class="post-message"></div></div></div>`;
   
       container.querySelector(".time").textContent = post.post_date;
       container.querySelector(".post-id").textContent = post.post_id;
-      container.querySelector(".post-message").textContent = post.message;
+      container.querySelector(".post-message").innerHTML = post.message_html;
   
       if (post.attachment) {
         let file_info_cont = container.querySelector(".thread-post-file-info");
@@ -238,7 +386,6 @@ const hintPost = (element, id) => {
       }
 
       element.append(container);
-      softRenderMessages(container);
     })
     .catch((error) => {
     });
@@ -257,7 +404,10 @@ const unhintPost = (element) => {
 
 const pinThread = (thread_id) => {
   fetch(new Request("/pin-thread/" + thread_id, {
-    method: "GET",
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -269,7 +419,10 @@ const pinThread = (thread_id) => {
 
 const unpinThread = (thread_id) => {
   fetch(new Request("/unpin-thread/" + thread_id, {
-    method: "GET",
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -283,6 +436,7 @@ const lockThread = (thread_id, lock_status) => {
   fetch(new Request("/lock-thread/" + thread_id, {
     method: "POST",
     headers: {
+      'X-CSRF-Token': csrfToken(),
       'Accept': 'application/json',
       'Content-Type': 'application/json'
     },
@@ -298,9 +452,70 @@ const lockThread = (thread_id, lock_status) => {
   });
 }
 
+const unarchiveThread = (thread_id) => {
+  fetch(new Request("/unarchive-thread/" + thread_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const purgeArchive = (handle) => {
+  const bf = document.getElementById("archive-purge-form");
+  const data = new FormData(bf);
+
+  let before = data.get("before");
+  let question = before ? "Poistetaanko arkistoidut langat ennen " + before + "?" : "Poistetaanko kaikki arkistoidut langat?";
+
+  if (!confirm(question)) return;
+
+  fetch(new Request("/purge-archive/" + handle, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify({
+      before: before,
+    })
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
 const deleteThread = (thread_id) => {
   fetch(new Request("/delete-thread/" + thread_id, {
     method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const restoreThread = (thread_id) => {
+  fetch(new Request("/restore-thread/" + thread_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -313,6 +528,53 @@ const deleteThread = (thread_id) => {
 const deleteBan = (ban_id) => {
   fetch(new Request("/delete-ban/" + ban_id, {
     method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const assignRole = (user_id) => {
+  const rf = document.getElementById("user-role-form");
+  const data = new FormData(rf);
+
+  let object = {
+    role_id: Number(data.get("role_id"))
+  };
+
+  let board_id = data.get("board_id");
+
+  if (board_id) object.board_id = Number(board_id);
+
+  fetch(new Request("/user-roles/" + user_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify(object)
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const removeRole = (user_role_id) => {
+  fetch(new Request("/delete-user-role/" + user_role_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -325,6 +587,9 @@ const deleteBan = (ban_id) => {
 const deleteBoard = (board_id) => {
   fetch(new Request("/delete-board/" + board_id, {
     method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -337,6 +602,24 @@ const deleteBoard = (board_id) => {
 const deletePost = (post_id) => {
   fetch(new Request("/delete-post/" + post_id, {
     method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
+  }))
+  .then(res => {
+    window.location.reload();
+  })
+  .catch((error) => {
+    console.log(error)
+  });
+}
+
+const restorePost = (post_id) => {
+  fetch(new Request("/restore-post/" + post_id, {
+    method: "POST",
+    headers: {
+      'X-CSRF-Token': csrfToken(),
+    },
   }))
   .then(res => {
     window.location.reload();
@@ -365,6 +648,9 @@ const scrollToBottom = () => {
 const logout = (event) => {
     fetch(new Request("/logout", {
             method: "POST",
+            headers: {
+                'X-CSRF-Token': csrfToken(),
+            },
         })
     )
     .then(() => location.replace("/"));
@@ -411,6 +697,10 @@ const fetchCaptcha = () => {
         cc.innerHTML = "";
         cc.appendChild(captcha);
         kapchanState.current_captcha = captcha_data.id;
+
+        // Plain forms, such as the login form, submit the id themselves.
+        const ci = document.getElementById("captcha-id");
+        if (ci) ci.value = captcha_data.id;
     });
 }
 
@@ -433,13 +723,16 @@ const submitPost = () => {
 
     fetch(window.location.href, {
         method: "POST",
+        headers: {
+            'X-CSRF-Token': csrfToken(),
+        },
         body: data,
     })
     .then((res) => {
         if (res.ok) {
             pf.reset();
             location.reload();
-        } else if (res.status == 403) {
+        } else if (res.status == 403 || res.status == 429) {
             res.json()
             .then(json => {
                 let errContainer = document.getElementById("err-container");
@@ -463,38 +756,6 @@ const enlargeImage = (container_id, image_id) => {
     }
 }
 
-const softRenderMessages = (element) => {
-  element.querySelectorAll(".soft-render").forEach((msg) => {
-    let text = msg.textContent;
-
-    //Regex strings
-	let find = [
-    /&/g,
-		/<(.*?)>/g,
-    /^(?=>[^>])>([^\r\n]+)/gm,
-    />>(\d+)/g,
-		/\[spoiler\](.*?)\[\/spoiler\]/g,
-    /(([https?|ftp]+:\/\/)([^\s/?\.#-]+\.?)+(\/[^\s]*)?)/gi,
-	];
-
-	//Regex string replacements
-	let replace = [
-    '&amp;',
-		'&lt;$1&gt;',
-    '<span class="implying">&gt;$1</span>',
-    '<span class="backlink">&gt;&gt;$1</span>',
-		'<span class="spoiler">$1</span>',
-    '<a class="link" href="$1">$1</a>',
-	];
-
-    for (let i =0; i < find.length; i++) {
-	  text = text.replace(find[i], replace[i]);
-	}
-
-    msg.innerHTML = text;
-  })
-}
-
 const openMobileMenu = () => {
   const mobileMenu = document.getElementById("m-m");
   const mobileCanvas = document.getElementById("m-c");
@@ -550,38 +811,6 @@ document.addEventListener("DOMContentLoaded", (event) => {
     slider.addEventListener('mouseleave', stopDragging, false);
   }
 
-  document.querySelectorAll(".msg-lbl").forEach((msg) => {
-    let text = msg.textContent;
-
-    //Regex strings
-	let find = [
-    /&/g,
-		/<(.*?)>/g,
-    /^(?=>[^>])>([^\r\n]+)/gm,
-    />>(\d+)/g,
-		/\[spoiler\](.*?)\[\/spoiler\]/g,
-    /(([https?|ftp]+:\/\/)([^\s/?\.#-]+\.?)+(\/[^\s]*)?)/gi,
-	];
-
-	//Regex string replacements
-	let replace = [
-    '&amp;',
-		'&lt;$1&gt;',
-    '<span class="implying">&gt;$1</span>',
-    '<span class="backlink" onClick="showPost($1)" onpointerenter="hintPost(this, $1)" onpointerleave="unhintPost(this)">&gt;&gt;$1</span>',
-		'<span class="spoiler">$1</span>',
-    '<a class="link" href="$1">$1</a>',
-	];
-
-    for (let i =0; i < find.length; i++) {
-	  text = text.replace(find[i], replace[i]);
-	}
-
-    msg.innerHTML = text;
-  })
-
-  softRenderMessages(document);
-  
   if (window.location.href.includes("#p")) {
     let post_id = window.location.href.match(/p(\d+)/g)[0];
     if (post_id) {
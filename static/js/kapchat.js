@@ -1,8 +1,9 @@
 const kapchatState = {
   socket: null,
   rooms: null,
-  users: [],
+  users: new Map(),
   current_room: null,
+  keep_scroll: false,
 };
 
 const connect = () => {
@@ -19,39 +20,48 @@ const connect = () => {
       kapchatState.socket.send(JSON.stringify({
         event: 2,
       }));
-
-      kapchatState.socket.send(JSON.stringify({
-        event: 3,
-      }));
     }
 
     kapchatState.socket.onmessage = ev => {
       let message = JSON.parse(ev.data);
 
       switch (message.event) {
+        case 9:
+          addHistory(message.room, message.data, message.older);
+          break;
+
+        case 14:
+        case 12:
+        case 10:
+        case 8:
         case 7:
         case 6:
           displayTimeout(message.message);
           break;
 
+        case 13:
+          removeMessages(message.ids);
+          break;
+
+        case 11:
         case 5:
           updateChatRooms(message.data);
           break;
 
         case 4:
-          updateUsers(message.data);
+          updateUsers(message.room, message.data);
           break;
         
         case 3:
-          addMessage(message.username, message.message, message.room)
+          addMessage(message.id, message.username, message.message, message.room)
           break;
 
         case 2:
-          deleteUser(message.username);
+          deleteUser(message.room, message.username);
           break;
 
         case 1:
-          addUser(message.username);
+          addUser(message.room, message.username);
           break;
 
         default:
@@ -73,14 +83,85 @@ const displayTimeout = (msg) => {
   container.appendChild(error);
 }
 
-const addMessage = (username, message, roomm) => {
+const addMessage = (id, username, message, roomm) => {
   let room = kapchatState.rooms.get(roomm);
-  room.add({
+
+  if (!room) {
+    return;
+  }
+
+  room.messages.push({
+    id: id,
     username: username,
     message: message,
   });
 
-  renderRoom(roomm);
+  if (roomm == kapchatState.current_room) {
+    renderRoom(roomm);
+  }
+}
+
+const addHistory = (roomm, messages, older) => {
+  let room = kapchatState.rooms.get(roomm);
+
+  if (!room) {
+    return;
+  }
+
+  let history = messages.map(message => ({
+    id: message.id,
+    username: message.username,
+    message: message.message,
+  }));
+
+  if (older) {
+    room.messages = history.concat(room.messages);
+  } else {
+    room.messages = history;
+  }
+
+  room.loading = false;
+  room.exhausted = messages.length == 0;
+
+  if (roomm == kapchatState.current_room) {
+    if (older) {
+      let container = document.getElementById("chat-messages");
+      let distanceFromBottom = container.scrollHeight - container.scrollTop;
+
+      kapchatState.keep_scroll = true;
+      renderRoom(roomm);
+      container.scrollTo(0, container.scrollHeight - distanceFromBottom);
+    } else {
+      renderRoom(roomm);
+    }
+  }
+}
+
+const removeMessages = (ids) => {
+  kapchatState.rooms.forEach((room) => {
+    room.messages = room.messages.filter(message => !ids.includes(message.id));
+  });
+
+  if (kapchatState.current_room) {
+    renderRoom(kapchatState.current_room);
+  }
+}
+
+const loadOlderMessages = () => {
+  let roomm = kapchatState.current_room;
+  let room = kapchatState.rooms && kapchatState.rooms.get(roomm);
+
+  if (!room || room.loading || room.exhausted || room.messages.length == 0) {
+    return;
+  }
+
+  room.loading = true;
+
+  kapchatState.socket.send(JSON.stringify({
+    event: 6,
+    room: roomm,
+    before: room.messages[0].id,
+  }));
 }
 
 const renderRoom = (roomm) => {
@@ -88,7 +169,7 @@ const renderRoom = (roomm) => {
     let container = document.querySelector(".chat-messages");
     container.innerHTML = "";
 
-    room.forall(message => {
+    room.messages.forEach(message => {
       let chatTextCont = document.createElement("div");
       chatTextCont.classList.add("chat-text-cont");
 
@@ -107,22 +188,32 @@ const renderRoom = (roomm) => {
     })
 }
 
-const deleteUser = (user) => {
-  let index = kapchatState.users.indexOf(user);
+const deleteUser = (room, user) => {
+  let users = kapchatState.users.get(room) || [];
+  let index = users.indexOf(user);
 
   if (index != -1) {
-    kapchatState.users.splice(index, 1);
-    updateUsers(kapchatState.users);
+    users.splice(index, 1);
+    updateUsers(room, users);
   }
 }
 
-const addUser = (user) => {
-  kapchatState.users.push(user);
-  updateUsers(kapchatState.users);
+const addUser = (room, user) => {
+  let users = kapchatState.users.get(room) || [];
+  users.push(user);
+  updateUsers(room, users);
+}
+
+const updateUsers = (room, users) => {
+  kapchatState.users.set(room, users);
+
+  if (room == kapchatState.current_room) {
+    renderUsers(room);
+  }
 }
 
-const updateUsers = (users) => {
-  kapchatState.users = users;
+const renderUsers = (room) => {
+  let users = kapchatState.users.get(room) || [];
 
   let container = document.querySelector(".chat-users");
   container.innerHTML = "";
@@ -139,13 +230,45 @@ const updateUsers = (users) => {
 }
 
 const updateChatRooms = (rooms) => {
+  let previous_rooms = kapchatState.rooms || new Map();
   kapchatState.rooms = new Map();
-  kapchatState.current_room = rooms[0];
 
   rooms.forEach((room) => {
-    kapchatState.rooms.set(room, make_CRS_Buffer(50))
+    if (previous_rooms.has(room)) {
+      kapchatState.rooms.set(room, previous_rooms.get(room));
+      return;
+    }
+
+    kapchatState.rooms.set(room, {
+      messages: [],
+      loading: false,
+      exhausted: false,
+    });
+
+    kapchatState.socket.send(JSON.stringify({
+      event: 4,
+      room: room,
+    }));
   });
 
+  previous_rooms.forEach((_, room) => {
+    if (!kapchatState.rooms.has(room)) {
+      kapchatState.users.delete(room);
+    }
+  });
+
+  if (!kapchatState.rooms.has(kapchatState.current_room)) {
+    kapchatState.current_room = rooms[0];
+
+    if (kapchatState.current_room) {
+      renderRoom(kapchatState.current_room);
+      renderUsers(kapchatState.current_room);
+    } else {
+      document.querySelector(".chat-messages").innerHTML = "";
+      document.querySelector(".chat-users").innerHTML = "";
+    }
+  }
+
   let container = document.querySelector(".chat-rooms");
   container.innerHTML = "";
 
@@ -161,10 +284,15 @@ const updateChatRooms = (rooms) => {
 
     roomBlock.addEventListener("click", e => {
       let previous_room = document.getElementById(kapchatState.current_room);
-      previous_room.classList.remove("room-block--active");
+
+      if (previous_room) {
+        previous_room.classList.remove("room-block--active");
+      }
+
       kapchatState.current_room = room;
       roomBlock.classList.add("room-block--active");
       renderRoom(room);
+      renderUsers(room);
     })
 
     container.appendChild(roomBlock);
@@ -204,36 +332,26 @@ const sendFromText = (event) => {
 
 connect()
 
-function make_CRS_Buffer(size) {
-  return {
-    A:  [],
-    Ai: 0,
-    Asz:    size,
-    add:    function(value) {
-      this.A[ this.Ai ] = value;
-      this.Ai = (this.Ai + 1) % this.Asz;
-    },
-    forall: function(callback) {
-      var mAi = this.A.length < this.Asz ? 0 : this.Ai;
-      for (var i = 0; i < this.A.length; i++) {
-        callback(this.A[ (mAi + i) % this.Asz ]);
-      }
-    }
-  };
-}
-
 document.addEventListener("DOMContentLoaded", (event) => {
   const scrollingElement = document.getElementById("chat-messages");
   const config = { childList: true };
 
   const callback = function (mutationsList, observer) {
     for (let mutation of mutationsList) {
-      if (mutation.type === "childList") {
+      if (mutation.type === "childList" && !kapchatState.keep_scroll) {
         scrollingElement.scrollTo(0, scrollingElement.scrollHeight);
       }
     }
+
+    kapchatState.keep_scroll = false;
   };
 
   const observer = new MutationObserver(callback);
   observer.observe(scrollingElement, config);
+
+  scrollingElement.addEventListener("scroll", () => {
+    if (scrollingElement.scrollTop == 0) {
+      loadOlderMessages();
+    }
+  });
 });
\ No newline at end of file
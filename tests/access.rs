@@ -0,0 +1,358 @@
+#[macro_use]
+mod common;
+
+use actix_web::{http::{header, StatusCode}, test};
+use chrono::Duration;
+use common::{ban, create_board, create_thread, create_user, peer, session_cookie, Multipart, TestDb};
+use kapchan::models::{posts::{Post, PostInput}, threads::Thread, users::AccessLevel};
+
+
+const FORBIDDEN_PAGE: &str = "Sinulla ei ole käyttöoikeuksia tälle sivulle";
+const BANNED_PAGE: &str = "Olet Bannattu!";
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn board_access_follows_user_access_level() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_board(&db, "m", AccessLevel::Member as u8, 10, false).await;
+    create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+    create_user(&db, "rekisteri", "salasana", AccessLevel::Registered).await;
+
+    // Anonymous visitors get an anonymous user and session on their first request.
+    let req = test::TestRequest::get()
+    .uri("/m")
+    .peer_addr(peer("127.0.0.1"))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert!(session_cookie(&res).is_some());
+
+    let body = test::read_body(res).await;
+    assert!(String::from_utf8_lossy(&body).contains(FORBIDDEN_PAGE));
+
+    let req = test::TestRequest::get()
+    .uri("/api/v1/m/catalog")
+    .peer_addr(peer("127.0.0.1"))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    for (username, allowed) in [("rekisteri", false), ("jasen", true)] {
+        let req = test::TestRequest::post()
+        .uri("/login")
+        .peer_addr(peer("127.0.0.1"))
+        .set_form([("username", username), ("pwd", "salasana")])
+        .to_request();
+
+        let res = test::call_service(&app, req).await;
+        let cookie = session_cookie(&res).unwrap();
+
+        let req = test::TestRequest::get()
+        .uri("/m")
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie.clone())
+        .to_request();
+
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(!String::from_utf8_lossy(&body).contains(FORBIDDEN_PAGE), allowed);
+
+        let (content_type, body) = Multipart::new()
+        .text("topic", "Jäsenlanka")
+        .text("message", "Viesti")
+        .no_file("attachment")
+        .finish();
+
+        let req = test::TestRequest::post()
+        .uri("/m")
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie)
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_request();
+
+        let res = test::call_service(&app, req).await;
+        let expected = if allowed { StatusCode::CREATED } else { StatusCode::FORBIDDEN };
+        assert_eq!(res.status(), expected);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn banned_ip_is_locked_out_until_expiry() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let moderator = create_user(&db, "moderaattori", "salasana", AccessLevel::Moderator).await;
+
+    ban(&db, moderator.id, None, "10.0.0.5", Duration::days(1)).await;
+    ban(&db, moderator.id, None, "10.0.0.6", Duration::days(-1)).await;
+
+    let req = test::TestRequest::get()
+    .uri("/b")
+    .peer_addr(peer("10.0.0.5"))
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains(BANNED_PAGE));
+
+    let (content_type, body) = Multipart::new()
+    .text("topic", "Bannattu")
+    .text("message", "Viesti")
+    .no_file("attachment")
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri("/b")
+    .peer_addr(peer("10.0.0.5"))
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::get()
+    .uri("/captcha")
+    .peer_addr(peer("10.0.0.5"))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // Expired bans and other addresses are unaffected.
+    for ip in ["10.0.0.6", "10.0.0.7"] {
+        let req = test::TestRequest::get()
+        .uri("/b")
+        .peer_addr(peer(ip))
+        .to_request();
+
+        let body = test::call_and_read_body(&app, req).await;
+        assert!(!String::from_utf8_lossy(&body).contains(BANNED_PAGE));
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn user_ban_follows_the_account_to_a_new_address() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let moderator = create_user(&db, "moderaattori", "salasana", AccessLevel::Moderator).await;
+    let user = create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    ban(&db, moderator.id, Some(user.id), "10.0.0.5", Duration::days(1)).await;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("10.0.0.9"))
+    .set_form([("username", "kapakka"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    let req = test::TestRequest::get()
+    .uri("/b")
+    .peer_addr(peer("10.0.0.9"))
+    .cookie(cookie)
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains(BANNED_PAGE));
+
+    // Other visitors from the same address are not affected by a user ban.
+    let req = test::TestRequest::get()
+    .uri("/b")
+    .peer_addr(peer("10.0.0.9"))
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(!String::from_utf8_lossy(&body).contains(BANNED_PAGE));
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn range_ban_covers_the_whole_prefix() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    create_user(&db, "moderaattori", "salasana", AccessLevel::Moderator).await;
+    let user = create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let thread = create_thread(&db, &board, user.id, "lanka").await;
+    let post = Thread::by_id(thread.id, &db.pool).await.unwrap().posts.remove(0).post;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.2"))
+    .set_form([("username", "moderaattori"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/ban-user-by-post/{}", post.id))
+    .peer_addr(peer("127.0.0.2"))
+    .cookie(cookie)
+    .set_json(serde_json::json!({
+        "ban_duration_days": 1,
+        "reason": "testi",
+        "ban_range": "narrow",
+    }))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    // The post was made from 127.0.0.1, so 127.0.0.0/24 is banned.
+    for (ip, banned) in [("127.0.0.77", true), ("127.0.0.255", true), ("127.0.1.1", false), ("::ffff:127.0.0.9", true)] {
+        let req = test::TestRequest::get()
+        .uri("/b")
+        .peer_addr(peer(ip))
+        .to_request();
+
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(String::from_utf8_lossy(&body).contains(BANNED_PAGE), banned, "{}", ip);
+    }
+
+    assert!(Post::last_ip_by_user(user.id, &db.pool).await.unwrap().is_some());
+}
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn moderator_notes_on_posts() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    create_user(&db, "moderaattori", "salasana", AccessLevel::Moderator).await;
+    let user = create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let thread = create_thread(&db, &board, user.id, "lanka").await;
+    let post = Thread::by_id(thread.id, &db.pool).await.unwrap().posts.remove(0).post;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.2"))
+    .set_form([("username", "moderaattori"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    // Durations that would overflow the expiry are refused.
+    let req = test::TestRequest::post()
+    .uri(&format!("/ban-user-by-post/{}", post.id))
+    .peer_addr(peer("127.0.0.2"))
+    .cookie(cookie.clone())
+    .set_json(serde_json::json!({ "ban_duration_days": i64::MAX, "reason": "testi" }))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/ban-user-by-post/{}", post.id))
+    .peer_addr(peer("127.0.0.2"))
+    .cookie(cookie.clone())
+    .set_json(serde_json::json!({
+        "ban_duration_days": 1,
+        "reason": "testi",
+        "mod_note": "  (KÄYTTÄJÄ BANNATTIIN TÄSTÄ VIESTISTÄ)  ",
+    }))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let post = Post::by_id(post.id, &db.pool).await.unwrap();
+    assert_eq!(post.mod_note.as_deref(), Some("(KÄYTTÄJÄ BANNATTIIN TÄSTÄ VIESTISTÄ)"));
+
+    // Only moderators can write private notes.
+    let req = test::TestRequest::post()
+    .uri(&format!("/post-note/{}", post.id))
+    .peer_addr(peer("127.0.0.3"))
+    .set_json(serde_json::json!({ "note": "salainen" }))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/post-note/{}", post.id))
+    .peer_addr(peer("127.0.0.2"))
+    .cookie(cookie.clone())
+    .set_json(serde_json::json!({ "note": "salainen" }))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.3"))
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("(KÄYTTÄJÄ BANNATTIIN TÄSTÄ VIESTISTÄ)"));
+    assert!(!body.contains("salainen"));
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.2"))
+    .cookie(cookie)
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Muistiinpano: salainen"));
+}
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn api_threads_hide_posts_above_the_users_level() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let user = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+    let thread = create_thread(&db, &board, user.id, "lanka").await;
+
+    // Posted while the board was for members only.
+    let hidden = Post::insert_post_by_thread_id(thread.id, &db.pool, PostInput {
+        access_level: AccessLevel::Member as u8,
+        user_id: user.id,
+        show_username: false,
+        sage: false,
+        message: "jäsenille".to_owned(),
+        message_hash: "jäsenille".to_owned(),
+        ip_address: "127.0.0.1".to_owned(),
+        country_code: None,
+        mod_note: None,
+        capcode: None,
+        reply_ids: vec![],
+    })
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/api/v1/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.2"))
+    .to_request();
+
+    let res: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let post_ids: Vec<u64> = res["posts"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|postdata| postdata["post"]["id"].as_u64().unwrap())
+    .collect();
+
+    assert_eq!(post_ids.len(), 1);
+    assert!(!post_ids.contains(&hidden.id.into()));
+}
@@ -0,0 +1,117 @@
+#[macro_use]
+mod common;
+
+use actix_web::{http::{header, StatusCode}, test};
+use chrono::{Duration, Utc};
+use common::{create_board, create_thread, create_user, peer, Multipart, TestDb};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use kapchan::{models::{threads::Thread, users::AccessLevel}, schema::threads};
+
+
+/// Moves the bump time of a thread into the past, since threads created
+/// within the same second would otherwise be ordered arbitrarily.
+async fn set_bump_age(db: &TestDb, thread_id: u32, hours: i64) {
+    let mut conn = db.pool.get().await.unwrap();
+
+    diesel::update(threads::table.find(thread_id))
+    .set(threads::bump_time.eq((Utc::now() - Duration::hours(hours)).naive_utc()))
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+async fn archived(db: &TestDb, thread_id: u32) -> bool {
+    Thread::thread_by_id(thread_id, &db.pool).await.unwrap().archived
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn insert_thread_archives_least_recently_bumped_thread() {
+    let db = TestDb::new().await;
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 2, false).await;
+    let user = create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let oldest = create_thread(&db, &board, user.id, "vanhin").await;
+    set_bump_age(&db, oldest.id, 3).await;
+
+    let older = create_thread(&db, &board, user.id, "vanha").await;
+    set_bump_age(&db, older.id, 2).await;
+
+    assert!(!archived(&db, oldest.id).await);
+    assert!(!archived(&db, older.id).await);
+
+    let newest = create_thread(&db, &board, user.id, "uusi").await;
+
+    assert!(archived(&db, oldest.id).await);
+    assert!(!archived(&db, older.id).await);
+    assert!(!archived(&db, newest.id).await);
+
+    let catalog = Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap();
+    assert_eq!(catalog.len(), 2);
+    assert!(catalog.iter().all(|thread| thread.id != oldest.id));
+
+    assert_eq!(Thread::count_archived(&db.pool, board.id, &Default::default()).await.unwrap(), 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn pinned_threads_are_not_archived() {
+    let db = TestDb::new().await;
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 2, false).await;
+    let user = create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let pinned = create_thread(&db, &board, user.id, "kiinnitetty").await;
+    Thread::pin_thread(&db.pool, pinned.id, true).await.unwrap();
+    set_bump_age(&db, pinned.id, 3).await;
+
+    let older = create_thread(&db, &board, user.id, "vanha").await;
+    set_bump_age(&db, older.id, 2).await;
+
+    let newest = create_thread(&db, &board, user.id, "uusi").await;
+
+    assert!(!archived(&db, pinned.id).await);
+    assert!(archived(&db, older.id).await);
+    assert!(!archived(&db, newest.id).await);
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn archived_threads_reject_replies() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 1, false).await;
+    let user = create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let old = create_thread(&db, &board, user.id, "vanha").await;
+    set_bump_age(&db, old.id, 1).await;
+
+    create_thread(&db, &board, user.id, "uusi").await;
+    assert!(archived(&db, old.id).await);
+
+    let (content_type, body) = Multipart::new()
+    .text("message", "Vastaus arkistoon")
+    .no_file("attachment")
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/b/thread/{}", old.id))
+    .peer_addr(peer("127.0.0.1"))
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::get()
+    .uri("/b/archive")
+    .peer_addr(peer("127.0.0.1"))
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("vanha"));
+}
\ No newline at end of file
@@ -0,0 +1,86 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{create_user, TestDb};
+use kapchan::models::{chat_messages::{ChatMessage, ChatMessageModel}, chat_rooms::{ChatRoom, ChatRoomModel}, users::AccessLevel};
+
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn deleted_rooms_take_their_history_with_them() {
+    let db = TestDb::new().await;
+
+    let user = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+
+    let room = ChatRoomModel {
+        name: "salainen",
+        access_level: AccessLevel::Member as u8,
+    }
+    .insert(&db.pool)
+    .await
+    .unwrap();
+
+    ChatMessageModel {
+        room_id: room.id,
+        user_id: user.id,
+        display_name: "jasen",
+        body: "vain jäsenille",
+    }
+    .insert(&db.pool)
+    .await
+    .unwrap();
+
+    assert_eq!(ChatMessage::list_by_room(&db.pool, room.id, None, 50).await.unwrap().len(), 1);
+
+    ChatRoom::delete_chat_room(&db.pool, room.id).await.unwrap();
+
+    // A new room with the same name starts without the old history.
+    let room = ChatRoomModel {
+        name: "salainen",
+        access_level: AccessLevel::Anonymous as u8,
+    }
+    .insert(&db.pool)
+    .await
+    .unwrap();
+
+    assert!(ChatMessage::list_by_room(&db.pool, room.id, None, 50).await.unwrap().is_empty());
+}
+
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn clearing_messages_stays_in_the_room() {
+    let db = TestDb::new().await;
+
+    let user = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+    let mut rooms = Vec::new();
+
+    for name in ["yleinen", "toinen"] {
+        let room = ChatRoomModel {
+            name,
+            access_level: AccessLevel::Anonymous as u8,
+        }
+        .insert(&db.pool)
+        .await
+        .unwrap();
+
+        ChatMessageModel {
+            room_id: room.id,
+            user_id: user.id,
+            display_name: "jasen",
+            body: "roskaa",
+        }
+        .insert(&db.pool)
+        .await
+        .unwrap();
+
+        rooms.push(room);
+    }
+
+    let since = Utc::now().naive_utc() - Duration::hours(1);
+    let ids = ChatMessage::delete_recent_by_user(&db.pool, rooms[0].id, user.id, since).await.unwrap();
+
+    assert_eq!(ids.len(), 1);
+    assert!(ChatMessage::list_by_room(&db.pool, rooms[0].id, None, 50).await.unwrap().is_empty());
+    assert_eq!(ChatMessage::list_by_room(&db.pool, rooms[1].id, None, 50).await.unwrap().len(), 1);
+}
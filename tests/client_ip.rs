@@ -0,0 +1,110 @@
+use std::net::IpAddr;
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use kapchan::services::ip::{ip_key, range_ban, resolve_client_ip, BanRange, TrustedProxies};
+
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+fn headers(headers: &[(&'static str, &str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+
+    for (name, value) in headers {
+        map.append(HeaderName::from_static(name), HeaderValue::from_str(value).unwrap());
+    }
+
+    map
+}
+
+fn proxies(list: &str) -> TrustedProxies {
+    list.parse().unwrap()
+}
+
+#[test]
+fn headers_from_untrusted_peers_are_ignored() {
+    let headers = headers(&[
+        ("x-forwarded-for", "203.0.113.7"),
+        ("forwarded", "for=203.0.113.8"),
+        ("x-real-ip", "203.0.113.9"),
+    ]);
+
+    assert_eq!(resolve_client_ip(ip("198.51.100.1"), &headers, &TrustedProxies::default()), ip("198.51.100.1"));
+    assert_eq!(resolve_client_ip(ip("198.51.100.1"), &headers, &proxies("127.0.0.1")), ip("198.51.100.1"));
+}
+
+#[test]
+fn x_forwarded_for_skips_trusted_hops_only() {
+    let trusted = proxies("127.0.0.1, 10.0.0.0/8");
+
+    let single = headers(&[("x-forwarded-for", "203.0.113.7")]);
+    assert_eq!(resolve_client_ip(ip("127.0.0.1"), &single, &trusted), ip("203.0.113.7"));
+
+    // The client spoofs an address; the proxy appends the real one.
+    let spoofed = headers(&[("x-forwarded-for", "1.2.3.4, 203.0.113.7")]);
+    assert_eq!(resolve_client_ip(ip("127.0.0.1"), &spoofed, &trusted), ip("203.0.113.7"));
+
+    let chained = headers(&[("x-forwarded-for", "1.2.3.4, 203.0.113.7, 10.1.2.3")]);
+    assert_eq!(resolve_client_ip(ip("127.0.0.1"), &chained, &trusted), ip("203.0.113.7"));
+
+    let split = headers(&[("x-forwarded-for", "1.2.3.4"), ("x-forwarded-for", "203.0.113.7")]);
+    assert_eq!(resolve_client_ip(ip("127.0.0.1"), &split, &trusted), ip("203.0.113.7"));
+
+    let garbage = headers(&[("x-forwarded-for", "unknown")]);
+    assert_eq!(resolve_client_ip(ip("127.0.0.1"), &garbage, &trusted), ip("127.0.0.1"));
+}
+
+#[test]
+fn forwarded_header_is_preferred() {
+    let trusted = proxies("::1");
+
+    let headers = headers(&[
+        ("forwarded", "for=1.2.3.4, for=\"[2001:db8:cafe::17]:4711\";proto=https"),
+        ("x-forwarded-for", "203.0.113.7"),
+    ]);
+
+    assert_eq!(resolve_client_ip(ip("::1"), &headers, &trusted), ip("2001:db8:cafe::17"));
+}
+
+#[test]
+fn x_real_ip_is_used_without_forwarding_chain() {
+    let headers = headers(&[("x-real-ip", "203.0.113.9")]);
+
+    assert_eq!(resolve_client_ip(ip("127.0.0.1"), &headers, &proxies("127.0.0.1")), ip("203.0.113.9"));
+}
+
+#[test]
+fn trusted_proxy_list_parsing() {
+    assert!("127.0.0.1, 10.0.0.0/8, ::1, fd00::/8".parse::<TrustedProxies>().is_ok());
+    assert!("".parse::<TrustedProxies>().unwrap().is_empty());
+    assert!("localhost".parse::<TrustedProxies>().is_err());
+    assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+}
+
+#[test]
+fn range_bans_cover_the_prefix() {
+    let range = range_ban("203.0.113.77", BanRange::Narrow).unwrap();
+    assert_eq!(range.cidr, "203.0.113.0/24");
+    assert_eq!(range.start, ip_key(&ip("203.0.113.0")));
+    assert_eq!(range.end, ip_key(&ip("203.0.113.255")));
+
+    let range = range_ban("203.0.113.77", BanRange::Wide).unwrap();
+    assert_eq!(range.cidr, "203.0.0.0/16");
+
+    let range = range_ban("2001:db8:cafe:17::1", BanRange::Narrow).unwrap();
+    assert_eq!(range.cidr, "2001:db8:cafe:17::/64");
+    assert!(range.start <= ip_key(&ip("2001:db8:cafe:17:ffff::1")));
+    assert!(range.end >= ip_key(&ip("2001:db8:cafe:17:ffff::1")));
+    assert!(range.end < ip_key(&ip("2001:db8:cafe:18::1")));
+
+    let range = range_ban("2001:db8:cafe:17::1", BanRange::Wide).unwrap();
+    assert_eq!(range.cidr, "2001:db8:cafe::/48");
+
+    // IPv4-mapped addresses are banned as IPv4.
+    let range = range_ban("::ffff:203.0.113.77", BanRange::Narrow).unwrap();
+    assert_eq!(range.cidr, "203.0.113.0/24");
+    assert_eq!(ip_key(&ip("::ffff:203.0.113.5")), ip_key(&ip("203.0.113.5")));
+
+    assert!(range_ban("", BanRange::Narrow).is_none());
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+use kapchan::config::{Config, ConfigError};
+
+
+#[test]
+fn durations_too_long_to_build_are_rejected() {
+    let mut config = Config::default();
+    assert!(config.validate().is_ok());
+
+    config.server.session_ttl_days = i64::MAX;
+    config.captcha.expiry_minutes = i64::MAX;
+    config.login.window_minutes = i64::MAX;
+    config.flood.duplicate_window_seconds = i64::MAX;
+
+    match config.validate() {
+        Err(ConfigError::Invalid(errors)) => assert_eq!(errors.len(), 4),
+        res => panic!("expected the config to be rejected, got {:?}", res),
+    }
+}
@@ -0,0 +1,170 @@
+#[macro_use]
+mod common;
+
+use std::collections::HashMap;
+
+use actix_web::{body::MessageBody, cookie::Cookie, dev::ServiceResponse, http::{header, StatusCode}, test, web, App, HttpResponse};
+use common::{create_board, create_thread, create_user, location, session_cookie, test_config, TestDb};
+use kapchan::{config::Config, middleware::csrf::{CSRF_COOKIE, CSRF_HEADER}, models::{threads::Thread, users::AccessLevel}};
+
+
+fn csrf_config() -> Config {
+    let mut config = test_config();
+    config.security.csrf_protection = true;
+
+    config
+}
+
+fn csrf_cookie<B: MessageBody>(res: &ServiceResponse<B>) -> Option<Cookie<'static>> {
+    res.response()
+    .cookies()
+    .find(|cookie| cookie.name() == CSRF_COOKIE)
+    .map(|cookie| cookie.into_owned())
+}
+
+#[actix_web::test]
+async fn handlers_receive_the_checked_form() {
+    let app = test::init_service(
+        App::new()
+        .wrap(kapchan::middleware::csrf::Csrf)
+        .wrap(
+            actix_session::SessionMiddleware::builder(
+                actix_session::storage::CookieSessionStore::default(), 
+                actix_web::cookie::Key::generate(),
+            )
+            .cookie_name(common::SESSION_COOKIE.to_owned())
+            .build(),
+        )
+        .route("/", web::get().to(HttpResponse::Ok))
+        .route("/echo", web::post().to(|form: web::Form<HashMap<String, String>>| async move {
+            HttpResponse::Ok().body(form.get("message").cloned().unwrap_or_default())
+        }))
+    )
+    .await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+    let session = session_cookie(&res).unwrap();
+    let token = csrf_cookie(&res).unwrap().value().to_owned();
+
+    let req = test::TestRequest::post()
+    .uri("/echo")
+    .cookie(session)
+    .set_form([("csrf_token", token.as_str()), ("message", "Hei & moi")])
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, "Hei & moi");
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn forms_need_the_session_token() {
+    let db = TestDb::new().await;
+    let app = test_app!(db, kapchan::services::geoip::GeoIp::default(), csrf_config());
+
+    create_user(&db, "kayttaja", "salasana", AccessLevel::Member).await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/login").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let session = session_cookie(&res).unwrap();
+    let token = csrf_cookie(&res).unwrap().value().to_owned();
+
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(body.contains(&format!("name=\"csrf_token\" value=\"{}\"", token)));
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .cookie(session.clone())
+    .set_form([("username", "kayttaja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // A token of another session doesn't do either.
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .set_form([("username", "kayttaja"), ("pwd", "salasana"), ("csrf_token", &token)])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .cookie(session)
+    .set_form([("username", "kayttaja"), ("pwd", "salasana"), ("csrf_token", &token)])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(location(&res).unwrap(), "/");
+
+    // The token outlives the new session key of the login.
+    let session = session_cookie(&res).unwrap();
+
+    let req = test::TestRequest::post()
+    .uri("/logout")
+    .cookie(session)
+    .insert_header((CSRF_HEADER, token.as_str()))
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn cross_origin_requests_are_rejected() {
+    let db = TestDb::new().await;
+    let app = test_app!(db, kapchan::services::geoip::GeoIp::default(), csrf_config());
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let moderator = create_user(&db, "valvoja", "salasana", AccessLevel::Moderator).await;
+    let thread = create_thread(&db, &board, moderator.id, "lanka").await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/login").to_request()).await;
+    let session = session_cookie(&res).unwrap();
+    let token = csrf_cookie(&res).unwrap().value().to_owned();
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .cookie(session.clone())
+    .set_form([("username", "valvoja"), ("pwd", "salasana"), ("csrf_token", &token)])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let session = session_cookie(&res).unwrap();
+
+    let pin = |origin: &str| test::TestRequest::post()
+    .uri(&format!("/pin-thread/{}", thread.id))
+    .cookie(session.clone())
+    .insert_header((CSRF_HEADER, token.as_str()))
+    .insert_header((header::ORIGIN, origin))
+    .to_request();
+
+    let res = test::call_service(&app, pin("https://evil.test")).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(&app, pin("null")).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    assert!(!Thread::by_id(thread.id, &db.pool).await.unwrap().thread.pinned);
+
+    let res = test::call_service(&app, pin("http://localhost:8080")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert!(Thread::by_id(thread.id, &db.pool).await.unwrap().thread.pinned);
+
+    // Pinning is no longer possible through a link.
+    let req = test::TestRequest::get()
+    .uri(&format!("/unpin-thread/{}", thread.id))
+    .cookie(session)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    assert!(Thread::by_id(thread.id, &db.pool).await.unwrap().thread.pinned);
+}
\ No newline at end of file
@@ -0,0 +1,133 @@
+#!/usr/bin/env python3
+"""Writes geoip-country.mmdb, a tiny MaxMind format country database used by
+the GeoIP tests. Only the standard library is needed, so the fixture can be
+regenerated offline:
+
+    python3 tests/fixtures/generate_geoip.py
+
+The database is IPv4 only and maps documentation ranges to countries.
+"""
+
+import ipaddress
+import os
+import struct
+
+NETWORKS = {
+    "192.0.2.0/24": ("FI", "Finland"),
+    "198.51.100.0/24": ("SE", "Sweden"),
+}
+
+RECORD_SIZE = 24
+
+
+def control(type_id, size):
+    if type_id <= 7:
+        head = bytes([(type_id << 5) | size])
+    else:
+        head = bytes([size, type_id - 7])
+
+    return head
+
+
+def encode(value):
+    if isinstance(value, str):
+        data = value.encode()
+        assert len(data) < 29
+        return control(2, len(data)) + data
+
+    if isinstance(value, dict):
+        out = control(7, len(value))
+
+        for key, item in value.items():
+            out += encode(key) + encode(item)
+
+        return out
+
+    if isinstance(value, list):
+        out = control(11, len(value))
+
+        for item in value:
+            out += encode(item)
+
+        return out
+
+    raise TypeError(value)
+
+
+def encode_uint(type_id, value):
+    data = value.to_bytes((value.bit_length() + 7) // 8, "big")
+    return control(type_id, len(data)) + data
+
+
+def build_tree(networks):
+    # Each node is [left, right], where a record is ("node", index),
+    # ("data", offset) or None.
+    nodes = [[None, None]]
+
+    for network, offset in networks:
+        net = ipaddress.ip_network(network)
+        bits = int(net.network_address)
+        node = 0
+
+        for depth in range(net.prefixlen):
+            bit = (bits >> (31 - depth)) & 1
+
+            if depth == net.prefixlen - 1:
+                nodes[node][bit] = ("data", offset)
+                break
+
+            if nodes[node][bit] is None:
+                nodes.append([None, None])
+                nodes[node][bit] = ("node", len(nodes) - 1)
+
+            node = nodes[node][bit][1]
+
+    return nodes
+
+
+def main():
+    data = b""
+    offsets = []
+
+    for network, (iso_code, name) in NETWORKS.items():
+        offsets.append((network, len(data)))
+        data += encode({"country": {"iso_code": iso_code, "names": {"en": name}}})
+
+    nodes = build_tree(offsets)
+    node_count = len(nodes)
+
+    def record(value):
+        if value is None:
+            return node_count
+
+        kind, target = value
+
+        if kind == "node":
+            return target
+
+        return node_count + 16 + target
+
+    tree = b""
+
+    for left, right in nodes:
+        tree += struct.pack(">I", record(left))[1:] + struct.pack(">I", record(right))[1:]
+
+    metadata = control(7, 9)
+    metadata += encode("binary_format_major_version") + encode_uint(5, 2)
+    metadata += encode("binary_format_minor_version") + encode_uint(5, 0)
+    metadata += encode("build_epoch") + control(0, 8) + bytes([9 - 7]) + (1735689600).to_bytes(8, "big")
+    metadata += encode("database_type") + encode("Kapchan-Test-Country")
+    metadata += encode("description") + encode({"en": "Kapchan test data"})
+    metadata += encode("ip_version") + encode_uint(5, 4)
+    metadata += encode("languages") + encode(["en"])
+    metadata += encode("node_count") + encode_uint(6, node_count)
+    metadata += encode("record_size") + encode_uint(5, RECORD_SIZE)
+
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "geoip-country.mmdb")
+
+    with open(path, "wb") as f:
+        f.write(tree + bytes(16) + data + b"\xab\xcd\xefMaxMind.com" + metadata)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,135 @@
+#[macro_use]
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use common::{create_board, create_user, peer, session_cookie, test_config, Multipart, TestDb};
+use kapchan::{config::Config, models::{threads::Thread, users::AccessLevel}, services::flood::display_wait};
+use serde_json::Value;
+
+
+fn new_thread(handle: &str, message: &str, ip: &str) -> actix_web::test::TestRequest {
+    let (content_type, body) = Multipart::new()
+    .text("topic", "lanka")
+    .text("message", message)
+    .no_file("attachment")
+    .finish();
+
+    actix_web::test::TestRequest::post()
+    .uri(&format!("/{}", handle))
+    .peer_addr(peer(ip))
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+}
+
+fn reply(thread_id: u32, message: &str, ip: &str) -> actix_web::test::TestRequest {
+    let (content_type, body) = Multipart::new()
+    .text("message", message)
+    .no_file("attachment")
+    .finish();
+
+    actix_web::test::TestRequest::post()
+    .uri(&format!("/b/thread/{}", thread_id))
+    .peer_addr(peer(ip))
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+}
+
+fn flood_config(post_cooldown: i64, thread_cooldown: i64, duplicate_window: i64) -> Config {
+    let mut config = test_config();
+
+    config.flood.post_cooldown_seconds = post_cooldown;
+    config.flood.thread_cooldown_seconds = thread_cooldown;
+    config.flood.duplicate_window_seconds = duplicate_window;
+
+    config
+}
+
+#[test]
+fn wait_times_round_up() {
+    assert_eq!(display_wait(0), "1 sekunti");
+    assert_eq!(display_wait(12), "12 sekuntia");
+    assert_eq!(display_wait(60), "1 minuutti");
+    assert_eq!(display_wait(61), "2 minuuttia");
+    assert_eq!(display_wait(3600), "1 tunti");
+    assert_eq!(display_wait(7201), "3 tuntia");
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn post_cooldown_applies_per_address_and_user() {
+    let db = TestDb::new().await;
+    let app = test_app!(db, kapchan::services::geoip::GeoIp::default(), flood_config(60, 0, 0));
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    create_user(&db, "valvoja", "salasana", AccessLevel::Moderator).await;
+
+    let res = actix_web::test::call_service(&app, new_thread("b", "Aloitus", "127.0.0.1").to_request()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let cookie = session_cookie(&res).unwrap();
+    let thread_id = Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap()[0].id;
+
+    // Same user from another address.
+    let res = actix_web::test::call_service(&app, reply(thread_id, "Heti perään", "127.0.0.9").cookie(cookie).to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let json: Value = actix_web::test::read_body_json(res).await;
+    assert!(json["error"].as_str().unwrap().starts_with("Lähetät viestejä liian nopeasti! Odota vielä"));
+
+    // New user from the same address.
+    let res = actix_web::test::call_service(&app, reply(thread_id, "Heti perään", "127.0.0.1").to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let res = actix_web::test::call_service(&app, reply(thread_id, "Eri osoitteesta", "127.0.0.2").to_request()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    // Staff is exempt.
+    let req = actix_web::test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.3"))
+    .set_form([("username", "valvoja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    for message in ["Yksi", "Kaksi"] {
+        let res = actix_web::test::call_service(&app, reply(thread_id, message, "127.0.0.3").cookie(cookie.clone()).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn thread_cooldown_and_duplicate_messages() {
+    let db = TestDb::new().await;
+    let app = test_app!(db, kapchan::services::geoip::GeoIp::default(), flood_config(0, 600, 600));
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    create_board(&db, "c", AccessLevel::Anonymous as u8, 10, false).await;
+
+    let res = actix_web::test::call_service(&app, new_thread("b", "Aloitus", "127.0.0.1").to_request()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let thread_id = Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap()[0].id;
+
+    // Replies aren't limited by the thread cooldown.
+    let res = actix_web::test::call_service(&app, reply(thread_id, "Vastaus", "127.0.0.1").to_request()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = actix_web::test::call_service(&app, new_thread("b", "Toinen aloitus", "127.0.0.1").to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let json: Value = actix_web::test::read_body_json(res).await;
+    assert_eq!(json["error"], "Aloitat lankoja liian nopeasti! Odota vielä 10 minuuttia.");
+
+    // Identical messages are rejected from anyone on the same board only.
+    let res = actix_web::test::call_service(&app, reply(thread_id, "Aloitus", "127.0.0.2").to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let json: Value = actix_web::test::read_body_json(res).await;
+    assert!(json["error"].as_str().unwrap().starts_with("Sama viesti on lähetetty äskettäin!"));
+
+    let res = actix_web::test::call_service(&app, new_thread("c", "Aloitus", "127.0.0.2").to_request()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+}
\ No newline at end of file
@@ -0,0 +1,109 @@
+#[macro_use]
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use common::{create_board, peer, Multipart, TestDb};
+use kapchan::{
+    config::GeoIpConfig,
+    models::{boards::{Board, BoardModel}, threads::Thread, users::AccessLevel},
+    services::geoip::{flag, GeoIp},
+};
+
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/geoip-country.mmdb");
+
+fn fixture() -> GeoIp {
+    GeoIp::open(&GeoIpConfig {
+        database: Some(FIXTURE.to_owned()),
+    })
+    .unwrap()
+}
+
+#[test]
+fn countries_are_looked_up_from_the_database() {
+    let geoip = fixture();
+
+    assert!(geoip.is_enabled());
+    assert_eq!(geoip.country_code("192.0.2.55").as_deref(), Some("FI"));
+    assert_eq!(geoip.country_code("::ffff:198.51.100.7").as_deref(), Some("SE"));
+    assert_eq!(geoip.country_code("203.0.113.1"), None);
+    assert_eq!(geoip.country_code("2001:db8::1"), None);
+    assert_eq!(geoip.country_code("ei osoite"), None);
+}
+
+#[test]
+fn lookups_are_disabled_without_a_database() {
+    let geoip = GeoIp::open(&GeoIpConfig::default()).unwrap();
+
+    assert!(!geoip.is_enabled());
+    assert_eq!(geoip.country_code("192.0.2.55"), None);
+}
+
+#[test]
+fn flags_are_regional_indicators() {
+    assert_eq!(flag("FI"), "\u{1F1EB}\u{1F1EE}");
+    assert_eq!(flag("se"), "\u{1F1F8}\u{1F1EA}");
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn flags_are_shown_on_boards_that_enable_them() {
+    let db = TestDb::new().await;
+    let app = test_app!(db, fixture());
+
+    let flagged = create_board(&db, "int", AccessLevel::Anonymous as u8, 10, false).await;
+    let plain = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+
+    Board::update_board(&db.pool, flagged.id, BoardModel {
+        handle: &flagged.handle,
+        title: &flagged.title,
+        description: &flagged.description,
+        access_level: flagged.access_level,
+        active_threads_limit: flagged.active_threads_limit,
+        thread_size_limit: flagged.thread_size_limit,
+        captcha: flagged.captcha,
+        nsfw: flagged.nsfw,
+        flags: true,
+    })
+    .await
+    .unwrap();
+
+    let new_thread = |handle: &str| {
+        let (content_type, body) = Multipart::new()
+        .text("topic", "Lanka")
+        .text("message", "Terveiset")
+        .no_file("attachment")
+        .finish();
+
+        actix_web::test::TestRequest::post()
+        .uri(&format!("/{}", handle))
+        .peer_addr(peer("192.0.2.10"))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_request()
+    };
+
+    for handle in ["int", "b"] {
+        let res = actix_web::test::call_service(&app, new_thread(handle)).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    let threads = Thread::list_threads_by_board_catalog(&db.pool, flagged.id).await.unwrap();
+    assert_eq!(threads[0].op_post.country_code.as_deref(), Some("FI"));
+
+    // Countries aren't looked up for boards without flags.
+    let threads = Thread::list_threads_by_board_catalog(&db.pool, plain.id).await.unwrap();
+    assert_eq!(threads[0].op_post.country_code, None);
+
+    let flag_html = format!("<span class=\"flag\" title=\"FI\">{}</span>", flag("FI"));
+
+    for (handle, shown) in [("int", true), ("b", false)] {
+        let req = actix_web::test::TestRequest::get()
+        .uri(&format!("/{}", handle))
+        .peer_addr(peer("127.0.0.1"))
+        .to_request();
+
+        let body = actix_web::test::call_and_read_body(&app, req).await;
+        assert_eq!(String::from_utf8_lossy(&body).contains(&flag_html), shown);
+    }
+}
\ No newline at end of file
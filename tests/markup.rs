@@ -0,0 +1,92 @@
+use kapchan::{
+    models::posts::QuoteTarget,
+    services::markup::{format_message as format_with_quotes, format_preview, Quotes},
+};
+
+
+/// Renders with quotes of post 12 on /b/ and board /b/ resolving.
+fn format_message(message: &str) -> String {
+    let quotes = Quotes::new(
+        vec![QuoteTarget { post_id: 12, thread_id: 1, board_handle: "b".to_owned() }],
+        ["b".to_owned()],
+    );
+
+    format_with_quotes(message, &quotes)
+}
+
+#[test]
+fn html_is_escaped() {
+    assert_eq!(
+        format_message("<script>alert(\"x\")</script> & 'y'"),
+        "&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; &#39;y&#39;",
+    );
+}
+
+#[test]
+fn greentext_lines() {
+    assert_eq!(
+        format_message("hello\n>implying\n>>12"),
+        "hello\n<span class=\"implying\">&gt;implying</span>\n\
+        <span class=\"backlink\" onClick=\"showPost(12)\" onpointerenter=\"hintPost(this, 12)\" onpointerleave=\"unhintPost(this)\">&gt;&gt;12</span>",
+    );
+}
+
+#[test]
+fn inline_markup() {
+    assert_eq!(format_message("**bold** *italic*"), "<b>bold</b> <i>italic</i>");
+    assert_eq!(format_message("2 * 3 * 4"), "2 * 3 * 4");
+    assert_eq!(
+        format_message("[spoiler]**secret**[/spoiler]"),
+        "<span class=\"spoiler\"><b>secret</b></span>",
+    );
+    assert_eq!(format_message("`*not italic* <b>`"), "<code>*not italic* &lt;b&gt;</code>");
+}
+
+#[test]
+fn code_blocks() {
+    assert_eq!(
+        format_message("look:\n```\nfn main() {\n    >>1 <a>\n}\n```\ndone"),
+        "look:<pre class=\"code-block\"><code>fn main() {\n    &gt;&gt;1 &lt;a&gt;\n}</code></pre>done",
+    );
+
+    // Unclosed fences are left as text.
+    assert_eq!(format_message("```\ncode"), "```\ncode");
+}
+
+#[test]
+fn links() {
+    assert_eq!(
+        format_message("see https://example.com/a?b=1&c=2."),
+        "see <a class=\"link\" href=\"https://example.com/a?b=1&amp;c=2\" target=\"_blank\" rel=\"nofollow noopener noreferrer\">\
+        https://example.com/a?b=1&amp;c=2</a>.",
+    );
+    assert_eq!(format_message("javascript:alert(1)"), "javascript:alert(1)");
+    assert_eq!(format_message("xhttps://example.com"), "xhttps://example.com");
+}
+
+#[test]
+fn cross_board_quotes() {
+    assert_eq!(
+        format_message(">>>/b/"),
+        "<a class=\"backlink\" href=\"/b\">&gt;&gt;&gt;/b/</a>",
+    );
+    assert_eq!(
+        format_message(">>>/b/12"),
+        "<span class=\"backlink\" onClick=\"showPost(12)\" onpointerenter=\"hintPost(this, 12)\" onpointerleave=\"unhintPost(this)\">&gt;&gt;&gt;/b/12</span>",
+    );
+}
+
+#[test]
+fn unresolved_quotes_are_dead_links() {
+    assert_eq!(format_message(">>34"), "<span class=\"deadlink\">&gt;&gt;34</span>");
+    assert_eq!(format_message(">>>/a/12"), "<span class=\"deadlink\">&gt;&gt;&gt;/a/12</span>");
+    assert_eq!(format_message(">>>/a/"), "<span class=\"deadlink\">&gt;&gt;&gt;/a/</span>");
+}
+
+#[test]
+fn previews_have_no_links_or_blocks() {
+    assert_eq!(
+        format_preview(">>5 https://example.com\n```\nx\n```"),
+        "<span class=\"backlink\">&gt;&gt;5</span> <span class=\"link\">https://example.com</span>\n<code>x</code>",
+    );
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+#[macro_use]
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use common::{create_board, create_thread, create_user, peer, session_cookie, TestDb};
+use kapchan::models::{mod_actions::{ModAction, ModActionFilter}, users::AccessLevel};
+
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn staff_actions_are_logged_with_deleted_posts() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let moderator = create_user(&db, "valvoja", "salasana", AccessLevel::Moderator).await;
+    let member = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+    let pinned = create_thread(&db, &board, member.id, "kiinnitetty").await;
+    let deleted = create_thread(&db, &board, member.id, "poistettava viesti").await;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "valvoja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    for uri in [format!("/pin-thread/{}", pinned.id), format!("/delete-thread/{}", deleted.id)] {
+        let req = test::TestRequest::post()
+        .uri(&uri)
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie.clone())
+        .to_request();
+
+        let res = test::call_service(&app, req).await;
+        assert!(res.status().is_success());
+    }
+
+    let actions = ModAction::load_actions(&db.pool, &ModActionFilter::default(), 20, 0).await.unwrap();
+    assert_eq!(actions.len(), 2);
+
+    let deletion = actions.iter().find(|action| action.action == "delete_thread").unwrap();
+    assert_eq!(deletion.moderator_id, moderator.id);
+    assert_eq!(deletion.target_id, Some(member.id));
+    assert_eq!(deletion.item_id, Some(deleted.id.into()));
+
+    let snapshot = deletion.snapshot_posts();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].message, "poistettava viesti");
+    assert_eq!(snapshot[0].user_id, member.id);
+
+    let filter = ModActionFilter {
+        action: Some("pin_thread".to_owned()),
+        ..Default::default()
+    };
+
+    let actions = ModAction::load_actions(&db.pool, &filter, 20, 0).await.unwrap();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].item_id, Some(pinned.id.into()));
+    assert_eq!(actions[0].board_id, Some(board.id));
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/mod-log/1?action=delete_thread&target={}", member.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(body.contains("poistettava viesti"));
+    assert!(!body.contains("/pin_thread"));
+
+    // Members can't read the log.
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "jasen"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    let req = test::TestRequest::get()
+    .uri("/mod-log/1")
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Sinulla ei ole käyttöoikeuksia tälle sivulle"));
+}
\ No newline at end of file
@@ -0,0 +1,267 @@
+#[macro_use]
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{create_board, create_thread, create_user, peer, session_cookie, TestDb};
+use diesel::ExpressionMethods;
+use diesel_async::RunQueryDsl;
+use kapchan::{models::{roles::{Role, UserRole, UserRoleModel}, threads::Thread, users::{AccessLevel, User, UserData}}, schema::{role_capabilities, roles}, services::permissions::{authorize_area, authorize_ban, authorize_user_modification, Capability, PermissionError, Permissions}};
+use serde_json::json;
+
+
+#[test]
+fn staff_can_only_act_below_their_level() {
+    let admin = AccessLevel::Admin as u8;
+    let member = AccessLevel::Member as u8;
+
+    assert!(matches!(authorize_user_modification(admin, member, AccessLevel::Moderator as u8), Ok(AccessLevel::Moderator)));
+    assert_eq!(authorize_user_modification(admin, member, admin).err(), Some(PermissionError::Forbidden));
+    assert_eq!(authorize_user_modification(admin, member, AccessLevel::Root as u8).err(), Some(PermissionError::Forbidden));
+    assert_eq!(authorize_user_modification(admin, admin, member).err(), Some(PermissionError::Forbidden));
+    assert_eq!(authorize_user_modification(admin, member, 50).err(), Some(PermissionError::InvalidLevel(50)));
+    assert_eq!(authorize_user_modification(AccessLevel::Moderator as u8, member, AccessLevel::Moderator as u8).err(), Some(PermissionError::Forbidden));
+
+    assert_eq!(authorize_ban(AccessLevel::Moderator as u8, member), Ok(()));
+    assert_eq!(authorize_ban(AccessLevel::Moderator as u8, AccessLevel::Moderator as u8), Err(PermissionError::Forbidden));
+    assert_eq!(authorize_ban(member, member), Err(PermissionError::Forbidden));
+
+    assert!(matches!(authorize_area(admin, admin), Ok(AccessLevel::Admin)));
+    assert_eq!(authorize_area(admin, AccessLevel::Owner as u8).err(), Some(PermissionError::Forbidden));
+    assert_eq!(authorize_area(admin, 11).err(), Some(PermissionError::InvalidLevel(11)));
+}
+
+fn user_data(access_level: AccessLevel) -> UserData {
+    UserData {
+        id: 1,
+        access_level: access_level as u8,
+        ip_addr: "127.0.0.1".to_owned(),
+        user_agent: String::new(),
+        banned: None,
+    }
+}
+
+#[test]
+fn roles_grant_capabilities_on_their_board_only() {
+    let janitor = Permissions::new(user_data(AccessLevel::Member), vec![(Capability::DeletePost, Some(1))]);
+
+    assert!(janitor.can(Capability::DeletePost, Some(1)));
+    assert!(!janitor.can(Capability::DeletePost, Some(2)));
+    assert!(!janitor.can(Capability::DeletePost, None));
+    assert!(!janitor.can(Capability::Ban, Some(1)));
+    assert!(janitor.can_anywhere(Capability::DeletePost));
+    assert!(janitor.board_tools(1).delete_post && !janitor.board_tools(2).delete_post);
+    assert!(janitor.board_tools(1).private_note && !janitor.board_tools(2).private_note);
+
+    let global = Permissions::new(user_data(AccessLevel::Registered), vec![(Capability::Pin, None)]);
+
+    assert!(global.can(Capability::Pin, Some(2)));
+    assert!(global.can(Capability::Pin, None));
+
+    // Staff levels keep what they could do before roles.
+    let moderator = Permissions::new(user_data(AccessLevel::Moderator), Vec::new());
+
+    assert_eq!(moderator.require(Capability::Lock, Some(2)), Ok(()));
+    assert_eq!(moderator.require(Capability::ManageBoards, None), Err(PermissionError::Forbidden));
+    assert_eq!("review_applications".parse::<Capability>(), Ok(Capability::ReviewApplications));
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn board_roles_only_apply_on_their_board() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let other_board = create_board(&db, "g", AccessLevel::Anonymous as u8, 10, false).await;
+    let member = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+    let thread = create_thread(&db, &board, member.id, "lanka").await;
+    let other_thread = create_thread(&db, &other_board, member.id, "toinen").await;
+
+    let moderator = Role::list_all(&db.pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .find(|role| role.name == "moderator")
+    .unwrap();
+
+    UserRoleModel {
+        user_id: member.id,
+        role_id: moderator.id,
+        board_id: Some(board.id),
+    }
+    .insert(&db.pool)
+    .await
+    .unwrap();
+
+    let req = actix_web::test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "jasen"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    for (thread_id, status) in [(thread.id, StatusCode::OK), (other_thread.id, StatusCode::FORBIDDEN)] {
+        let req = actix_web::test::TestRequest::post()
+        .uri(&format!("/pin-thread/{}", thread_id))
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie.clone())
+        .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), status);
+    }
+
+    assert!(Thread::by_id(thread.id, &db.pool).await.unwrap().thread.pinned);
+    assert!(!Thread::by_id(other_thread.id, &db.pool).await.unwrap().thread.pinned);
+
+    // Reports can be handled on the board, but not listed site-wide.
+    for (uri, allowed) in [("/reports/1?board=b", true), ("/reports/1?board=g", false), ("/reports/1", false)] {
+        let req = actix_web::test::TestRequest::get()
+        .uri(uri)
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie.clone())
+        .to_request();
+
+        let body = actix_web::test::call_and_read_body(&app, req).await;
+        assert_eq!(!String::from_utf8_lossy(&body).contains("Sinulla ei ole käyttöoikeuksia"), allowed);
+    }
+
+    // Managing boards is not part of the role.
+    let req = actix_web::test::TestRequest::post()
+    .uri(&format!("/delete-board/{}", board.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn admins_cannot_modify_their_equals_or_grant_their_own_level() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_user(&db, "yllapitaja", "salasana", AccessLevel::Admin).await;
+    let other_admin = create_user(&db, "toinen", "salasana", AccessLevel::Admin).await;
+    let member = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+
+    let req = actix_web::test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "yllapitaja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    for (user_id, access_level, status) in [
+        (member.id, AccessLevel::Root as u8, StatusCode::FORBIDDEN),
+        (member.id, AccessLevel::Admin as u8, StatusCode::FORBIDDEN),
+        (member.id, 41, StatusCode::BAD_REQUEST),
+        (other_admin.id, AccessLevel::Member as u8, StatusCode::FORBIDDEN),
+        (member.id, AccessLevel::Moderator as u8, StatusCode::CREATED),
+    ] {
+        let req = actix_web::test::TestRequest::post()
+        .uri(&format!("/modify-user/{}", user_id))
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie.clone())
+        .set_json(json!({ "access_level": access_level }))
+        .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), status);
+    }
+
+    assert_eq!(User::by_id(other_admin.id, &db.pool).await.unwrap().access_level, AccessLevel::Admin as u8);
+    assert_eq!(User::by_id(member.id, &db.pool).await.unwrap().access_level, AccessLevel::Moderator as u8);
+
+    let req = actix_web::test::TestRequest::post()
+    .uri(&format!("/ban-user-by-id/{}", other_admin.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .set_json(json!({ "reason": "testi", "ban_duration_days": 1 }))
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn roles_can_only_grant_their_own_capabilities() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let manager = create_user(&db, "hallinta", "salasana", AccessLevel::Member).await;
+    let member = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+
+    let mut conn = db.pool.get().await.unwrap();
+
+    diesel::insert_into(roles::table)
+    .values(roles::name.eq("user_manager"))
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    let all_roles = Role::list_all(&db.pool).await.unwrap();
+    let role_id = |name: &str| all_roles.iter().find(|role| role.name == name).unwrap().id;
+
+    diesel::insert_into(role_capabilities::table)
+    .values((role_capabilities::role_id.eq(role_id("user_manager")), role_capabilities::capability.eq("manage_users")))
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    for (name, board_id) in [("user_manager", None), ("janitor", Some(board.id))] {
+        UserRoleModel {
+            user_id: manager.id,
+            role_id: role_id(name),
+            board_id,
+        }
+        .insert(&db.pool)
+        .await
+        .unwrap();
+    }
+
+    let req = actix_web::test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "hallinta"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    for (role, board_id, status) in [
+        ("moderator", Some(board.id), StatusCode::FORBIDDEN),
+        ("janitor", None, StatusCode::FORBIDDEN),
+        ("janitor", Some(board.id), StatusCode::CREATED),
+        ("user_manager", None, StatusCode::CREATED),
+    ] {
+        let req = actix_web::test::TestRequest::post()
+        .uri(&format!("/user-roles/{}", member.id))
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie.clone())
+        .set_json(json!({ "role_id": role_id(role), "board_id": board_id }))
+        .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), status);
+    }
+
+    let user_roles = UserRole::list_by_user(member.id, &db.pool).await.unwrap();
+    assert_eq!(user_roles.len(), 2);
+
+    let req = actix_web::test::TestRequest::post()
+    .uri(&format!("/delete-user-role/{}", user_roles[0].user_role.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
@@ -0,0 +1,385 @@
+#[macro_use]
+mod common;
+
+use std::{fs, path::Path, time::Duration};
+
+use actix_web::{http::{header, StatusCode}, test};
+use common::{create_board, create_thread, create_user, peer, png_image, session_cookie, Multipart, TestDb};
+use kapchan::models::{captchas::Captcha, threads::Thread, users::{AccessLevel, User}};
+use serde_json::Value;
+
+
+/// Attachments are written to disk on a background thread, so wait for the
+/// thumbnail before inspecting or cleaning up the files of a post.
+async fn wait_for_thumbnail(post_id: u32, file_name: &str) -> bool {
+    let thumbnail = format!("thumbnails/{}/{}", post_id, file_name);
+
+    for _ in 0..50 {
+        if Path::new(&thumbnail).exists() {
+            return true;
+        }
+
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    false
+}
+
+async fn op_id(db: &TestDb, thread_id: u32) -> u32 {
+    Thread::by_id(thread_id, &db.pool).await.unwrap().posts[0].post.id
+}
+
+fn remove_post_files(post_id: u32) {
+    let _ = fs::remove_dir_all(format!("files/{}", post_id));
+    let _ = fs::remove_dir_all(format!("thumbnails/{}", post_id));
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn thread_and_reply_with_attachments() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+
+    let (content_type, body) = Multipart::new()
+    .text("topic", "Testilanka")
+    .text("message", "Ensimmäinen viesti")
+    .file("attachment", "kuva.png", "image/png", &png_image())
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri("/b")
+    .peer_addr(peer("127.0.0.1"))
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let cookie = session_cookie(&res).expect("posting should create an anonymous session");
+
+    let threads = Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap();
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0].title, "Testilanka");
+
+    let thread_id = threads[0].id;
+    let op = &threads[0].op_post;
+    let attachment = op.attachment.as_ref().expect("op should have an attachment");
+
+    assert_eq!(attachment.file_type, "image");
+    assert_eq!((attachment.width, attachment.height), (8, 8));
+    assert!(wait_for_thumbnail(op.id, "kuva.png").await);
+
+    let (content_type, body) = Multipart::new()
+    .text("message", &format!(">>{}\nVastaus", op.id))
+    .file("attachment", "vastaus.png", "image/png", &png_image())
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/b/thread/{}", thread_id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let thread = Thread::by_id(thread_id, &db.pool).await.unwrap();
+    assert_eq!(thread.posts.len(), 2);
+
+    let reply = &thread.posts[1];
+    assert!(reply.attachment.is_some());
+    assert!(wait_for_thumbnail(reply.post.id, "vastaus.png").await);
+    assert_eq!(thread.posts[0].replies, vec![reply.post.id]);
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/thumbnails/{}", reply.post.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Replies without a file are accepted, unsupported files are not.
+    let (content_type, body) = Multipart::new()
+    .text("message", "Ilman liitettä")
+    .no_file("attachment")
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/b/thread/{}", thread_id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let (content_type, body) = Multipart::new()
+    .text("message", "Tekstitiedosto")
+    .file("attachment", "teksti.txt", "text/plain", b"ei kuva")
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/b/thread/{}", thread_id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let thread = Thread::by_id(thread_id, &db.pool).await.unwrap();
+    assert_eq!(thread.posts.len(), 3);
+
+    for post in thread.posts {
+        remove_post_files(post.post.id);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn empty_and_oversized_messages_are_rejected() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+
+    for message in [String::new(), "a".repeat(20_001)] {
+        let (content_type, body) = Multipart::new()
+        .text("topic", "Testilanka")
+        .text("message", &message)
+        .no_file("attachment")
+        .finish();
+
+        let req = test::TestRequest::post()
+        .uri("/b")
+        .peer_addr(peer("127.0.0.1"))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_request();
+
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn captcha_is_verified_on_captcha_boards() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "c", AccessLevel::Anonymous as u8, 10, true).await;
+
+    let thread_form = |captcha: Option<(u64, &str)>| {
+        let mut form = Multipart::new()
+        .text("topic", "Captchalanka")
+        .text("message", "Viesti");
+
+        if let Some((id, answer)) = captcha {
+            form = form
+            .text("captcha", answer)
+            .text("captcha_id", &id.to_string());
+        }
+
+        form.no_file("attachment").finish()
+    };
+
+    let post_thread = |(content_type, body): (String, Vec<u8>)| {
+        test::TestRequest::post()
+        .uri("/c")
+        .peer_addr(peer("127.0.0.1"))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_request()
+    };
+
+    // Missing captcha.
+    let res = test::call_service(&app, post_thread(thread_form(None))).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let new_captcha = || {
+        test::TestRequest::get()
+        .uri("/captcha")
+        .peer_addr(peer("127.0.0.1"))
+        .to_request()
+    };
+
+    // Wrong answer, the captcha is consumed either way.
+    let captcha: Value = test::call_and_read_body_json(&app, new_captcha()).await;
+    let captcha_id = captcha["id"].as_u64().unwrap();
+    assert!(!captcha["captcha"].as_str().unwrap().is_empty());
+
+    let res = test::call_service(&app, post_thread(thread_form(Some((captcha_id, "väärä"))))).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert!(Captcha::by_id(captcha_id, &db.pool).await.is_err());
+
+    let res = test::call_service(&app, post_thread(thread_form(Some((captcha_id, "väärä"))))).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // Correct answer.
+    let captcha: Value = test::call_and_read_body_json(&app, new_captcha()).await;
+    let captcha_id = captcha["id"].as_u64().unwrap();
+    let answer = Captcha::by_id(captcha_id, &db.pool).await.unwrap().answer;
+
+    let res = test::call_service(&app, post_thread(thread_form(Some((captcha_id, &answer))))).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let threads = Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap();
+    assert_eq!(threads.len(), 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn quotes_of_missing_or_hidden_posts_become_dead_links() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let user = create_user(&db, "lainaaja", "salasana123", AccessLevel::Member).await;
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let members = create_board(&db, "jas", AccessLevel::Member as u8, 10, false).await;
+
+    let quoted = create_thread(&db, &board, user.id, "lainattu").await;
+    let thread = create_thread(&db, &board, user.id, "lanka").await;
+    let hidden = create_thread(&db, &members, user.id, "piilotettu").await;
+
+    let quoted_op = op_id(&db, quoted.id).await;
+    let hidden_op = op_id(&db, hidden.id).await;
+
+    let message = format!(">>{0}\n>>>/b/{0}\n>>>/jas/{1}\n>>999999\n>>>/b/\n>>>/jas/", quoted_op, hidden_op);
+
+    let (content_type, body) = Multipart::new()
+    .text("message", &message)
+    .no_file("attachment")
+    .finish();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.1"))
+    .insert_header((header::CONTENT_TYPE, content_type))
+    .set_payload(body)
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let cookie = session_cookie(&res).unwrap();
+
+    // The cross-thread reply is listed under the quoted post, the hidden one
+    // and the missing one are not recorded at all.
+    let reply_id = Thread::by_id(thread.id, &db.pool).await.unwrap().posts[1].post.id;
+    let quoted_thread = Thread::by_id(quoted.id, &db.pool).await.unwrap();
+    assert_eq!(quoted_thread.posts[0].replies, vec![reply_id]);
+
+    let hidden_thread = Thread::by_id(hidden.id, &db.pool).await.unwrap();
+    assert!(hidden_thread.posts[0].replies.is_empty());
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    let html = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(html.contains(&format!("onClick=\"showPost({0})\" onpointerenter=\"hintPost(this, {0})\" onpointerleave=\"unhintPost(this)\">&gt;&gt;{0}</span>", quoted_op)));
+    assert!(html.contains(&format!("unhintPost(this)\">&gt;&gt;&gt;/b/{}</span>", quoted_op)));
+    assert!(html.contains(&format!("<span class=\"deadlink\">&gt;&gt;&gt;/jas/{}</span>", hidden_op)));
+    assert!(html.contains("<span class=\"deadlink\">&gt;&gt;999999</span>"));
+    assert!(html.contains("<a class=\"backlink\" href=\"/b\">&gt;&gt;&gt;/b/</a>"));
+    assert!(html.contains("<span class=\"deadlink\">&gt;&gt;&gt;/jas/</span>"));
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn sage_and_signed_posts() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let moderator = create_user(&db, "valvoja", "salasana", AccessLevel::Moderator).await;
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let thread = create_thread(&db, &board, moderator.id, "lanka").await;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "valvoja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    let reply = |options: &str, cookie: Option<actix_web::cookie::Cookie<'static>>| {
+        let (content_type, body) = Multipart::new()
+        .text("message", "Vastaus")
+        .text("options", options)
+        .no_file("attachment")
+        .finish();
+
+        let mut req = test::TestRequest::post()
+        .uri(&format!("/b/thread/{}", thread.id))
+        .peer_addr(peer("127.0.0.1"))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body);
+
+        if let Some(cookie) = cookie {
+            req = req.cookie(cookie);
+        }
+
+        req.to_request()
+    };
+
+    // A saged reply doesn't bump the thread.
+    let res = test::call_service(&app, reply("Sage, nimi", Some(cookie.clone()))).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let after_sage = Thread::by_id(thread.id, &db.pool).await.unwrap();
+    assert_eq!(after_sage.thread.bump_time, thread.bump_time);
+
+    let signed = &after_sage.posts[1].post;
+    assert!(signed.sage);
+    assert!(signed.show_username);
+    assert_eq!(signed.capcode.as_deref(), Some("moderaattori"));
+
+    // Anonymous users can't sign their posts.
+    let res = test::call_service(&app, reply("nimi", None)).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let after_reply = Thread::by_id(thread.id, &db.pool).await.unwrap();
+    assert!(!after_reply.posts[2].post.show_username);
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie)
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    let html = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(html.contains("<span class=\"username signed\"> valvoja </span><span class=\"capcode\">## moderaattori</span>"));
+
+    // The capcode stays as it was posted when the author is demoted.
+    User::update_access_level(moderator.id, AccessLevel::Member as u8, &db.pool).await.unwrap();
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.2"))
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    let html = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(html.contains("<span class=\"username signed\"> valvoja </span><span class=\"capcode\">## moderaattori</span>"));
+}
\ No newline at end of file
@@ -0,0 +1,102 @@
+#[macro_use]
+mod common;
+
+use std::{thread::sleep, time::Duration};
+
+use actix_web::http::{header, StatusCode};
+use common::{create_user, peer, session_cookie, test_config, TestDb};
+use kapchan::{config::{Config, RateLimitConfig, TokenBucket}, middleware::rate_limit::{RateLimiter, RouteGroup}, models::users::AccessLevel};
+
+
+fn limits(captcha: TokenBucket) -> RateLimitConfig {
+    let mut config = test_config().rate_limit;
+    config.captcha = captcha;
+
+    config
+}
+
+#[test]
+fn buckets_limit_addresses_and_users_separately() {
+    let limiter = RateLimiter::new(&limits(TokenBucket { burst: 2, per_minute: 1 }));
+
+    let a = Some("192.0.2.1".parse().unwrap());
+    let b = Some("192.0.2.2".parse().unwrap());
+
+    assert_eq!(limiter.check(RouteGroup::Captcha, a, Some(1)), Ok(()));
+    assert_eq!(limiter.check(RouteGroup::Captcha, a, None), Ok(()));
+    assert_eq!(limiter.check(RouteGroup::Captcha, a, None), Err(60));
+
+    // The user's bucket still has a request left, the other address two.
+    assert_eq!(limiter.check(RouteGroup::Captcha, b, Some(1)), Ok(()));
+    assert_eq!(limiter.check(RouteGroup::Captcha, b, Some(1)), Err(60));
+    assert_eq!(limiter.check(RouteGroup::Captcha, b, Some(2)), Ok(()));
+
+    // Other groups have their own buckets and are off in the test config.
+    assert_eq!(limiter.check(RouteGroup::Post, a, Some(1)), Ok(()));
+}
+
+#[test]
+fn ipv6_subscribers_share_a_bucket() {
+    let limiter = RateLimiter::new(&limits(TokenBucket { burst: 1, per_minute: 1 }));
+
+    assert_eq!(limiter.check(RouteGroup::Captcha, Some("2001:db8::1".parse().unwrap()), None), Ok(()));
+    assert!(limiter.check(RouteGroup::Captcha, Some("2001:db8::ffff:2".parse().unwrap()), None).is_err());
+    assert_eq!(limiter.check(RouteGroup::Captcha, Some("2001:db8:0:1::1".parse().unwrap()), None), Ok(()));
+}
+
+#[test]
+fn refilled_buckets_are_evicted() {
+    let limiter = RateLimiter::new(&limits(TokenBucket { burst: 1, per_minute: 60_000 }));
+
+    assert_eq!(limiter.check(RouteGroup::Captcha, Some("192.0.2.1".parse().unwrap()), Some(1)), Ok(()));
+    assert_eq!(limiter.bucket_count(), 2);
+
+    sleep(Duration::from_millis(20));
+    limiter.evict();
+
+    assert_eq!(limiter.bucket_count(), 0);
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn limited_requests_get_retry_after() {
+    let db = TestDb::new().await;
+
+    let mut config: Config = test_config();
+    config.rate_limit.captcha = TokenBucket { burst: 2, per_minute: 2 };
+
+    let app = test_app!(db, kapchan::services::geoip::GeoIp::default(), config);
+
+    create_user(&db, "valvoja", "salasana", AccessLevel::Moderator).await;
+
+    for _ in 0..2 {
+        let req = actix_web::test::TestRequest::get().uri("/captcha").peer_addr(peer("127.0.0.1")).to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let req = actix_web::test::TestRequest::get().uri("/captcha").peer_addr(peer("127.0.0.1")).to_request();
+    let res = actix_web::test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "30");
+
+    let req = actix_web::test::TestRequest::get().uri("/captcha").peer_addr(peer("127.0.0.2")).to_request();
+    let res = actix_web::test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Moderators are exempt.
+    let req = actix_web::test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.3"))
+    .set_form([("username", "valvoja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = actix_web::test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    for _ in 0..3 {
+        let req = actix_web::test::TestRequest::get().uri("/captcha").peer_addr(peer("127.0.0.3")).cookie(cookie.clone()).to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
\ No newline at end of file
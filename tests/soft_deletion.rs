@@ -0,0 +1,114 @@
+#[macro_use]
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use chrono::{Duration, Utc};
+use common::{create_board, create_thread, create_user, peer, session_cookie, TestDb};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use kapchan::{models::{mod_actions::{ModAction, ModActionFilter}, threads::Thread, users::AccessLevel}, schema::threads, services::retention::purge_deleted};
+
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn deleted_threads_are_hidden_restorable_and_purged() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let board = create_board(&db, "b", AccessLevel::Anonymous as u8, 10, false).await;
+    let moderator = create_user(&db, "valvoja", "salasana", AccessLevel::Moderator).await;
+    let member = create_user(&db, "jasen", "salasana", AccessLevel::Member).await;
+    let thread = create_thread(&db, &board, member.id, "poistettava lanka").await;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "valvoja"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    let cookie = session_cookie(&res).unwrap();
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/delete-thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_success());
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/delete-thread/{}", thread.id + 1000))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // The thread is kept but hidden from the catalog.
+    let deleted = Thread::thread_by_id(thread.id, &db.pool).await.unwrap();
+    assert!(deleted.deleted_at.is_some());
+    assert_eq!(deleted.deleted_by, Some(moderator.id));
+    assert!(Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap().is_empty());
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.2"))
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(!String::from_utf8_lossy(&body).contains("poistettava lanka"));
+
+    let req = test::TestRequest::get()
+    .uri(&format!("/b/thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .to_request();
+
+    let body = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(body.contains("poistettava lanka"));
+    assert!(body.contains("Lanka poistettu"));
+
+    let req = test::TestRequest::post()
+    .uri(&format!("/restore-thread/{}", thread.id))
+    .peer_addr(peer("127.0.0.1"))
+    .cookie(cookie.clone())
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_success());
+
+    assert!(Thread::thread_by_id(thread.id, &db.pool).await.unwrap().deleted_at.is_none());
+    assert_eq!(Thread::list_threads_by_board_catalog(&db.pool, board.id).await.unwrap().len(), 1);
+
+    let filter = ModActionFilter {
+        action: Some("restore_thread".to_owned()),
+        ..Default::default()
+    };
+
+    let actions = ModAction::load_actions(&db.pool, &filter, 20, 0).await.unwrap();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].item_id, Some(thread.id.into()));
+
+    // Deleted content is purged once it's older than the retention period.
+    Thread::soft_delete_thread(&db.pool, thread.id, moderator.id).await.unwrap();
+
+    purge_deleted(&db.pool, 30).await.unwrap();
+    assert!(Thread::thread_by_id(thread.id, &db.pool).await.is_ok());
+
+    let mut conn = db.pool.get().await.unwrap();
+
+    diesel::update(threads::table.find(thread.id))
+    .set(threads::deleted_at.eq((Utc::now() - Duration::days(31)).naive_utc()))
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    purge_deleted(&db.pool, 30).await.unwrap();
+    assert!(matches!(
+        Thread::thread_by_id(thread.id, &db.pool).await,
+        Err(diesel::result::Error::NotFound)
+    ));
+}
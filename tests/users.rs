@@ -0,0 +1,188 @@
+#[macro_use]
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use common::{create_user, location, peer, session_cookie, test_config, TestDb};
+use kapchan::{models::users::{AccessLevel, User}, services::captchas::new_captcha};
+
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn registration_upgrades_anonymous_user() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    let req = test::TestRequest::post()
+    .uri("/register")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "kapakka"), ("email", "kapakka@kapsi.test"), ("pwd", "salasana")])
+    .to_request();
+
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(location(&res).as_deref(), Some("/apply"));
+
+    let user = User::by_username("kapakka", &db.pool).await.unwrap();
+    assert_eq!(user.access_level, AccessLevel::Registered as u8);
+    assert_eq!(user.email.as_deref(), Some("kapakka@kapsi.test"));
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn registration_rejects_invalid_and_duplicate_input() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let req = test::TestRequest::post()
+    .uri("/register")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "ei sallittu"), ("email", "ei@kapsi.test"), ("pwd", "salasana")])
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Käyttäjänimi sisältää kiellettyjä merkkejä!"));
+
+    let req = test::TestRequest::post()
+    .uri("/register")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "kapakka"), ("email", "toinen@kapsi.test"), ("pwd", "salasana")])
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Käyttäjänimi tai sähköposti on jo olemassa!"));
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn login_by_username_and_email() {
+    let db = TestDb::new().await;
+    let app = test_app!(db);
+
+    create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "kapakka"), ("pwd", "väärä")])
+    .to_request();
+
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Virheellinen käyttäjänimi tai salasana!"));
+
+    let req = test::TestRequest::post()
+    .uri("/login")
+    .peer_addr(peer("127.0.0.1"))
+    .set_form([("username", "tuntematon"), ("pwd", "salasana")])
+    .to_request();
+
+    // Unknown accounts get the same answer.
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Virheellinen käyttäjänimi tai salasana!"));
+
+    for username in ["kapakka", "kapakka@kapsi.test"] {
+        let req = test::TestRequest::post()
+        .uri("/login")
+        .peer_addr(peer("127.0.0.1"))
+        .set_form([("username", username), ("pwd", "salasana")])
+        .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::FOUND);
+        assert_eq!(location(&res).as_deref(), Some("/"));
+
+        let cookie = session_cookie(&res).expect("login should set a session cookie");
+
+        // A logged in user is no longer anonymous, so registering again is refused.
+        let req = test::TestRequest::post()
+        .uri("/register")
+        .peer_addr(peer("127.0.0.1"))
+        .cookie(cookie)
+        .set_form([("username", "uusi"), ("email", "uusi@kapsi.test"), ("pwd", "salasana")])
+        .to_request();
+
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs a MySQL server in TEST_DATABASE_URL"]
+async fn repeated_failed_logins_require_captcha_and_lock_out() {
+    let db = TestDb::new().await;
+
+    let mut config = test_config();
+    config.login.captcha_after = 2;
+    config.login.lockout_after = 3;
+    config.login.ip_lockout_after = 0;
+    config.login.lockout_seconds = 600;
+
+    let app = test_app!(db, kapchan::services::geoip::GeoIp::default(), config);
+
+    create_user(&db, "kapakka", "salasana", AccessLevel::Registered).await;
+    create_user(&db, "toinen", "salasana", AccessLevel::Registered).await;
+
+    let login = |username: &str, pwd: &str, ip: &str, captcha: Option<(u64, &str)>| {
+        let mut form = vec![("username", username.to_owned()), ("pwd", pwd.to_owned())];
+
+        if let Some((id, answer)) = captcha {
+            form.push(("captcha_id", id.to_string()));
+            form.push(("captcha", answer.to_owned()));
+        }
+
+        test::TestRequest::post()
+        .uri("/login")
+        .peer_addr(peer(ip))
+        .set_form(form)
+        .to_request()
+    };
+
+    for _ in 0..2 {
+        let body = test::call_and_read_body(&app, login("kapakka", "väärä", "127.0.0.1", None)).await;
+        assert!(String::from_utf8_lossy(&body).contains("Virheellinen käyttäjänimi tai salasana!"));
+    }
+
+    let body = test::call_and_read_body(&app, login("kapakka", "salasana", "127.0.0.1", None)).await;
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("Ratkaise captcha kirjautuaksesi!"));
+    assert!(body.contains("name=\"captcha\""));
+
+    let captcha = new_captcha(&db.pool, "abcde".to_owned(), 5).await.unwrap();
+    let body = test::call_and_read_body(&app, login("kapakka", "salasana", "127.0.0.1", Some((captcha.id, "väärä")))).await;
+    assert!(String::from_utf8_lossy(&body).contains("Captcha epäonnistui!"));
+
+    // The third failure locks the account, even with the right password.
+    let captcha = new_captcha(&db.pool, "abcde".to_owned(), 5).await.unwrap();
+    let body = test::call_and_read_body(&app, login("kapakka", "väärä", "127.0.0.1", Some((captcha.id, "abcde")))).await;
+    assert!(String::from_utf8_lossy(&body).contains("Virheellinen käyttäjänimi tai salasana!"));
+
+    let res = test::call_service(&app, login("kapakka", "salasana", "127.0.0.2", None)).await;
+    assert_ne!(res.status(), StatusCode::FOUND);
+
+    let body = test::read_body(res).await;
+    assert!(String::from_utf8_lossy(&body).contains("Liian monta epäonnistunutta kirjautumisyritystä! Odota vielä 10 minuuttia."));
+
+    // Unknown accounts are tracked the same way, whatever the address.
+    for ip in ["127.0.0.3", "127.0.0.4"] {
+        let body = test::call_and_read_body(&app, login("tuntematon", "väärä", ip, None)).await;
+        assert!(String::from_utf8_lossy(&body).contains("Virheellinen käyttäjänimi tai salasana!"));
+    }
+
+    let body = test::call_and_read_body(&app, login("Tuntematon", "väärä", "127.0.0.5", None)).await;
+    assert!(String::from_utf8_lossy(&body).contains("Ratkaise captcha kirjautuaksesi!"));
+
+    // The failing address needs a captcha for other accounts too.
+    let req = test::TestRequest::get().uri("/login").peer_addr(peer("127.0.0.1")).to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("name=\"captcha\""));
+
+    let body = test::call_and_read_body(&app, login("toinen", "salasana", "127.0.0.1", None)).await;
+    assert!(String::from_utf8_lossy(&body).contains("Ratkaise captcha kirjautuaksesi!"));
+
+    let captcha = new_captcha(&db.pool, "abcde".to_owned(), 5).await.unwrap();
+    let res = test::call_service(&app, login("toinen", "salasana", "127.0.0.1", Some((captcha.id, "abcde")))).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+}
\ No newline at end of file